@@ -1,7 +1,8 @@
-import { useState, useRef } from "react";
+import { useState, useRef, useEffect } from "react";
 import { VoiceCircle, StatusText } from "./components/VoiceCircle";
 import { ChatmateList } from "./components/ChatmateList";
 import { LanguageSelection } from "./components/LanguageSelection";
+import { ChatEvents } from "./components/ChatEvents";
 import { useChatmate, useWebSocket, useAudioRecording } from "./hooks";
 import {
   SignedIn,
@@ -28,10 +29,8 @@ function App() {
     "idle" | "recording" | "waiting" | "responding"
   >("idle");
   const chatmateId = useChatmate(selectedChatmateId);
-  const { isConnected, wsRef } = useWebSocket(
-    view === "chat" ? chatmateId : null,
-    setState
-  );
+  const { isConnected, wsRef, corrections, scenarioProgress, clearCorrections } =
+    useWebSocket(view === "chat" ? chatmateId : null, setState);
   const { startRecording, stopRecording } = useAudioRecording(
     state,
     isConnected,
@@ -40,6 +39,12 @@ function App() {
   );
   const { sessionClaims, getToken } = useAuth();
 
+  useEffect(() => {
+    if (state === "recording") {
+      clearCorrections();
+    }
+  }, [state, clearCorrections]);
+
   // Threshold to distinguish quick tap from long press (1000ms)
   const TAP_THRESHOLD_MS = 1000;
   const pressStartTimeRef = useRef<number | null>(null);
@@ -246,6 +251,11 @@ function App() {
             )}
 
             <StatusText state={state} isConnected={isConnected} />
+
+            <ChatEvents
+              corrections={corrections}
+              scenarioProgress={scenarioProgress}
+            />
           </div>
         )}
       </SignedIn>
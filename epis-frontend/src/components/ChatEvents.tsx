@@ -0,0 +1,72 @@
+import type { Correction, ScenarioProgress } from "../hooks/useWebSocket";
+
+// Readable names of correction categories
+const CATEGORY_NAMES: Record<Correction["category"], string> = {
+  grammar: "Grammar",
+  vocabulary: "Vocabulary",
+  word_order: "Word order",
+  usage: "Usage",
+};
+
+// Chat Events Component, showing the corrections of the last user message and the progress of
+// the role-play scenario
+export function ChatEvents({
+  corrections,
+  scenarioProgress,
+}: {
+  corrections: Correction[];
+  scenarioProgress: ScenarioProgress | null;
+}) {
+  if (corrections.length === 0 && !scenarioProgress) {
+    return null;
+  }
+
+  return (
+    <div className="mt-8 w-full max-w-md flex flex-col gap-4 px-4">
+      {scenarioProgress && (
+        <div className="rounded border-2 border-solid border-slate-300 bg-white p-3 text-sm">
+          <p className="font-semibold text-gray-800">
+            {scenarioProgress.completed
+              ? "Scenario completed 🎉"
+              : "Scenario goals"}
+          </p>
+          <ul className="mt-2 flex flex-col gap-1">
+            {scenarioProgress.completed_goals.map((goal) => (
+              <li key={goal} className="text-green-700">
+                ✓ {goal}
+              </li>
+            ))}
+            {scenarioProgress.remaining_goals.map((goal) => (
+              <li key={goal} className="text-gray-600">
+                ○ {goal}
+              </li>
+            ))}
+          </ul>
+        </div>
+      )}
+
+      {corrections.length > 0 && (
+        <div className="rounded border-2 border-solid border-slate-300 bg-white p-3 text-sm">
+          <p className="font-semibold text-gray-800">Corrections</p>
+          <ul className="mt-2 flex flex-col gap-2">
+            {corrections.map((correction, index) => (
+              <li key={index}>
+                <p>
+                  <span className="text-red-600 line-through">
+                    {correction.original}
+                  </span>{" "}
+                  →{" "}
+                  <span className="text-green-700">{correction.corrected}</span>
+                </p>
+                <p className="text-xs text-gray-500">
+                  {CATEGORY_NAMES[correction.category]}:{" "}
+                  {correction.explanation}
+                </p>
+              </li>
+            ))}
+          </ul>
+        </div>
+      )}
+    </div>
+  );
+}
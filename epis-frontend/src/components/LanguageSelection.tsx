@@ -38,7 +38,11 @@ export function LanguageSelection({
         return;
       }
 
-      const result = await handshakeChatmate(token, { language });
+      const result = await handshakeChatmate(token, {
+        language,
+        // The browser language is taken as the native language of the learner
+        native_language: navigator.language.split("-")[0],
+      });
       if (!result.ok) {
         setError(result.error.message);
         return;
@@ -1,9 +1,30 @@
-import { useState, useEffect, useRef } from "react";
+import { useState, useEffect, useRef, useCallback } from "react";
 import { useAuth } from "@clerk/clerk-react";
 import { config } from "../config";
 import type { VoiceChatState } from "./useConversation";
 
-// Audio playback utility
+// Correction of the last user message
+export type Correction = {
+  original: string;
+  corrected: string;
+  category: "grammar" | "vocabulary" | "word_order" | "usage";
+  explanation: string;
+};
+
+// Progress of the learner in the role-play scenario of the chat session
+export type ScenarioProgress = {
+  scenario_id: string;
+  completed_goals: string[];
+  remaining_goals: string[];
+  completed: boolean;
+};
+
+// Chat event sent as a json text message next to the binary reply audio chunks
+type ChatEvent =
+  | { type: "corrections"; corrections: Correction[] }
+  | ({ type: "scenario_progress" } & ScenarioProgress);
+
+// Audio playback utility, resolving when playback ends
 async function playAudio(
   arrayBuffer: ArrayBuffer,
   onStateChange: (state: VoiceChatState) => void
@@ -17,21 +38,26 @@ async function playAudio(
 
     audio.preload = "auto";
 
-    audio.onended = () => {
-      URL.revokeObjectURL(audioUrl);
-      onStateChange("idle");
-    };
+    const ended = new Promise<void>((resolve) => {
+      audio.onended = () => {
+        URL.revokeObjectURL(audioUrl);
+        onStateChange("idle");
+        resolve();
+      };
 
-    audio.onerror = (error) => {
-      console.error("Audio playback error:", error);
-      console.error("Audio format: 32-bit float WAV, 24kHz, mono");
-      URL.revokeObjectURL(audioUrl);
-      onStateChange("idle");
-    };
+      audio.onerror = (error) => {
+        console.error("Audio playback error:", error);
+        console.error("Audio format: 32-bit float WAV, 24kHz, mono");
+        URL.revokeObjectURL(audioUrl);
+        onStateChange("idle");
+        resolve();
+      };
+    });
 
     onStateChange("responding");
     await audio.play();
     console.log("Playing AI response audio (32-bit float WAV, 24kHz)");
+    await ended;
   } catch (error) {
     console.error("Failed to play audio:", error);
     console.error("Expected format: 32-bit float WAV, 24kHz sample rate, mono");
@@ -45,7 +71,12 @@ export function useWebSocket(
   onStateChange: (state: VoiceChatState) => void
 ) {
   const [isConnected, setIsConnected] = useState(false);
+  const [corrections, setCorrections] = useState<Correction[]>([]);
+  const [scenarioProgress, setScenarioProgress] =
+    useState<ScenarioProgress | null>(null);
   const wsRef = useRef<WebSocket | null>(null);
+  // Replies are streamed as multiple audio chunks, so they are played one after another
+  const playbackQueueRef = useRef<Promise<void>>(Promise.resolve());
 
   const { getToken } = useAuth();
 
@@ -67,11 +98,34 @@ export function useWebSocket(
       };
 
       ws.onmessage = (event) => {
-        try {
-          playAudio(event.data, onStateChange);
-        } catch (error) {
-          console.error("Failed to parse websocket message:", error);
+        // Chat events are text messages, while reply audio chunks are binary ones
+        if (typeof event.data === "string") {
+          try {
+            const chatEvent = JSON.parse(event.data) as ChatEvent;
+            switch (chatEvent.type) {
+              case "corrections":
+                setCorrections(chatEvent.corrections);
+                break;
+              case "scenario_progress":
+                setScenarioProgress({
+                  scenario_id: chatEvent.scenario_id,
+                  completed_goals: chatEvent.completed_goals,
+                  remaining_goals: chatEvent.remaining_goals,
+                  completed: chatEvent.completed,
+                });
+                break;
+              default:
+                console.warn("Unknown chat event:", chatEvent);
+            }
+          } catch (error) {
+            console.error("Failed to parse websocket message:", error);
+          }
+          return;
         }
+
+        playbackQueueRef.current = playbackQueueRef.current.then(() =>
+          playAudio(event.data, onStateChange)
+        );
       };
 
       ws.onclose = () => {
@@ -98,5 +152,8 @@ export function useWebSocket(
     };
   }, [chatmateId, onStateChange, getToken]);
 
-  return { isConnected, wsRef };
+  // Corrections are of the last user message, so they are cleared once a new one is recorded
+  const clearCorrections = useCallback(() => setCorrections([]), []);
+
+  return { isConnected, wsRef, corrections, scenarioProgress, clearCorrections };
 }
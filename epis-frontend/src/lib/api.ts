@@ -11,6 +11,10 @@ export interface ListChatmatesResponse {
 
 export interface HandshakeChatmateRequest {
   language: string;
+  voice?: string;
+  speaking_style?: string;
+  // ISO 639-1 code of the native language of the learner, e.g. "fa"
+  native_language?: string;
 }
 
 export interface HandshakeChatmateResponse {
@@ -0,0 +1 @@
+DROP TABLE tts_cache;
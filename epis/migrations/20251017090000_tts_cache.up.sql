@@ -0,0 +1,8 @@
+CREATE TABLE tts_cache (
+    key TEXT PRIMARY KEY,
+    audio BYTEA NOT NULL,
+    size_bytes INT NOT NULL,
+    last_accessed_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now(),
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now()
+);
+CREATE INDEX tts_cache_last_accessed_at_idx ON tts_cache (last_accessed_at);
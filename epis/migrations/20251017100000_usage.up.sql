@@ -0,0 +1,8 @@
+CREATE TABLE usage (
+    message_id UUID PRIMARY KEY REFERENCES message(id) ON DELETE CASCADE,
+    input_tokens INT NOT NULL DEFAULT 0,
+    output_tokens INT NOT NULL DEFAULT 0,
+    audio_seconds REAL NOT NULL DEFAULT 0,
+    characters INT NOT NULL DEFAULT 0,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now()
+);
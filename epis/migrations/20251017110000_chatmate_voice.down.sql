@@ -0,0 +1,2 @@
+ALTER TABLE chatmate DROP COLUMN speaking_style;
+ALTER TABLE chatmate DROP COLUMN voice;
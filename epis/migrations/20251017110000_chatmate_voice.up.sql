@@ -0,0 +1,2 @@
+ALTER TABLE chatmate ADD COLUMN voice TEXT NOT NULL DEFAULT 'alloy';
+ALTER TABLE chatmate ADD COLUMN speaking_style TEXT;
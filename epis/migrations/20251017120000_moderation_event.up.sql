@@ -0,0 +1,10 @@
+CREATE TABLE moderation_event (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    chatmate_id UUID NOT NULL REFERENCES chatmate(id) ON DELETE CASCADE,
+    stage TEXT NOT NULL CHECK (stage IN ('input', 'output')),
+    action TEXT NOT NULL CHECK (action IN ('canned_reply', 'replaced')),
+    content TEXT NOT NULL,
+    categories TEXT[] NOT NULL DEFAULT '{}',
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now()
+);
+CREATE INDEX moderation_event_chatmate_id_idx ON moderation_event (chatmate_id);
@@ -0,0 +1,7 @@
+DROP INDEX learned_vocab_chatmate_id_due_at_idx;
+ALTER TABLE learned_vocab
+    DROP COLUMN ease,
+    DROP COLUMN stability,
+    DROP COLUMN difficulty,
+    DROP COLUMN interval_days,
+    DROP COLUMN due_at;
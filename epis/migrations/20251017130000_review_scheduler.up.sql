@@ -0,0 +1,12 @@
+ALTER TABLE learned_vocab
+    ADD COLUMN ease REAL NOT NULL DEFAULT 2.5,
+    ADD COLUMN stability REAL NOT NULL DEFAULT 1,
+    ADD COLUMN difficulty REAL NOT NULL DEFAULT 5,
+    ADD COLUMN interval_days REAL NOT NULL DEFAULT 1,
+    ADD COLUMN due_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now();
+-- Keep the previous exponential schedule for already learned vocab
+UPDATE learned_vocab
+SET interval_days = 2 ^ GREATEST(streak - 1, 0),
+    stability = 2 ^ GREATEST(streak - 1, 0),
+    due_at = last_used + (2 ^ GREATEST(streak - 1, 0)) * INTERVAL '1 day';
+CREATE INDEX learned_vocab_chatmate_id_due_at_idx ON learned_vocab (chatmate_id, due_at);
@@ -0,0 +1 @@
+ALTER TABLE learned_vocab DROP COLUMN produced_count;
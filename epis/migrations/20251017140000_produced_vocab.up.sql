@@ -0,0 +1 @@
+ALTER TABLE learned_vocab ADD COLUMN produced_count INT NOT NULL DEFAULT 0;
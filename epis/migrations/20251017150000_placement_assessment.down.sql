@@ -0,0 +1 @@
+DROP TABLE placement_assessment;
@@ -0,0 +1,8 @@
+CREATE TABLE placement_assessment (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    chatmate_id UUID NOT NULL REFERENCES chatmate(id) ON DELETE CASCADE,
+    cefr_level TEXT NOT NULL CHECK (cefr_level IN ('A1', 'A2', 'B1', 'B2', 'C1', 'C2')),
+    rationale TEXT NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now()
+);
+CREATE INDEX placement_assessment_chatmate_id_idx ON placement_assessment (chatmate_id);
@@ -0,0 +1,11 @@
+CREATE TABLE level_change (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    chatmate_id UUID NOT NULL REFERENCES chatmate(id) ON DELETE CASCADE,
+    from_level TEXT NOT NULL CHECK (from_level IN ('A1', 'A2', 'B1', 'B2', 'C1', 'C2')),
+    to_level TEXT NOT NULL CHECK (to_level IN ('A1', 'A2', 'B1', 'B2', 'C1', 'C2')),
+    rationale TEXT NOT NULL,
+    status TEXT NOT NULL CHECK (status IN ('pending', 'applied', 'rejected')),
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now(),
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now()
+);
+CREATE INDEX level_change_chatmate_id_idx ON level_change (chatmate_id);
@@ -0,0 +1,10 @@
+CREATE TABLE correction (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    message_id UUID NOT NULL REFERENCES message(id) ON DELETE CASCADE,
+    original TEXT NOT NULL,
+    corrected TEXT NOT NULL,
+    category TEXT NOT NULL CHECK (category IN ('grammar', 'vocabulary', 'word_order', 'usage')),
+    explanation TEXT NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now()
+);
+CREATE INDEX correction_message_id_idx ON correction (message_id);
@@ -0,0 +1 @@
+DROP TABLE pronunciation_score;
@@ -0,0 +1,12 @@
+CREATE TABLE pronunciation_score (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    message_id UUID NOT NULL REFERENCES message(id) ON DELETE CASCADE,
+    position INT NOT NULL,
+    word TEXT NOT NULL,
+    intended TEXT,
+    score REAL NOT NULL,
+    start_seconds REAL,
+    end_seconds REAL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now()
+);
+CREATE INDEX pronunciation_score_message_id_idx ON pronunciation_score (message_id);
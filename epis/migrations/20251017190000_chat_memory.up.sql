@@ -0,0 +1,7 @@
+CREATE TABLE chat_memory (
+    chatmate_id UUID PRIMARY KEY REFERENCES chatmate(id) ON DELETE CASCADE,
+    summary TEXT NOT NULL,
+    learner_facts TEXT[] NOT NULL DEFAULT '{}',
+    summarized_until TIMESTAMP WITH TIME ZONE NOT NULL,
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now()
+);
@@ -0,0 +1,2 @@
+DROP TABLE curriculum_unit;
+DROP TABLE learning_goal;
@@ -0,0 +1,17 @@
+CREATE TABLE learning_goal (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    chatmate_id UUID NOT NULL REFERENCES chatmate(id) ON DELETE CASCADE,
+    description TEXT NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now(),
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now()
+);
+CREATE INDEX learning_goal_chatmate_id_idx ON learning_goal (chatmate_id);
+
+CREATE TABLE curriculum_unit (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    goal_id UUID NOT NULL REFERENCES learning_goal(id) ON DELETE CASCADE,
+    position INTEGER NOT NULL,
+    title TEXT NOT NULL,
+    target_vocab TEXT[] NOT NULL DEFAULT '{}',
+    UNIQUE (goal_id, position)
+);
@@ -0,0 +1,5 @@
+ALTER TABLE learned_vocab
+    DROP COLUMN part_of_speech,
+    DROP COLUMN translation,
+    DROP COLUMN definition,
+    DROP COLUMN example;
@@ -0,0 +1,5 @@
+ALTER TABLE learned_vocab
+    ADD COLUMN part_of_speech TEXT CHECK (part_of_speech IN ('noun', 'verb', 'adjective', 'adverb', 'pronoun', 'preposition', 'conjunction', 'interjection', 'idiom', 'other')),
+    ADD COLUMN translation TEXT,
+    ADD COLUMN definition TEXT,
+    ADD COLUMN example TEXT;
@@ -0,0 +1 @@
+ALTER TABLE chatmate DROP COLUMN native_language;
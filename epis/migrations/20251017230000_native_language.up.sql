@@ -0,0 +1 @@
+ALTER TABLE chatmate ADD COLUMN native_language TEXT NOT NULL DEFAULT 'en';
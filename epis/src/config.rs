@@ -16,13 +16,66 @@ use serde::Deserialize;
 /// Represent an AI model, containing its name and provider
 #[derive(Debug, Clone, Deserialize, Getters)]
 pub struct AiModel {
-  // TODO: Support different providers, other than OpenAI
-  // https://github.com/mkermani144/epis/issues/2
-  /// Model proovider
-  #[allow(dead_code)]
+  /// Model provider, i.e. openai, openai-compatible, ollama or fake
   provider: String,
   /// Model name
   model: String,
+  /// Base url of the provider api, falling back to the provider default if not set
+  #[serde(default)]
+  base_url: Option<String>,
+  /// Provider api key, falling back to [Config::openai_api_key] for OpenAI based providers
+  #[serde(default)]
+  api_key: Option<String>,
+  /// Api flavour used for generation by OpenAI based providers, i.e. responses, chat-completions
+  /// or chat-completions-prompted-json
+  #[serde(default)]
+  api_flavour: Option<String>,
+  /// Max number of corrective re-prompts after a generation not matching the structured output
+  /// schema, falling back to a single re-prompt if not set. Only used by llm models.
+  #[serde(default)]
+  max_repair_attempts: Option<u32>,
+  /// Voice used instead of the voice of the chatmate, e.g. for a fallback model of a provider not
+  /// supporting the chatmate voices. Only used by tts models.
+  #[serde(default)]
+  voice: Option<String>,
+  /// Fixture file of scripted responses, only used by the fake provider
+  #[serde(default)]
+  fixture_path: Option<String>,
+  /// Resilience policy of calls to this model
+  #[serde(default)]
+  resilience: ResiliencePolicy,
+  /// Ordered fallback models, each one used if the previous ones fail. Fallbacks of a fallback
+  /// are ignored.
+  #[serde(default)]
+  fallbacks: Vec<AiModel>,
+}
+
+/// Resilience policy of calls to an ai model
+#[derive(Debug, Clone, Deserialize, Getters)]
+#[serde(default)]
+pub struct ResiliencePolicy {
+  /// Timeout of each call, in milliseconds
+  timeout_ms: u64,
+  /// Max number of retries of a call failed with a transient error
+  max_retries: u32,
+  /// Backoff before the first retry, in milliseconds, doubling on each retry
+  initial_backoff_ms: u64,
+  /// Number of consecutive transient failures after which the circuit breaker opens
+  circuit_breaker_threshold: u32,
+  /// Duration the circuit breaker stays open before trying the model again, in milliseconds
+  circuit_breaker_cooldown_ms: u64,
+}
+
+impl Default for ResiliencePolicy {
+  fn default() -> Self {
+    Self {
+      timeout_ms: 30_000,
+      max_retries: 2,
+      initial_backoff_ms: 250,
+      circuit_breaker_threshold: 5,
+      circuit_breaker_cooldown_ms: 30_000,
+    }
+  }
 }
 
 /// Details of all ai models needed
@@ -34,6 +87,70 @@ pub struct AiModels {
   llm: AiModel,
   /// Text to speech
   tts: AiModel,
+  /// Embedding of chat messages, used for recalling related past exchanges, which are retrieved
+  /// lexically by BM25 instead if not set. Its fallbacks should produce embeddings comparable to
+  /// its own.
+  #[serde(default)]
+  embedding: Option<AiModel>,
+}
+
+/// Config of the cache of text to speech audio
+#[derive(Debug, Clone, Deserialize, Getters)]
+pub struct TtsCacheConfig {
+  /// Cache storage backend, i.e. filesystem or postgres
+  backend: String,
+  /// Directory of the cached audio files, only used by the filesystem backend
+  #[serde(default)]
+  path: Option<String>,
+  /// Max total size of the cached audio, least recently used entries being evicted beyond it
+  max_size_bytes: u64,
+}
+
+/// Config of the moderation of chat texts
+#[derive(Debug, Clone, Deserialize, Getters)]
+pub struct ModerationConfig {
+  /// Moderation provider, i.e. keyword (a list of regex patterns) or openai
+  provider: String,
+  /// Case insensitive regex patterns of unsafe content, only used by the keyword provider
+  #[serde(default)]
+  patterns: Vec<String>,
+  /// Moderation model, only used by the openai provider, falling back to the latest OpenAI
+  /// moderation model if not set
+  #[serde(default)]
+  model: Option<String>,
+  /// Moderation api key, falling back to [Config::openai_api_key]
+  #[serde(default)]
+  api_key: Option<String>,
+}
+
+/// Config of the realtime ai agent
+#[derive(Debug, Clone, Deserialize, Getters)]
+#[serde(default)]
+pub struct RealtimeAiAgentConfig {
+  /// Implementation of the agent, i.e. chained (chaining the stt, llm and tts ai models) or
+  /// openai-realtime (a speech to speech OpenAI Realtime api session per chat)
+  implementation: String,
+  /// Speech to speech model, only used by the openai-realtime implementation
+  model: Option<String>,
+  /// Model transcribing user messages, only used by the openai-realtime implementation
+  transcription_model: Option<String>,
+  /// Websocket base url of the Realtime api, e.g. of a local mock server, falling back to OpenAI
+  /// if not set
+  base_url: Option<String>,
+  /// Realtime api key, falling back to [Config::openai_api_key]
+  api_key: Option<String>,
+}
+
+impl Default for RealtimeAiAgentConfig {
+  fn default() -> Self {
+    Self {
+      implementation: "chained".to_string(),
+      model: None,
+      transcription_model: None,
+      base_url: None,
+      api_key: None,
+    }
+  }
 }
 
 /// All of the configs needed
@@ -45,12 +162,34 @@ pub struct Config {
   port: u16,
   /// Ai models config used by service
   ai_models: AiModels,
-  /// Clerk auth provider secret
-  clerk_sk: String,
+  /// Clerk auth provider secret, required unless a dev user is set
+  #[serde(default)]
+  clerk_sk: Option<String>,
+  /// Id of the user every request is authenticated as with any jwt, bypassing Clerk. Only meant
+  /// for development.
+  #[serde(default)]
+  dev_user_id: Option<String>,
   /// App (frontend) URL, used for adding CORS headers
   app_url: String,
-  /// OpenAI api key
-  openai_api_key: String,
+  /// OpenAI api key, only required if an ai model uses OpenAI without its own api key
+  #[serde(default)]
+  openai_api_key: Option<String>,
+  /// Text to speech cache config, disabling the cache if not set
+  #[serde(default)]
+  tts_cache: Option<TtsCacheConfig>,
+  /// Moderation config, disabling moderation if not set. The openai-realtime realtime ai agent only
+  /// moderates user messages.
+  #[serde(default)]
+  moderation: Option<ModerationConfig>,
+  /// Spaced repetition algorithm scheduling vocab reviews, one of sm2 or fsrs, defaulting to fsrs
+  #[serde(default)]
+  review_scheduler: Option<String>,
+  /// How proposed CEFR level changes are applied, one of auto or confirm, defaulting to auto
+  #[serde(default)]
+  cefr_progression: Option<String>,
+  /// Realtime ai agent config, defaulting to the chained implementation
+  #[serde(default)]
+  realtime_ai_agent: RealtimeAiAgentConfig,
 }
 
 impl Config {
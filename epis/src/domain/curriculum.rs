@@ -0,0 +1,107 @@
+use std::collections::HashSet;
+
+use tracing::warn;
+
+use crate::domain::{
+  models::{CurriculumUnit, EpisError, Id, LearningFocus, LearningGoal, LearningGoalData},
+  ports::EpisRepository,
+};
+
+/// Max number of the vocab of the next curriculum unit given to the llm at once, so that it can
+/// pick one fitting the conversation
+const UNIT_VOCAB_LIMIT: usize = 5;
+
+/// Normalize a learning goal, trimming its texts and dropping its blank vocab
+///
+/// # Errors
+/// If the goal has no description or one of its units has no title,
+/// [EpisError::InvalidLearningGoal] is returned
+pub fn normalize_learning_goal(
+  learning_goal_data: LearningGoalData,
+) -> Result<LearningGoalData, EpisError> {
+  let description = learning_goal_data.description().trim().to_string();
+  if description.is_empty() {
+    return Err(EpisError::InvalidLearningGoal);
+  }
+
+  let curriculum = learning_goal_data
+    .curriculum()
+    .iter()
+    .map(|unit| {
+      let title = unit.title().trim().to_string();
+      if title.is_empty() {
+        return Err(EpisError::InvalidLearningGoal);
+      }
+      let target_vocab = unit
+        .target_vocab()
+        .iter()
+        .map(|vocab| vocab.trim().to_string())
+        .filter(|vocab| !vocab.is_empty())
+        .collect();
+
+      Ok(CurriculumUnit::new(title, target_vocab))
+    })
+    .collect::<Result<Vec<_>, _>>()?;
+
+  Ok(LearningGoalData::new(description, curriculum))
+}
+
+/// Get the learning focus of a chat from the learning goals of the learner. The next curriculum
+/// unit is the first one, in the order of the goals, with vocab the learner hasn't learned yet.
+fn learning_focus(learning_goals: &[LearningGoal], learned_vocab: &[String]) -> LearningFocus {
+  // Learned vocab is matched case-insensitively, as it's stored
+  let learned_vocab = learned_vocab
+    .iter()
+    .map(|vocab| vocab.to_lowercase())
+    .collect::<HashSet<_>>();
+  let goals = learning_goals
+    .iter()
+    .map(|learning_goal| learning_goal.description().clone())
+    .collect();
+
+  let next_unit = learning_goals
+    .iter()
+    .flat_map(|learning_goal| learning_goal.curriculum())
+    .find_map(|unit| {
+      let target_vocab = unit
+        .target_vocab()
+        .iter()
+        .filter(|vocab| !learned_vocab.contains(&vocab.to_lowercase()))
+        .take(UNIT_VOCAB_LIMIT)
+        .cloned()
+        .collect::<Vec<_>>();
+
+      (!target_vocab.is_empty()).then(|| (unit.title().clone(), target_vocab))
+    });
+
+  match next_unit {
+    Some((unit_title, target_vocab)) => LearningFocus::new(goals, Some(unit_title), target_vocab),
+    None => LearningFocus::new(goals, None, Vec::new()),
+  }
+}
+
+/// Get the learning focus of a chat from the learning goals of the learner and the vocab they have
+/// learned
+///
+/// # Errors
+/// If error is related to data store, [EpisError::RepoError] is returned
+pub async fn get_learning_focus(
+  epis_repo: &impl EpisRepository,
+  chatmate_id: &Id,
+) -> Result<LearningFocus, EpisError> {
+  let learning_goals = epis_repo
+    .get_learning_goals(chatmate_id)
+    .await
+    .inspect_err(|error| warn!(%error, "Error while getting learning goals"))
+    .map_err(|_| EpisError::RepoError)?;
+  if learning_goals.is_empty() {
+    return Ok(LearningFocus::default());
+  }
+  let learned_vocab = epis_repo
+    .fetch_learned_vocab(chatmate_id)
+    .await
+    .inspect_err(|error| warn!(%error, "Error while fetching learned vocab"))
+    .map_err(|_| EpisError::RepoError)?;
+
+  Ok(learning_focus(&learning_goals, &learned_vocab))
+}
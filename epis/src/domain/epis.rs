@@ -1,26 +1,85 @@
 use std::sync::Arc;
 
 use derive_more::Constructor;
+use isolang::Language;
+use tokio::sync::mpsc;
 use tracing::{debug, instrument, trace, warn};
 
 use crate::domain::{
+  curriculum::normalize_learning_goal,
   models::{
-    ChatMate, ChatMateLanguage, EpisAudioMessage, EpisAudioMessageFormat, EpisError, Id,
-    RealtimeAiAgentChatContext, UserId,
+    CHATMATE_VOICES, ChatEvent, ChatMate, ChatMateLanguage, ChatMateVoice, EpisAudioMessage,
+    EpisAudioMessageFormat, EpisError, Id, LearningGoal, LearningGoalData, LevelChange,
+    LevelChangeStatus, MAX_SPEAKING_STYLE_CHARS, NativeLanguage, PlacementProgress,
+    RealtimeAiAgentChatContext, ScenarioId, ScenarioProgress, UsageReport, UserId,
+    WordPronunciation,
   },
-  ports::{AudioDuplex, Epis as EpisService, EpisRepository, RealtimeAiAgent},
+  placement::PLACEMENT_TURNS,
+  ports::{AudioDuplex, Epis as EpisService, EpisRepository, RealtimeAiAgent, UserManagement},
+  pronunciation::PRONUNCIATION_REPORT_MAX_SCORE,
+  scenario::get_scenario,
 };
 
+/// Capacity of the channel of reply audio chunks
+const REPLY_CHUNKS_CAPACITY: usize = 16;
+
+/// Normalize the voice of a chatmate, lowercasing its identifier and dropping a blank speaking
+/// style
+///
+/// # Errors
+/// - If the voice is not supported, [EpisError::UnsupportedVoice] is returned
+/// - If the speaking style is too long, [EpisError::SpeakingStyleTooLong] is returned
+fn normalize_chatmate_voice(voice: &ChatMateVoice) -> Result<ChatMateVoice, EpisError> {
+  let voice_id = voice.voice().trim().to_lowercase();
+  if !CHATMATE_VOICES.contains(&voice_id.as_str()) {
+    return Err(EpisError::UnsupportedVoice);
+  }
+
+  let speaking_style = voice
+    .speaking_style()
+    .as_deref()
+    .map(str::trim)
+    .filter(|speaking_style| !speaking_style.is_empty());
+  if speaking_style
+    .is_some_and(|speaking_style| speaking_style.chars().count() > MAX_SPEAKING_STYLE_CHARS)
+  {
+    return Err(EpisError::SpeakingStyleTooLong);
+  }
+
+  Ok(ChatMateVoice::new(
+    voice_id,
+    speaking_style.map(str::to_string),
+  ))
+}
+
+/// Normalize the native language of a learner, lowercasing its ISO 639-1 code
+///
+/// # Errors
+/// - If the native language is not an ISO 639-1 code, [EpisError::UnsupportedNativeLanguage] is
+///   returned
+fn normalize_native_language(
+  native_language: &NativeLanguage,
+) -> Result<NativeLanguage, EpisError> {
+  let code = native_language.as_ref().trim().to_lowercase();
+  if Language::from_639_1(&code).is_none() {
+    return Err(EpisError::UnsupportedNativeLanguage);
+  }
+
+  Ok(NativeLanguage::new(code))
+}
+
 /// The canonical implementation of [EpisService]
 #[derive(Debug, Clone, Constructor)]
-pub struct Epis<ER: EpisRepository, RAA: RealtimeAiAgent> {
+pub struct Epis<ER: EpisRepository, UM: UserManagement, RAA: RealtimeAiAgent> {
   /// The epis repo
   repository: Arc<ER>,
+  /// User management, for CEFR level
+  user_management: Arc<UM>,
   /// Realtime AI agent
   realtime_ai_agent: Arc<RAA>,
 }
 
-impl<ER: EpisRepository, RAA: RealtimeAiAgent> Epis<ER, RAA> {
+impl<ER: EpisRepository, UM: UserManagement, RAA: RealtimeAiAgent> Epis<ER, UM, RAA> {
   /// Assert that the chatmate with the provided language is not already handshaken
   ///
   /// # Errors
@@ -42,19 +101,48 @@ impl<ER: EpisRepository, RAA: RealtimeAiAgent> Epis<ER, RAA> {
 
     Err(EpisError::AlreadyHandshaken)
   }
+
+  /// Get a chatmate of a user by its id, or none if the user has no chatmate with the id
+  ///
+  /// # Errors
+  /// - If any repo error occurs, return [EpisError::RepoError]
+  pub async fn get_user_chatmate(
+    &self,
+    user_id: &UserId,
+    chatmate_id: &Id,
+  ) -> Result<Option<ChatMate>, EpisError> {
+    // Chatmates are looked up among the user's ones, so that other users' chatmates are not found
+    Ok(
+      self
+        .repository
+        .get_chatmates(user_id, None)
+        .await?
+        .into_iter()
+        .find(|chatmate| chatmate.id().as_ref() == chatmate_id.as_ref()),
+    )
+  }
 }
 
-impl<ER: EpisRepository, RAA: RealtimeAiAgent> EpisService for Epis<ER, RAA> {
+impl<ER: EpisRepository, UM: UserManagement, RAA: RealtimeAiAgent> EpisService
+  for Epis<ER, UM, RAA>
+{
   #[instrument(skip(self))]
   async fn handshake(
     &self,
     user_id: &UserId,
     language: &ChatMateLanguage,
+    voice: &ChatMateVoice,
+    native_language: &NativeLanguage,
   ) -> Result<ChatMate, EpisError> {
+    let voice = normalize_chatmate_voice(voice)?;
+    let native_language = normalize_native_language(native_language)?;
     self.assert_not_handshaken(user_id, language).await?;
     debug!("Asserted that chatmate is not handshaken");
 
-    self.repository.create_chatmate(user_id, language).await
+    self
+      .repository
+      .create_chatmate(user_id, language, &voice, &native_language)
+      .await
   }
 
   #[instrument(skip(self, duplex))]
@@ -64,7 +152,13 @@ impl<ER: EpisRepository, RAA: RealtimeAiAgent> EpisService for Epis<ER, RAA> {
     chatmate_id: &Id,
     duplex: &mut impl AudioDuplex,
     audio_format: &EpisAudioMessageFormat,
+    scenario_id: Option<&ScenarioId>,
   ) -> Result<(), EpisError> {
+    // Progress of the scenario lives as long as the chat session, so that a new session starts the
+    // scenario over
+    let mut scenario_progress = scenario_id
+      .map(|scenario_id| ScenarioProgress::new(get_scenario(scenario_id), Vec::new(), false));
+
     loop {
       let audio_bytes = duplex
         .receive()
@@ -76,29 +170,60 @@ impl<ER: EpisRepository, RAA: RealtimeAiAgent> EpisService for Epis<ER, RAA> {
 
       trace!("Message received");
 
-      let chat_context = RealtimeAiAgentChatContext::new(user_id.clone(), chatmate_id.clone());
+      let chat_context = RealtimeAiAgentChatContext::new(
+        user_id.clone(),
+        chatmate_id.clone(),
+        scenario_progress.clone(),
+      );
 
-      let response = self
-        .realtime_ai_agent
-        .chat(audio_message, &chat_context)
-        .await
-        .inspect_err(|error| warn!(%error, "Ai agent chat failed"))
-        .map_err(|e| match e {
-          EpisError::NoCredit => EpisError::NoCredit,
-          _ => EpisError::AiAgentFailure,
-        })?;
+      let (reply_chunks_sender, mut reply_chunks_receiver) = mpsc::channel(REPLY_CHUNKS_CAPACITY);
 
-      trace!("Ai agent generated a response");
+      let agent_chat = async {
+        self
+          .realtime_ai_agent
+          .chat(audio_message, &chat_context, reply_chunks_sender)
+          .await
+          .inspect_err(|error| warn!(%error, "Ai agent chat failed"))
+          .map_err(|e| match e {
+            EpisError::NoCredit => EpisError::NoCredit,
+            _ => EpisError::AiAgentFailure,
+          })
+      };
 
-      let (reponse_bytes, _) = response.into_parts();
+      let delivery = async {
+        while let Some(reply_chunk) = reply_chunks_receiver.recv().await {
+          let (reply_chunk_bytes, _) = reply_chunk.into_parts();
 
-      duplex
-        .send(reponse_bytes)
-        .await
-        .inspect_err(|error| warn!(%error, "Sending message over the duplex failed"))
-        .map_err(|_| EpisError::DuplexError)?;
+          duplex
+            .send(reply_chunk_bytes)
+            .await
+            .inspect_err(|error| warn!(%error, "Sending message over the duplex failed"))
+            .map_err(|_| EpisError::DuplexError)?;
+
+          trace!("Reply chunk sent back to the user");
+        }
+
+        Ok(())
+      };
+
+      let (events, _) = tokio::try_join!(agent_chat, delivery)?;
 
-      trace!("Response sent back to the user")
+      trace!("Ai agent reply completed");
+
+      for event in events {
+        // Once a scenario is completed, the chat goes on as small talk
+        if let ChatEvent::ScenarioProgress(progress) = &event {
+          scenario_progress = (!progress.completed()).then(|| progress.clone());
+        }
+
+        duplex
+          .send_event(event)
+          .await
+          .inspect_err(|error| warn!(%error, "Sending event over the duplex failed"))
+          .map_err(|_| EpisError::DuplexError)?;
+
+        trace!("Chat event sent to the user");
+      }
     }
   }
 
@@ -106,4 +231,188 @@ impl<ER: EpisRepository, RAA: RealtimeAiAgent> EpisService for Epis<ER, RAA> {
   async fn list_chatmates(&self, user_id: &UserId) -> Result<Vec<ChatMate>, EpisError> {
     self.repository.get_chatmates(user_id, None).await
   }
+
+  #[instrument(skip(self))]
+  async fn get_placement_progress(
+    &self,
+    user_id: &UserId,
+    chatmate_id: &Id,
+  ) -> Result<Option<PlacementProgress>, EpisError> {
+    let Some(chatmate) = self.get_user_chatmate(user_id, chatmate_id).await? else {
+      return Ok(None);
+    };
+
+    let cefr_level = self
+      .user_management
+      .get_cefr_level(user_id, chatmate.language())
+      .await?;
+    let assessed_turns = self
+      .repository
+      .get_placement_levels(chatmate.id())
+      .await?
+      .len() as u32;
+
+    Ok(Some(PlacementProgress::new(
+      assessed_turns.min(PLACEMENT_TURNS),
+      PLACEMENT_TURNS,
+      cefr_level,
+    )))
+  }
+
+  #[instrument(skip(self))]
+  async fn list_level_changes(
+    &self,
+    user_id: &UserId,
+    chatmate_id: &Id,
+  ) -> Result<Option<Vec<LevelChange>>, EpisError> {
+    let Some(chatmate) = self.get_user_chatmate(user_id, chatmate_id).await? else {
+      return Ok(None);
+    };
+
+    Ok(Some(
+      self.repository.get_level_changes(chatmate.id()).await?,
+    ))
+  }
+
+  #[instrument(skip(self))]
+  async fn resolve_level_change(
+    &self,
+    user_id: &UserId,
+    chatmate_id: &Id,
+    level_change_id: &Id,
+    confirmed: bool,
+  ) -> Result<Option<LevelChange>, EpisError> {
+    let Some(chatmate) = self.get_user_chatmate(user_id, chatmate_id).await? else {
+      return Ok(None);
+    };
+    let Some(level_change) = self
+      .repository
+      .get_level_changes(chatmate.id())
+      .await?
+      .into_iter()
+      .find(|level_change| level_change.id().as_ref() == level_change_id.as_ref())
+    else {
+      return Ok(None);
+    };
+    if *level_change.status() != LevelChangeStatus::Pending {
+      return Err(EpisError::LevelChangeAlreadyResolved);
+    }
+
+    let status = if confirmed {
+      self
+        .user_management
+        .set_cefr_level(user_id, chatmate.language(), level_change.to_level())
+        .await?;
+      LevelChangeStatus::Applied
+    } else {
+      LevelChangeStatus::Rejected
+    };
+    self
+      .repository
+      .update_level_change_status(level_change.id(), &status)
+      .await?;
+    debug!(?status, "Level change resolved");
+
+    let (id, from_level, to_level, rationale, _) = level_change.into_parts();
+    Ok(Some(LevelChange::new(
+      id, from_level, to_level, rationale, status,
+    )))
+  }
+
+  #[instrument(skip(self))]
+  async fn list_learning_goals(
+    &self,
+    user_id: &UserId,
+    chatmate_id: &Id,
+  ) -> Result<Option<Vec<LearningGoal>>, EpisError> {
+    let Some(chatmate) = self.get_user_chatmate(user_id, chatmate_id).await? else {
+      return Ok(None);
+    };
+
+    Ok(Some(
+      self.repository.get_learning_goals(chatmate.id()).await?,
+    ))
+  }
+
+  #[instrument(skip(self))]
+  async fn create_learning_goal(
+    &self,
+    user_id: &UserId,
+    chatmate_id: &Id,
+    learning_goal_data: LearningGoalData,
+  ) -> Result<Option<LearningGoal>, EpisError> {
+    let learning_goal_data = normalize_learning_goal(learning_goal_data)?;
+    let Some(chatmate) = self.get_user_chatmate(user_id, chatmate_id).await? else {
+      return Ok(None);
+    };
+
+    let learning_goal = self
+      .repository
+      .create_learning_goal(chatmate.id(), &learning_goal_data)
+      .await?;
+    debug!(learning_goal_id = %learning_goal.id(), "Learning goal created");
+
+    Ok(Some(learning_goal))
+  }
+
+  #[instrument(skip(self))]
+  async fn update_learning_goal(
+    &self,
+    user_id: &UserId,
+    chatmate_id: &Id,
+    learning_goal_id: &Id,
+    learning_goal_data: LearningGoalData,
+  ) -> Result<Option<LearningGoal>, EpisError> {
+    let learning_goal_data = normalize_learning_goal(learning_goal_data)?;
+    let Some(chatmate) = self.get_user_chatmate(user_id, chatmate_id).await? else {
+      return Ok(None);
+    };
+
+    self
+      .repository
+      .update_learning_goal(chatmate.id(), learning_goal_id, &learning_goal_data)
+      .await
+  }
+
+  #[instrument(skip(self))]
+  async fn delete_learning_goal(
+    &self,
+    user_id: &UserId,
+    chatmate_id: &Id,
+    learning_goal_id: &Id,
+  ) -> Result<Option<()>, EpisError> {
+    let Some(chatmate) = self.get_user_chatmate(user_id, chatmate_id).await? else {
+      return Ok(None);
+    };
+
+    let deleted = self
+      .repository
+      .delete_learning_goal(chatmate.id(), learning_goal_id)
+      .await?;
+
+    Ok(deleted.then_some(()))
+  }
+
+  #[instrument(skip(self))]
+  async fn get_pronunciation_report(
+    &self,
+    user_id: &UserId,
+    chatmate_id: &Id,
+  ) -> Result<Option<Vec<WordPronunciation>>, EpisError> {
+    let Some(chatmate) = self.get_user_chatmate(user_id, chatmate_id).await? else {
+      return Ok(None);
+    };
+
+    Ok(Some(
+      self
+        .repository
+        .get_pronunciation_report(chatmate.id(), PRONUNCIATION_REPORT_MAX_SCORE, None)
+        .await?,
+    ))
+  }
+
+  #[instrument(skip(self))]
+  async fn get_usage(&self, user_id: &UserId) -> Result<UsageReport, EpisError> {
+    self.repository.get_usage_by_user(user_id).await
+  }
 }
@@ -0,0 +1,144 @@
+use tracing::{debug, warn};
+
+use crate::domain::{
+  models::{ChatMateLanguage, ChatMemory, ChatMessage, ChatMessageRole, EpisError, Id},
+  placement::language_name,
+  ports::{AiGateway, EpisRepository},
+};
+
+/// Number of the most recent messages of a chat sent to the llm as is, along with the memory of
+/// the older ones
+pub const CHAT_HISTORY_SIZE: u8 = 10;
+/// Min number of messages older than the chat history to compress into the memory at once, so that
+/// a chat is not summarized on every turn
+const SUMMARY_BATCH_SIZE: usize = 10;
+/// Max number of learner facts kept in the memory of a chat
+const MAX_LEARNER_FACTS: usize = 20;
+
+/// Generate instructions of updating the memory of a chat with a batch of older messages
+fn generate_summary_instructions(language: &ChatMateLanguage) -> String {
+  let language_name = language_name(language);
+
+  format!(
+    r#"
+# Identity
+
+You are the memory of a foreign language chatmate that helps a user learn {language_name} via small talks.
+
+# Instructions
+
+- You are given the current summary of the conversation, the current facts about the user, and the next messages of the conversation.
+- Update the summary so that it covers the current summary and the next messages, keeping the topics talked about, the stories the user told, and anything worth following up on. The summary should not exceed 150 words, and should be written in English.
+- Update the facts about the user, e.g. their name, job, family, hobbies, likes and dislikes, and plans such as trips or exams. Keep the current facts unless the messages contradict them, and merge duplicates. Each fact should be a short sentence in English, and there should be at most {MAX_LEARNER_FACTS} facts.
+- Only use what the user actually said, and never make up facts.
+- Ignore the language mistakes of the user, as they are handled separately.
+"#
+  )
+}
+
+/// Format a batch of messages along with the current memory of a chat as a summarization input
+fn format_summary_input(chat_memory: &ChatMemory, messages: &[ChatMessage]) -> String {
+  let summary = if chat_memory.summary().is_empty() {
+    "(none)"
+  } else {
+    chat_memory.summary()
+  };
+  let learner_facts = if chat_memory.learner_facts().is_empty() {
+    "(none)".to_string()
+  } else {
+    chat_memory.learner_facts().join("\n- ")
+  };
+  let messages = messages
+    .iter()
+    .map(|message| {
+      let speaker = match message.role() {
+        ChatMessageRole::User => "User",
+        ChatMessageRole::Ai => "Chatmate",
+        ChatMessageRole::System => "System",
+      };
+      format!("{speaker}: {}", message.message())
+    })
+    .collect::<Vec<_>>()
+    .join("\n");
+
+  format!(
+    "Current summary:\n{summary}\n\nCurrent facts about the user:\n- {learner_facts}\n\nNext messages:\n{messages}"
+  )
+}
+
+/// Compress the messages of a chat older than the chat history into its memory, once enough of
+/// them are accumulated. The usage of the summarization is accounted to the last summarized
+/// message.
+///
+/// # Errors
+/// - If summarization fails, [EpisError::ProviderError] or [EpisError::InvalidStructuredOutput] is
+///   returned
+/// - If error is related to data store, [EpisError::RepoError] is returned
+pub async fn summarize_chat(
+  ai_gateway: &impl AiGateway,
+  epis_repo: &impl EpisRepository,
+  model: &str,
+  chatmate_id: &Id,
+  language: &ChatMateLanguage,
+) -> Result<(), EpisError> {
+  let Some(unsummarized_messages) = epis_repo
+    .get_unsummarized_messages(chatmate_id, CHAT_HISTORY_SIZE)
+    .await
+    .inspect_err(|error| warn!(%error, "Error while getting unsummarized messages"))
+    .map_err(|_| EpisError::RepoError)?
+  else {
+    return Ok(());
+  };
+  if unsummarized_messages.messages().len() < SUMMARY_BATCH_SIZE {
+    return Ok(());
+  }
+
+  let chat_memory = epis_repo
+    .get_chat_memory(chatmate_id)
+    .await
+    .inspect_err(|error| warn!(%error, "Error while getting chat memory"))
+    .map_err(|_| EpisError::RepoError)?;
+  let (messages, last_message_id) = unsummarized_messages.into_parts();
+  let summary_input = vec![
+    ChatMessage::new(
+      ChatMessageRole::System,
+      generate_summary_instructions(language),
+    ),
+    ChatMessage::new(
+      ChatMessageRole::User,
+      format_summary_input(&chat_memory, &messages),
+    ),
+  ];
+  let (summary, mut learner_facts, usage) = ai_gateway
+    .summarize_chat(model, &summary_input)
+    .await
+    .inspect_err(|error| warn!(%error, "Error during chat summarization"))
+    .map_err(|error| match error {
+      EpisError::InvalidStructuredOutput => error,
+      _ => EpisError::ProviderError,
+    })?
+    .into_parts();
+  learner_facts.truncate(MAX_LEARNER_FACTS);
+  debug!(
+    summarized_messages = messages.len(),
+    learner_facts = learner_facts.len(),
+    "Chat summarized"
+  );
+
+  epis_repo
+    .store_chat_memory(
+      chatmate_id,
+      &ChatMemory::new(summary, learner_facts),
+      &last_message_id,
+    )
+    .await
+    .inspect_err(|error| warn!(%error, "Error while storing chat memory"))
+    .map_err(|_| EpisError::RepoError)?;
+  epis_repo
+    .store_usage(&last_message_id, &usage)
+    .await
+    .inspect_err(|error| warn!(%error, "Error while storing chat summarization usage"))
+    .map_err(|_| EpisError::RepoError)?;
+
+  Ok(())
+}
@@ -1,10 +1,28 @@
 //! All domain related stuff of Epis
 
+/// Learning goals of learners and their curriculum, steering chats towards them
+pub mod curriculum;
 /// Canonical implementation of the main Epis service
 pub mod epis;
+/// Long-term memory of chats, compressing older messages into a running summary and learner facts
+pub mod memory;
 /// Domain models
 pub mod models;
+/// Placement of learners at a CEFR level by assessing their first chat turns
+pub mod placement;
 /// Domain ports for communicating with external resources
 pub mod ports;
+/// Periodic evaluation of the CEFR level of learners, proposing level changes
+pub mod progression;
+/// Pronunciation scoring of the words of user messages
+pub mod pronunciation;
 /// Canonical implementation of a realtime ai agent
 pub mod realtime_ai_agent;
+/// Recall of past exchanges of chats, semantically via message embeddings
+pub mod recall;
+/// Spaced repetition schedulers of learned vocab reviews
+pub mod review_scheduler;
+/// Role-play scenarios of chat sessions, e.g. ordering at a café
+pub mod scenario;
+/// Per language tokenizers and lemmatizers, matching vocab in texts
+pub mod vocab_matcher;
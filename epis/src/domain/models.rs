@@ -1,3 +1,5 @@
+use std::ops::AddAssign;
+
 use derive_getters::{Dissolve, Getters};
 use derive_more::{AsRef, Constructor, Debug, Display, From, FromStr};
 use serde::Deserialize;
@@ -20,6 +22,46 @@ pub enum ChatMateLanguage {
   Tr,
 }
 
+/// Voice identifier used by chatmates by default
+pub const DEFAULT_CHATMATE_VOICE: &str = "alloy";
+
+/// Voice identifiers chatmates can use, i.e. the voices of the OpenAI speech models
+pub const CHATMATE_VOICES: &[&str] = &[
+  "alloy", "ash", "ballad", "coral", "echo", "fable", "onyx", "nova", "sage", "shimmer", "verse",
+];
+
+/// Max number of characters of the speaking style instructions of a chatmate
+pub const MAX_SPEAKING_STYLE_CHARS: usize = 300;
+
+/// Voice of a chatmate, used for text to speech
+#[derive(Debug, Clone, Getters, Constructor)]
+pub struct ChatMateVoice {
+  /// Provider specific voice identifier, e.g. alloy
+  voice: String,
+  /// Speaking style instructions, e.g. "Cheerful and a bit playful"
+  speaking_style: Option<String>,
+}
+
+impl Default for ChatMateVoice {
+  fn default() -> Self {
+    Self::new(DEFAULT_CHATMATE_VOICE.to_string(), None)
+  }
+}
+
+/// Native language of learners by default, as an ISO 639-1 code
+pub const DEFAULT_NATIVE_LANGUAGE: &str = "en";
+
+/// Native language of the learner of a chatmate as an ISO 639-1 code, e.g. fa, used for the
+/// explanations and translations of the chatmate
+#[derive(Debug, Clone, Constructor, Display, AsRef)]
+pub struct NativeLanguage(String);
+
+impl Default for NativeLanguage {
+  fn default() -> Self {
+    Self::new(DEFAULT_NATIVE_LANGUAGE.to_string())
+  }
+}
+
 /// Represent a chatmate, skilled in a specific language
 #[derive(Debug, Clone, Getters, Constructor)]
 pub struct ChatMate {
@@ -27,6 +69,10 @@ pub struct ChatMate {
   language: ChatMateLanguage,
   /// Chatmate id
   id: Id,
+  /// The voice of the chatmate
+  voice: ChatMateVoice,
+  /// Native language of the learner
+  native_language: NativeLanguage,
 }
 
 /// All possible errors of Epis
@@ -47,9 +93,30 @@ pub enum EpisError {
   /// Any error that is returned from the ai provider
   #[error("Provider error")]
   ProviderError,
+  /// Any error from the ai provider that may not happen on retry, e.g. a network error or timeout
+  #[error("Transient provider error")]
+  TransientProviderError,
+  /// The ai provider output doesn't match the structured output schema, even after repairing it
+  #[error("Invalid structured output")]
+  InvalidStructuredOutput,
   /// User has no credit and should top up
   #[error("No credit remaining")]
   NoCredit,
+  /// The level change is already applied or rejected
+  #[error("Level change already resolved")]
+  LevelChangeAlreadyResolved,
+  /// The learning goal has no description, or one of its curriculum units has no title
+  #[error("Invalid learning goal")]
+  InvalidLearningGoal,
+  /// The voice of a chatmate is not one of [CHATMATE_VOICES]
+  #[error("Unsupported voice")]
+  UnsupportedVoice,
+  /// The speaking style of a chatmate is longer than [MAX_SPEAKING_STYLE_CHARS]
+  #[error("Speaking style is too long")]
+  SpeakingStyleTooLong,
+  /// The native language of a learner is not an ISO 639-1 code
+  #[error("Unsupported native language")]
+  UnsupportedNativeLanguage,
   /// A fallback error
   #[error("Unknown error")]
   Unknown,
@@ -84,6 +151,8 @@ pub struct EpisAudioMessage {
 pub struct RealtimeAiAgentChatContext {
   user_id: UserId,
   chatmate_id: Id,
+  /// Progress of the learner in the role-play scenario of the chat session, if any
+  scenario_progress: Option<ScenarioProgress>,
 }
 
 /// A type alias for a very basic bytes representation
@@ -115,22 +184,284 @@ pub enum CreditAuthStatus {
   Unauthorized,
 }
 
-/// Structured response of ai generation
+/// Usage of an ai provider by one or more operations, used for pricing
+#[derive(Debug, Clone, Default, Getters, Constructor)]
+pub struct AiUsage {
+  /// Input tokens of generation, or of token billed transcription
+  input_tokens: u32,
+  /// Output tokens of generation, or of token billed transcription
+  output_tokens: u32,
+  /// Seconds of transcribed audio, for duration billed transcription
+  audio_seconds: f32,
+  /// Characters converted to speech
+  characters: u32,
+}
+
+/// Total ai usage of a user, summed across all of their messages
+#[derive(Debug, Clone, Default, Getters, Constructor)]
+pub struct UsageReport {
+  /// Input tokens of generation and token billed transcription
+  input_tokens: u64,
+  /// Output tokens of generation and token billed transcription
+  output_tokens: u64,
+  /// Seconds of transcribed audio
+  audio_seconds: f64,
+  /// Characters converted to speech
+  characters: u64,
+}
+
+impl AddAssign for AiUsage {
+  fn add_assign(&mut self, other: Self) {
+    self.input_tokens += other.input_tokens;
+    self.output_tokens += other.output_tokens;
+    self.audio_seconds += other.audio_seconds;
+    self.characters += other.characters;
+  }
+}
+
+/// Category of an error in a user message
+#[derive(Debug, Clone)]
+#[allow(clippy::missing_docs_in_private_items)]
+pub enum CorrectionCategory {
+  Grammar,
+  Vocabulary,
+  WordOrder,
+  Usage,
+}
+
+/// Correction of an error in a user message
 #[derive(Debug, Clone, Getters, Constructor)]
+pub struct Correction {
+  /// Erroneous fragment of the user message
+  original: String,
+  /// Corrected fragment
+  corrected: String,
+  /// Category of the error
+  category: CorrectionCategory,
+  /// Short explanation of the error
+  explanation: String,
+}
+
+/// Structured response of ai generation
+#[derive(Debug, Clone, Getters, Constructor, Dissolve)]
+#[dissolve(rename = "into_parts")]
 #[allow(clippy::missing_docs_in_private_items)]
 pub struct GenerationResponse {
   text: String,
-  learned_vocab: Vec<String>,
+  learned_vocab: Vec<NewVocab>,
+  corrections: Vec<Correction>,
+  scenario_assessment: ScenarioAssessment,
+  usage: AiUsage,
+}
+
+/// An event of a chat turn sent to the user next to the reply audio, without interrupting the
+/// conversation
+#[derive(Debug, Clone)]
+pub enum ChatEvent {
+  /// Corrections of the user message
+  Corrections(Vec<Correction>),
+  /// Progress of the learner in the role-play scenario of the chat session, sent when a goal is
+  /// completed and when the scenario is completed
+  ScenarioProgress(ScenarioProgress),
+}
+
+/// A unit of the curriculum of a learning goal
+#[derive(Debug, Clone, Getters, Constructor)]
+pub struct CurriculumUnit {
+  /// Title of the unit, e.g. "At the airport"
+  title: String,
+  /// Vocab taught in the unit, in base form and in the language of the chatmate
+  target_vocab: Vec<String>,
+}
+
+/// Content of a learning goal, for creating or updating it
+#[derive(Debug, Clone, Getters, Constructor)]
+pub struct LearningGoalData {
+  /// What the learner wants to achieve, e.g. "travel to Istanbul in May"
+  description: String,
+  /// Units of the curriculum of the goal, in order, which may be empty
+  curriculum: Vec<CurriculumUnit>,
+}
+
+/// A learning goal of a learner with a chatmate
+#[derive(Debug, Clone, Getters, Constructor, Dissolve)]
+#[dissolve(rename = "into_parts")]
+pub struct LearningGoal {
+  /// Learning goal id
+  id: Id,
+  /// What the learner wants to achieve, e.g. "travel to Istanbul in May"
+  description: String,
+  /// Units of the curriculum of the goal, in order, which may be empty
+  curriculum: Vec<CurriculumUnit>,
+}
+
+/// What a chat is steered towards by the learning goals of the learner
+#[derive(Debug, Clone, Default, Getters, Constructor)]
+pub struct LearningFocus {
+  /// Descriptions of the learning goals
+  goals: Vec<String>,
+  /// Title of the next curriculum unit, i.e. the first one with vocab not learned yet, if any
+  unit_title: Option<String>,
+  /// Vocab of the next curriculum unit not learned yet, used as the new vocab of the replies
+  target_vocab: Vec<String>,
+}
+
+/// Role-play scenarios of chat sessions
+#[derive(Debug, Clone, PartialEq, Eq, Display, FromStr)]
+#[allow(clippy::missing_docs_in_private_items)]
+pub enum ScenarioId {
+  Cafe,
+  Interview,
+  Doctor,
+}
+
+/// A role-play scenario of a chat session, in which the learner practices a real-life situation
+/// instead of small talk
+#[derive(Debug, Clone, Getters, Constructor)]
+pub struct Scenario {
+  /// Scenario id
+  id: ScenarioId,
+  /// Where the scenario takes place, and who the chatmate and the learner play
+  setting: String,
+  /// Goals the learner should achieve during the scenario, in English
+  goals: Vec<String>,
+  /// Vocab the learner is expected to use, in English, so that it can be used in any language
+  target_vocab: Vec<String>,
+  /// When the scenario is successfully completed, in English
+  success_condition: String,
+}
+
+/// Progress of a learner in a role-play scenario
+#[derive(Debug, Clone, Getters, Constructor)]
+pub struct ScenarioProgress {
+  /// The scenario
+  scenario: Scenario,
+  /// Goals of the scenario completed so far
+  completed_goals: Vec<String>,
+  /// Whether the success condition of the scenario is met
+  completed: bool,
+}
+
+/// Assessment of the last user message of a role-play scenario by the llm, returned along with the
+/// reply
+#[derive(Debug, Clone, Default, Getters, Constructor)]
+pub struct ScenarioAssessment {
+  /// Numbers of the scenario goals completed by the message, starting from 1
+  completed_goals: Vec<u8>,
+  /// Whether the success condition of the scenario is met
+  succeeded: bool,
+}
+
+/// A word of a transcription, along with how confidently it's recognized
+#[derive(Debug, Clone, Getters, Constructor)]
+pub struct TranscribedWord {
+  /// Recognized word
+  word: String,
+  /// Start of the word in the audio in seconds, if provided by the transcription model
+  start: Option<f32>,
+  /// End of the word in the audio in seconds, if provided by the transcription model
+  end: Option<f32>,
+  /// Confidence of recognizing the word, from 0 to 1
+  confidence: f32,
 }
 
 /// Structured response of ai transcription
-pub type TranscriptionResponse = String;
+#[derive(Debug, Clone, Getters, Constructor, Dissolve)]
+#[dissolve(rename = "into_parts")]
+#[allow(clippy::missing_docs_in_private_items)]
+pub struct TranscriptionResponse {
+  text: String,
+  words: Vec<TranscribedWord>,
+  usage: AiUsage,
+}
+
+/// Pronunciation score of a word of a user message
+#[derive(Debug, Clone, Getters, Constructor)]
+pub struct PronunciationScore {
+  /// Recognized word
+  word: String,
+  /// Vocab the learner most likely intended to say, if the word is an attempt at a target vocab
+  intended: Option<String>,
+  /// Score of pronouncing the intended word, from 0 to 1
+  score: f32,
+  /// Start of the word in the audio in seconds, if known
+  start: Option<f32>,
+  /// End of the word in the audio in seconds, if known
+  end: Option<f32>,
+}
+
+/// Pronunciation of a word across the user messages of a chat
+#[derive(Debug, Clone, Getters, Constructor)]
+pub struct WordPronunciation {
+  /// The word, i.e. the intended vocab if any, or the recognized word otherwise
+  word: String,
+  /// Average score of pronouncing the word, from 0 to 1
+  average_score: f32,
+  /// Number of times the word is said
+  attempts: u32,
+  /// Other words the word is recognized as, i.e. how it's mispronounced
+  heard_as: Vec<String>,
+}
 
 /// Structured response of ai text to speech
-pub type TextToSpeechResponse = SimpleBytes;
+#[derive(Debug, Clone, Getters, Constructor, Dissolve)]
+#[dissolve(rename = "into_parts")]
+#[allow(clippy::missing_docs_in_private_items)]
+pub struct TextToSpeechResponse {
+  audio: SimpleBytes,
+  usage: AiUsage,
+}
+
+/// Structured response of ai embedding, with an embedding per embedded text in the same order
+#[derive(Debug, Clone, Getters, Constructor, Dissolve)]
+#[dissolve(rename = "into_parts")]
+#[allow(clippy::missing_docs_in_private_items)]
+pub struct EmbeddingResponse {
+  embeddings: Vec<Vec<f32>>,
+  usage: AiUsage,
+}
+
+/// A chat message along with its id
+#[derive(Debug, Clone, Constructor, Getters, Dissolve)]
+#[dissolve(rename = "into_parts")]
+#[allow(clippy::missing_docs_in_private_items)]
+pub struct StoredChatMessage {
+  id: Id,
+  chat_message: ChatMessage,
+}
+
+/// Embedding of a chat message by an embedding model
+#[derive(Debug, Clone, Constructor, Getters)]
+#[allow(clippy::missing_docs_in_private_items)]
+pub struct MessageEmbedding {
+  message_id: Id,
+  embedding: Vec<f32>,
+}
+
+/// A past exchange of a chat, i.e. a user message and the ai reply to it, recalled for being
+/// related to the current user message
+#[derive(Debug, Clone, Constructor, Getters)]
+pub struct PastExchange {
+  /// Id of the user message
+  id: Id,
+  /// User message
+  user_message: String,
+  /// Ai reply to the user message, if any
+  ai_reply: Option<String>,
+}
+
+/// Past exchanges of a chat retrieved by a history retriever, along with the usage of retrieving
+/// them
+#[derive(Debug, Clone, Default, Constructor, Getters, Dissolve)]
+#[dissolve(rename = "into_parts")]
+#[allow(clippy::missing_docs_in_private_items)]
+pub struct RetrievedExchanges {
+  past_exchanges: Vec<PastExchange>,
+  usage: AiUsage,
+}
 
 /// CEFR level of user
-#[derive(Debug, Clone, Display, Default, FromStr)]
+#[derive(Debug, Clone, Display, Default, FromStr, PartialEq, Eq, PartialOrd, Ord)]
 #[allow(clippy::missing_docs_in_private_items)]
 pub enum CefrLevel {
   #[default]
@@ -142,6 +473,111 @@ pub enum CefrLevel {
   C2,
 }
 
+/// Assessment of the CEFR level a learner demonstrates in a conversation
+#[derive(Debug, Clone, Constructor, Getters, Dissolve)]
+#[dissolve(rename = "into_parts")]
+pub struct CefrAssessment {
+  /// Assessed CEFR level
+  cefr_level: CefrLevel,
+  /// Why the level is assessed, based on the learner's messages
+  rationale: String,
+  /// Usage of the assessment
+  usage: AiUsage,
+}
+
+/// Long-term memory of a chat, i.e. a running summary of its messages older than the chat history
+/// sent to the llm, along with facts about the learner mentioned in them
+#[derive(Debug, Clone, Default, Constructor, Getters)]
+pub struct ChatMemory {
+  /// Running summary of the older messages
+  summary: String,
+  /// Facts about the learner, e.g. their job, hobbies or plans
+  learner_facts: Vec<String>,
+}
+
+/// Summary of a chat generated by an llm
+#[derive(Debug, Clone, Constructor, Getters, Dissolve)]
+#[dissolve(rename = "into_parts")]
+pub struct ChatSummary {
+  /// Updated running summary
+  summary: String,
+  /// Updated facts about the learner
+  learner_facts: Vec<String>,
+  /// Usage of the summarization
+  usage: AiUsage,
+}
+
+/// Chat messages not yet compressed into the memory of a chat
+#[derive(Debug, Clone, Constructor, Getters, Dissolve)]
+#[dissolve(rename = "into_parts")]
+pub struct UnsummarizedMessages {
+  /// Messages, in ascending order
+  messages: Vec<ChatMessage>,
+  /// Id of the last message
+  last_message_id: Id,
+}
+
+/// Progress of the placement of a learner, i.e. assessing their CEFR level in the first turns of
+/// a chat with a new chatmate
+#[derive(Debug, Clone, Constructor, Getters)]
+pub struct PlacementProgress {
+  /// Number of turns assessed so far
+  assessed_turns: u32,
+  /// Number of turns needed for placement
+  total_turns: u32,
+  /// CEFR level of the learner, which is none while placement is in progress
+  cefr_level: Option<CefrLevel>,
+}
+
+/// How proposed CEFR level changes of learners are applied
+#[derive(Debug, Clone, Default, FromStr)]
+pub enum CefrProgressionMode {
+  /// Level changes are applied as soon as they are proposed
+  #[default]
+  Auto,
+  /// Level changes are applied only after the learner confirms them
+  Confirm,
+}
+
+/// Status of a CEFR level change
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LevelChangeStatus {
+  /// Proposed, waiting for the learner to confirm or reject it
+  Pending,
+  /// Applied to the CEFR level of the learner
+  Applied,
+  /// Rejected by the learner
+  Rejected,
+}
+
+/// A change of the CEFR level of a learner, proposed by evaluating their recent conversation with
+/// a chatmate
+#[derive(Debug, Clone, Constructor, Getters, Dissolve)]
+#[dissolve(rename = "into_parts")]
+pub struct LevelChange {
+  /// Level change id
+  id: Id,
+  /// CEFR level before the change
+  from_level: CefrLevel,
+  /// Proposed CEFR level
+  to_level: CefrLevel,
+  /// Why the change is proposed, based on the learner's messages
+  rationale: String,
+  /// Status of the change
+  status: LevelChangeStatus,
+}
+
+/// Stats of the vocab learned with a chatmate
+#[derive(Debug, Clone, Constructor, Getters)]
+pub struct VocabStats {
+  /// Number of learned words
+  learned: u32,
+  /// Number of learned words the learner has produced at least once
+  produced: u32,
+  /// Number of learned words whose review interval is at least three weeks
+  mature: u32,
+}
+
 /// Represents the role of a participant in a chat conversation
 #[derive(Debug, Clone)]
 pub enum ChatMessageRole {
@@ -167,14 +603,141 @@ pub struct ChatMessage {
 pub enum LearnedVocabStatus {
   New,
   Reviewed,
-  #[allow(dead_code)]
+  Produced,
   Reset,
 }
 
-/// Represent a word and its learning status
+/// Stage of the realtime chat pipeline a moderated text belongs to
+#[derive(Debug, Clone)]
+pub enum ModerationStage {
+  /// Transcription of a user message
+  Input,
+  /// Generated ai reply, checked before text to speech
+  Output,
+}
+
+/// Action taken on a text flagged by moderation
+#[derive(Debug, Clone)]
+pub enum ModerationAction {
+  /// A safe canned reply is sent instead of generating one
+  CannedReply,
+  /// The rest of the generated reply is replaced by a safe canned reply
+  Replaced,
+}
+
+/// Verdict of moderating a text
+#[derive(Debug, Clone, Default, Getters, Constructor)]
+pub struct ModerationVerdict {
+  /// Whether the text is unsafe
+  flagged: bool,
+  /// Provider specific categories the text is flagged for, e.g. violence
+  categories: Vec<String>,
+}
+
+/// A text flagged by moderation, along with the action taken on it
+#[derive(Debug, Clone, Getters, Constructor)]
+#[allow(clippy::missing_docs_in_private_items)]
+pub struct ModerationEvent {
+  stage: ModerationStage,
+  action: ModerationAction,
+  text: String,
+  verdict: ModerationVerdict,
+}
+
+/// Spaced repetition state of a learned word. Each scheduler only uses the part of the state
+/// related to its algorithm.
+#[derive(Debug, Clone, Constructor, Getters)]
+pub struct ReviewState {
+  /// SM-2 easiness factor
+  ease: f32,
+  /// FSRS stability, i.e. days after which recall probability drops to 90%
+  stability: f32,
+  /// FSRS difficulty, from 1 to 10
+  difficulty: f32,
+  /// Days from now until the next review
+  interval_days: f32,
+  /// Number of consecutive successful reviews
+  repetitions: u32,
+}
+
+/// Grade of recalling a word in a review
+#[derive(Debug, Clone)]
+pub enum ReviewGrade {
+  /// The word was forgotten
+  Again,
+  /// The word was recalled
+  Good,
+  /// The word was actively recalled, i.e. produced by the learner
+  Easy,
+}
+
+/// A learned word along with its current review state, and the days elapsed since its last review
+#[derive(Debug, Clone, Constructor, Getters, Dissolve)]
+#[dissolve(rename = "into_parts")]
+#[allow(clippy::missing_docs_in_private_items)]
+pub struct ScheduledVocab {
+  vocab: String,
+  review_state: ReviewState,
+  elapsed_days: f32,
+}
+
+/// An occurrence of a learned word in a text, along with its byte range in the text, e.g. for
+/// highlighting it
+#[derive(Debug, Clone, Constructor, Getters)]
+#[allow(clippy::missing_docs_in_private_items)]
+pub struct VocabMatch {
+  vocab: String,
+  start: usize,
+  end: usize,
+}
+
+/// Represent a word, its learning status, and its review state after it
 #[derive(Debug, Clone, Constructor, Getters)]
 #[allow(clippy::missing_docs_in_private_items)]
 pub struct LearnedVocabData {
   vocab: String,
   status: LearnedVocabStatus,
+  review_state: ReviewState,
+  /// Details of the word, only known when it's new
+  details: Option<VocabDetails>,
+}
+
+/// Part of speech of a vocab
+#[derive(Debug, Clone)]
+#[allow(clippy::missing_docs_in_private_items)]
+pub enum PartOfSpeech {
+  Noun,
+  Verb,
+  Adjective,
+  Adverb,
+  Pronoun,
+  Preposition,
+  Conjunction,
+  Interjection,
+  Idiom,
+  Other,
+}
+
+/// Details of a vocab, so that it's meaningful when the learner reviews it outside the
+/// conversation
+#[derive(Debug, Clone, Getters, Constructor)]
+pub struct VocabDetails {
+  /// Part of speech of the vocab
+  part_of_speech: PartOfSpeech,
+  /// Translation of the vocab into the native language of the learner
+  translation: String,
+  /// Short and simple definition of the vocab, in the language of the chatmate
+  definition: String,
+  /// Sentence of the ai reply the vocab is used in
+  example: String,
+}
+
+/// A new vocab used in an ai reply, along with its details
+#[derive(Debug, Clone, Getters, Constructor, Dissolve)]
+#[dissolve(rename = "into_parts")]
+pub struct NewVocab {
+  /// The vocab, in base or lemma form
+  vocab: String,
+  /// Details of the vocab
+  details: VocabDetails,
 }
@@ -1,15 +1,23 @@
 // All of the following traits are bound by the super traits in order to make them multithread
 // friendly
 
+use tokio::sync::mpsc::Sender;
+use tracing::trace;
+
 use crate::domain::models::{
-  AuthStatus, CefrLevel, ChatMate, ChatMateLanguage, ChatMessage, CreditAuthStatus,
-  EpisAudioMessage, EpisAudioMessageFormat, EpisError, GenerationResponse, Id, LearnedVocabData,
-  RealtimeAiAgentChatContext, SimpleBytes, TextToSpeechResponse, TranscriptionResponse, UserId,
+  AiUsage, AuthStatus, CefrAssessment, CefrLevel, ChatEvent, ChatMate, ChatMateLanguage,
+  ChatMateVoice, ChatMemory, ChatMessage, ChatSummary, Correction, CreditAuthStatus,
+  EmbeddingResponse, EpisAudioMessage, EpisAudioMessageFormat, EpisError, GenerationResponse, Id,
+  LearnedVocabData, LearningGoal, LearningGoalData, LevelChange, LevelChangeStatus,
+  MessageEmbedding, ModerationEvent, ModerationVerdict, NativeLanguage, PastExchange,
+  PlacementProgress, PronunciationScore, RealtimeAiAgentChatContext, RetrievedExchanges,
+  ScenarioId, ScheduledVocab, SimpleBytes, StoredChatMessage, TextToSpeechResponse,
+  TranscriptionResponse, UnsummarizedMessages, UsageReport, UserId, VocabStats, WordPronunciation,
 };
 
 /// Represent a data store for managing any data related to Epis
 pub trait EpisRepository: Clone + Send + Sync + 'static {
-  /// Create a chatmate
+  /// Create a chatmate of a learner with a native language
   ///
   /// # Errors
   /// - If chatmate already created for language, return [EpisError::AlreadyHandshaken]
@@ -18,6 +26,8 @@ pub trait EpisRepository: Clone + Send + Sync + 'static {
     &self,
     user_id: &UserId,
     chatmate_language: &ChatMateLanguage,
+    chatmate_voice: &ChatMateVoice,
+    native_language: &NativeLanguage,
   ) -> impl Future<Output = Result<ChatMate, EpisError>> + Send;
 
   /// Get a user's chatmate by its language, or none if it doesn't exist
@@ -49,7 +59,7 @@ pub trait EpisRepository: Clone + Send + Sync + 'static {
     limit: Option<u8>,
   ) -> impl Future<Output = Result<Vec<ChatMate>, EpisError>> + Send;
 
-  /// Fetch due vocab up to a limit based on an exponential algorithm
+  /// Fetch due vocab up to a limit, ranked by their next review time, i.e. the most overdue first
   ///
   /// # Errors
   /// - If any repo error occurs, return [EpisError::RepoError]
@@ -59,7 +69,26 @@ pub trait EpisRepository: Clone + Send + Sync + 'static {
     limit: Option<u8>,
   ) -> impl Future<Output = Result<Vec<String>, EpisError>> + Send;
 
-  /// Store (upsert) learned vocab
+  /// Fetch all learned vocab of a chatmate
+  ///
+  /// # Errors
+  /// - If any repo error occurs, return [EpisError::RepoError]
+  fn fetch_learned_vocab(
+    &self,
+    chatmate_id: &Id,
+  ) -> impl Future<Output = Result<Vec<String>, EpisError>> + Send;
+
+  /// Get learned vocab among a list of words, along with their review states
+  ///
+  /// # Errors
+  /// - If any repo error occurs, return [EpisError::RepoError]
+  fn get_scheduled_vocab(
+    &self,
+    chatmate_id: &Id,
+    vocab: &[String],
+  ) -> impl Future<Output = Result<Vec<ScheduledVocab>, EpisError>> + Send;
+
+  /// Store (upsert) learned vocab, scheduling their next review based on their review states
   ///
   /// # Errors
   /// - If any repo error occurs, return [EpisError::RepoError]
@@ -79,6 +108,258 @@ pub trait EpisRepository: Clone + Send + Sync + 'static {
     message: &ChatMessage,
   ) -> impl Future<Output = Result<Id, EpisError>> + Send;
 
+  /// Store the corrections of a user message
+  ///
+  /// # Errors
+  /// - If any repo error occurs, return [EpisError::RepoError]
+  fn store_corrections(
+    &self,
+    message_id: &Id,
+    corrections: &[Correction],
+  ) -> impl Future<Output = Result<(), EpisError>> + Send;
+
+  /// Store the pronunciation scores of the words of a user message
+  ///
+  /// # Errors
+  /// - If any repo error occurs, return [EpisError::RepoError]
+  fn store_pronunciation_scores(
+    &self,
+    message_id: &Id,
+    pronunciation_scores: &[PronunciationScore],
+  ) -> impl Future<Output = Result<(), EpisError>> + Send;
+
+  /// Get the words of a chat whose average pronunciation score is below a max score, the worst
+  /// pronounced first, up to a limit
+  ///
+  /// # Errors
+  /// - If any repo error occurs, return [EpisError::RepoError]
+  fn get_pronunciation_report(
+    &self,
+    chatmate_id: &Id,
+    max_score: f32,
+    limit: Option<u8>,
+  ) -> impl Future<Output = Result<Vec<WordPronunciation>, EpisError>> + Send;
+
+  /// Store the ai usage of producing a chat message, adding it to the usage already stored for the
+  /// message, if any
+  ///
+  /// # Errors
+  /// - If any repo error occurs, return [EpisError::RepoError]
+  fn store_usage(
+    &self,
+    message_id: &Id,
+    usage: &AiUsage,
+  ) -> impl Future<Output = Result<(), EpisError>> + Send;
+
+  /// Get the total ai usage of a user across all of their chatmates
+  ///
+  /// # Errors
+  /// - If any repo error occurs, return [EpisError::RepoError]
+  fn get_usage_by_user(
+    &self,
+    user_id: &UserId,
+  ) -> impl Future<Output = Result<UsageReport, EpisError>> + Send;
+
+  /// Store an event of a text flagged by moderation
+  ///
+  /// # Errors
+  /// - If any repo error occurs, return [EpisError::RepoError]
+  fn store_moderation_event(
+    &self,
+    chatmate_id: &Id,
+    moderation_event: &ModerationEvent,
+  ) -> impl Future<Output = Result<(), EpisError>> + Send;
+
+  /// Store an assessment of a placement turn of a chatmate
+  ///
+  /// # Errors
+  /// - If any repo error occurs, return [EpisError::RepoError]
+  fn store_placement_assessment(
+    &self,
+    chatmate_id: &Id,
+    cefr_assessment: &CefrAssessment,
+  ) -> impl Future<Output = Result<(), EpisError>> + Send;
+
+  /// Get the assessed CEFR levels of the placement turns of a chatmate, in ascending order
+  ///
+  /// # Errors
+  /// - If any repo error occurs, return [EpisError::RepoError]
+  fn get_placement_levels(
+    &self,
+    chatmate_id: &Id,
+  ) -> impl Future<Output = Result<Vec<CefrLevel>, EpisError>> + Send;
+
+  /// Count the messages sent by the user in a chat
+  ///
+  /// # Errors
+  /// - If any repo error occurs, return [EpisError::RepoError]
+  fn count_user_messages(
+    &self,
+    chatmate_id: &Id,
+  ) -> impl Future<Output = Result<u64, EpisError>> + Send;
+
+  /// Get stats of the vocab learned in a chat
+  ///
+  /// # Errors
+  /// - If any repo error occurs, return [EpisError::RepoError]
+  fn get_vocab_stats(
+    &self,
+    chatmate_id: &Id,
+  ) -> impl Future<Output = Result<VocabStats, EpisError>> + Send;
+
+  /// Store a proposed change of the CEFR level of a learner in a chat
+  ///
+  /// # Errors
+  /// - If any repo error occurs, return [EpisError::RepoError]
+  fn create_level_change(
+    &self,
+    chatmate_id: &Id,
+    from_level: &CefrLevel,
+    to_level: &CefrLevel,
+    rationale: &str,
+    status: &LevelChangeStatus,
+  ) -> impl Future<Output = Result<LevelChange, EpisError>> + Send;
+
+  /// Get all level changes of a chat, the latest first
+  ///
+  /// # Errors
+  /// - If any repo error occurs, return [EpisError::RepoError]
+  fn get_level_changes(
+    &self,
+    chatmate_id: &Id,
+  ) -> impl Future<Output = Result<Vec<LevelChange>, EpisError>> + Send;
+
+  /// Update the status of a level change
+  ///
+  /// # Errors
+  /// - If any repo error occurs, return [EpisError::RepoError]
+  fn update_level_change_status(
+    &self,
+    level_change_id: &Id,
+    status: &LevelChangeStatus,
+  ) -> impl Future<Output = Result<(), EpisError>> + Send;
+
+  /// Get the learning goals of a chat along with their curriculum, in the order they are created
+  ///
+  /// # Errors
+  /// - If any repo error occurs, return [EpisError::RepoError]
+  fn get_learning_goals(
+    &self,
+    chatmate_id: &Id,
+  ) -> impl Future<Output = Result<Vec<LearningGoal>, EpisError>> + Send;
+
+  /// Create a learning goal of a chat along with its curriculum
+  ///
+  /// # Errors
+  /// - If any repo error occurs, return [EpisError::RepoError]
+  fn create_learning_goal(
+    &self,
+    chatmate_id: &Id,
+    learning_goal_data: &LearningGoalData,
+  ) -> impl Future<Output = Result<LearningGoal, EpisError>> + Send;
+
+  /// Update a learning goal of a chat, replacing its description and curriculum, or return none
+  /// if the chat has no such goal
+  ///
+  /// # Errors
+  /// - If any repo error occurs, return [EpisError::RepoError]
+  fn update_learning_goal(
+    &self,
+    chatmate_id: &Id,
+    learning_goal_id: &Id,
+    learning_goal_data: &LearningGoalData,
+  ) -> impl Future<Output = Result<Option<LearningGoal>, EpisError>> + Send;
+
+  /// Delete a learning goal of a chat along with its curriculum, returning whether it existed
+  ///
+  /// # Errors
+  /// - If any repo error occurs, return [EpisError::RepoError]
+  fn delete_learning_goal(
+    &self,
+    chatmate_id: &Id,
+    learning_goal_id: &Id,
+  ) -> impl Future<Output = Result<bool, EpisError>> + Send;
+
+  /// Get the memory of a chat, which is empty if the chat is not summarized yet
+  ///
+  /// # Errors
+  /// - If any repo error occurs, return [EpisError::RepoError]
+  fn get_chat_memory(
+    &self,
+    chatmate_id: &Id,
+  ) -> impl Future<Output = Result<ChatMemory, EpisError>> + Send;
+
+  /// Get the messages of a chat not yet compressed into its memory, excluding a number of the most
+  /// recent ones, or none if there are no such messages
+  ///
+  /// # Errors
+  /// - If any repo error occurs, return [EpisError::RepoError]
+  fn get_unsummarized_messages(
+    &self,
+    chatmate_id: &Id,
+    recent_messages: u8,
+  ) -> impl Future<Output = Result<Option<UnsummarizedMessages>, EpisError>> + Send;
+
+  /// Store the memory of a chat, which summarizes its messages up to and including a message
+  ///
+  /// # Errors
+  /// - If any repo error occurs, return [EpisError::RepoError]
+  fn store_chat_memory(
+    &self,
+    chatmate_id: &Id,
+    chat_memory: &ChatMemory,
+    summarized_until_message_id: &Id,
+  ) -> impl Future<Output = Result<(), EpisError>> + Send;
+
+  /// Get the messages of a chat not yet embedded by an embedding model, oldest first, up to a limit
+  ///
+  /// # Errors
+  /// - If any repo error occurs, return [EpisError::RepoError]
+  fn get_unembedded_messages(
+    &self,
+    chatmate_id: &Id,
+    model: &str,
+    limit: u8,
+  ) -> impl Future<Output = Result<Vec<StoredChatMessage>, EpisError>> + Send;
+
+  /// Store the embeddings of chat messages by an embedding model
+  ///
+  /// # Errors
+  /// - If any repo error occurs, return [EpisError::RepoError]
+  fn store_message_embeddings(
+    &self,
+    model: &str,
+    message_embeddings: &[MessageEmbedding],
+  ) -> impl Future<Output = Result<(), EpisError>> + Send;
+
+  /// Find the past exchanges of a chat whose user message is the most similar to an embedding by
+  /// an embedding model, most similar first, excluding a number of the most recent messages and the
+  /// exchanges less similar than a min cosine similarity
+  ///
+  /// # Errors
+  /// - If any repo error occurs, return [EpisError::RepoError]
+  fn find_related_exchanges(
+    &self,
+    chatmate_id: &Id,
+    model: &str,
+    embedding: &[f32],
+    recent_messages: u8,
+    min_similarity: f32,
+    limit: u8,
+  ) -> impl Future<Output = Result<Vec<PastExchange>, EpisError>> + Send;
+
+  /// Get the last past exchanges of a chat after a user message, or from the beginning of the chat
+  /// if no message is given, up to a limit, in ascending order
+  ///
+  /// # Errors
+  /// - If any repo error occurs, return [EpisError::RepoError]
+  fn get_past_exchanges(
+    &self,
+    chatmate_id: &Id,
+    after_message_id: Option<&Id>,
+    limit: u16,
+  ) -> impl Future<Output = Result<Vec<PastExchange>, EpisError>> + Send;
+
   /// Get a list of the last previous messages in a chat up to a limit, in ascending order
   ///
   /// # Errors
@@ -92,7 +373,8 @@ pub trait EpisRepository: Clone + Send + Sync + 'static {
 
 /// Core Epis service where main business logic exists
 pub trait Epis: Clone + Send + Sync + 'static {
-  /// Handshake with a chatmate for chat initiation. Handshake consists of:
+  /// Handshake with a chatmate speaking with a voice for chat initiation, explaining and translating
+  /// in the native language of the learner. Handshake consists of:
   /// - Making sure no chatmate with the same language exists
   /// - Storing chatmate
   /// - Returning chatmate
@@ -104,10 +386,13 @@ pub trait Epis: Clone + Send + Sync + 'static {
     &self,
     user_id: &UserId,
     language: &ChatMateLanguage,
+    voice: &ChatMateVoice,
+    native_language: &NativeLanguage,
   ) -> impl Future<Output = Result<ChatMate, EpisError>> + Send;
 
   /// Speech-to-speech chat, connecting a user with a chatmate through a duplex with messages of a
-  /// specific format
+  /// specific format, optionally playing a role-play scenario instead of small talk until the
+  /// learner completes it
   ///
   /// # Errors
   /// - If error is during sending or receiving messages, [EpisError::DuplexError] is returned
@@ -120,6 +405,7 @@ pub trait Epis: Clone + Send + Sync + 'static {
     chatemate_id: &Id,
     duplex: &mut impl AudioDuplex,
     message_format: &EpisAudioMessageFormat,
+    scenario_id: Option<&ScenarioId>,
   ) -> impl Future<Output = Result<(), EpisError>> + Send;
 
   /// List all chatmates for a user
@@ -130,14 +416,129 @@ pub trait Epis: Clone + Send + Sync + 'static {
     &self,
     user_id: &UserId,
   ) -> impl Future<Output = Result<Vec<ChatMate>, EpisError>> + Send;
+
+  /// Get the placement progress of a user with one of their chatmates, or none if the chatmate
+  /// doesn't exist
+  ///
+  /// # Errors
+  /// - If error is related to data store, [EpisError::RepoError] is returned
+  /// - Otherwise [EpisError::Unknown] is returned
+  fn get_placement_progress(
+    &self,
+    user_id: &UserId,
+    chatmate_id: &Id,
+  ) -> impl Future<Output = Result<Option<PlacementProgress>, EpisError>> + Send;
+
+  /// List the CEFR level changes of a user with one of their chatmates, the latest first, or none
+  /// if the chatmate doesn't exist
+  ///
+  /// # Errors
+  /// - If error is related to data store, [EpisError::RepoError] is returned
+  fn list_level_changes(
+    &self,
+    user_id: &UserId,
+    chatmate_id: &Id,
+  ) -> impl Future<Output = Result<Option<Vec<LevelChange>>, EpisError>> + Send;
+
+  /// Confirm or reject a pending CEFR level change of a user with one of their chatmates, applying
+  /// it to the user's CEFR level if confirmed. The resolved change is returned, or none if the
+  /// chatmate or the change doesn't exist.
+  ///
+  /// # Errors
+  /// - If the change is not pending, [EpisError::LevelChangeAlreadyResolved] is returned
+  /// - If error is related to data store, [EpisError::RepoError] is returned
+  /// - Otherwise [EpisError::Unknown] is returned
+  fn resolve_level_change(
+    &self,
+    user_id: &UserId,
+    chatmate_id: &Id,
+    level_change_id: &Id,
+    confirmed: bool,
+  ) -> impl Future<Output = Result<Option<LevelChange>, EpisError>> + Send;
+
+  /// List the learning goals of a user with one of their chatmates, or none if the chatmate doesn't
+  /// exist
+  ///
+  /// # Errors
+  /// - If error is related to data store, [EpisError::RepoError] is returned
+  fn list_learning_goals(
+    &self,
+    user_id: &UserId,
+    chatmate_id: &Id,
+  ) -> impl Future<Output = Result<Option<Vec<LearningGoal>>, EpisError>> + Send;
+
+  /// Create a learning goal of a user with one of their chatmates, or return none if the chatmate
+  /// doesn't exist
+  ///
+  /// # Errors
+  /// - If the goal has no description or a unit has no title, [EpisError::InvalidLearningGoal] is
+  ///   returned
+  /// - If error is related to data store, [EpisError::RepoError] is returned
+  fn create_learning_goal(
+    &self,
+    user_id: &UserId,
+    chatmate_id: &Id,
+    learning_goal_data: LearningGoalData,
+  ) -> impl Future<Output = Result<Option<LearningGoal>, EpisError>> + Send;
+
+  /// Update a learning goal of a user with one of their chatmates, replacing its description and
+  /// curriculum, or return none if the chatmate or the goal doesn't exist
+  ///
+  /// # Errors
+  /// - If the goal has no description or a unit has no title, [EpisError::InvalidLearningGoal] is
+  ///   returned
+  /// - If error is related to data store, [EpisError::RepoError] is returned
+  fn update_learning_goal(
+    &self,
+    user_id: &UserId,
+    chatmate_id: &Id,
+    learning_goal_id: &Id,
+    learning_goal_data: LearningGoalData,
+  ) -> impl Future<Output = Result<Option<LearningGoal>, EpisError>> + Send;
+
+  /// Delete a learning goal of a user with one of their chatmates, or return none if the chatmate
+  /// or the goal doesn't exist
+  ///
+  /// # Errors
+  /// - If error is related to data store, [EpisError::RepoError] is returned
+  fn delete_learning_goal(
+    &self,
+    user_id: &UserId,
+    chatmate_id: &Id,
+    learning_goal_id: &Id,
+  ) -> impl Future<Output = Result<Option<()>, EpisError>> + Send;
+
+  /// Get the pronunciation report of a user with one of their chatmates, i.e. the words they
+  /// mumble or mispronounce the most, or none if the chatmate doesn't exist
+  ///
+  /// # Errors
+  /// - If error is related to data store, [EpisError::RepoError] is returned
+  fn get_pronunciation_report(
+    &self,
+    user_id: &UserId,
+    chatmate_id: &Id,
+  ) -> impl Future<Output = Result<Option<Vec<WordPronunciation>>, EpisError>> + Send;
+
+  /// Get the total ai usage of a user across all of their chatmates
+  ///
+  /// # Errors
+  /// - If error is related to data store, [EpisError::RepoError] is returned
+  fn get_usage(
+    &self,
+    user_id: &UserId,
+  ) -> impl Future<Output = Result<UsageReport, EpisError>> + Send;
 }
 
 /// An implementation-agnostic realtime ai agent, responsible for speech-to-speech generation
 pub trait RealtimeAiAgent: Clone + Send + Sync + 'static {
-  /// Send a message to the agent and receive another one
+  /// Send a message to the agent and receive the reply as ordered audio chunks through a channel,
+  /// each one sent as soon as it's ready. The events of the chat turn, e.g. corrections of the
+  /// message, are returned once the reply is complete.
   ///
   /// # Errors
   /// - If an external provider error occurs, [EpisError::ProviderError] is returned
+  /// - If the generated reply doesn't match the structured output schema,
+  ///   [EpisError::InvalidStructuredOutput] is returned
   /// - If error is related to data store, [EpisError::RepoError] is returned
   /// - If user has run out of credit, [EpisError::NoCredit] is returned
   /// - Otherwise [EpisError::Unknown] is returned
@@ -145,7 +546,8 @@ pub trait RealtimeAiAgent: Clone + Send + Sync + 'static {
     &self,
     audio_message: EpisAudioMessage,
     context: &RealtimeAiAgentChatContext,
-  ) -> impl Future<Output = Result<EpisAudioMessage, EpisError>> + Send;
+    reply_chunks: Sender<EpisAudioMessage>,
+  ) -> impl Future<Output = Result<Vec<ChatEvent>, EpisError>> + Send;
 }
 
 /// A very basic audio duplex, for sending and receiving [SimpleBytes]'s
@@ -166,6 +568,11 @@ pub trait AudioDuplex: Send + Sync + Clone + 'static {
     &mut self,
     audio_message: SimpleBytes,
   ) -> impl Future<Output = Result<(), EpisError>> + Send;
+  /// Send a [ChatEvent] over the duplex, next to the audio
+  ///
+  /// # Errors
+  /// If any error occurs, an [EpisError::DuplexError] is returned
+  fn send_event(&mut self, event: ChatEvent) -> impl Future<Output = Result<(), EpisError>> + Send;
 }
 
 /// User management port for everything related to users (e.g. auth, etc.)
@@ -204,6 +611,54 @@ pub trait UserManagement: Clone + Send + Sync + 'static {
     user_id: &UserId,
     language: &ChatMateLanguage,
   ) -> impl Future<Output = Result<Option<CefrLevel>, EpisError>> + Send;
+
+  /// Set CEFR level of a user for a language
+  ///
+  /// # Errors
+  /// If any error occurs, [EpisError::Unknown] is returned
+  fn set_cefr_level(
+    &self,
+    user_id: &UserId,
+    language: &ChatMateLanguage,
+    cefr_level: &CefrLevel,
+  ) -> impl Future<Output = Result<(), EpisError>> + Send;
+}
+
+/// A moderation service, checking texts for unsafe content
+pub trait ModerationPort: Clone + Send + Sync + 'static {
+  /// Moderate a text
+  ///
+  /// # Errors
+  /// If the moderation provider fails, [EpisError::ProviderError] is returned
+  fn moderate(
+    &self,
+    text: &str,
+  ) -> impl Future<Output = Result<ModerationVerdict, EpisError>> + Send;
+}
+
+/// A retriever of the past exchanges of chats related to a user message, giving the llm a memory of
+/// the messages older than the chat history
+pub trait HistoryRetriever: Clone + Send + Sync + 'static {
+  /// Index the new messages of a chat, so that they can be retrieved
+  ///
+  /// # Errors
+  /// - If indexing needs an ai provider and it fails, [EpisError::ProviderError] is returned
+  /// - If any repo error occurs, [EpisError::RepoError] is returned
+  fn index(&self, chatmate_id: &Id) -> impl Future<Output = Result<(), EpisError>> + Send;
+
+  /// Retrieve the past exchanges of a chat related to a user message, most related first, up to a
+  /// limit, excluding a number of the most recent messages
+  ///
+  /// # Errors
+  /// - If retrieval needs an ai provider and it fails, [EpisError::ProviderError] is returned
+  /// - If any repo error occurs, [EpisError::RepoError] is returned
+  fn retrieve(
+    &self,
+    chatmate_id: &Id,
+    user_message: &str,
+    recent_messages: u8,
+    limit: u8,
+  ) -> impl Future<Output = Result<RetrievedExchanges, EpisError>> + Send;
 }
 
 /// An abstraction over an AI provider which takes and returns structured data
@@ -211,17 +666,84 @@ pub trait AiGateway: Clone + Send + Sync + 'static {
   /// Normal text to text generation
   ///
   /// # Errors
-  /// If any error occurs, [EpisError::ProviderError] is returned
+  /// - If a transient error occurs, e.g. a network error, [EpisError::TransientProviderError] is
+  ///   returned
+  /// - If the output doesn't match the structured output schema even after repairing it,
+  ///   [EpisError::InvalidStructuredOutput] is returned
+  /// - Otherwise [EpisError::ProviderError] is returned
   fn generate(
     &self,
     model: &str,
     messages: &[ChatMessage],
   ) -> impl Future<Output = Result<GenerationResponse, EpisError>> + Send;
 
+  /// Text to text generation, streaming the response text deltas through a channel as they are
+  /// generated. The whole structured response is returned when generation is done.
+  ///
+  /// # Notes
+  /// The default implementation sends the whole response text as a single delta, so providers
+  /// supporting streaming should override it.
+  ///
+  /// # Errors
+  /// - If a transient error occurs, e.g. a network error, [EpisError::TransientProviderError] is
+  ///   returned
+  /// - If the output doesn't match the structured output schema even after repairing it,
+  ///   [EpisError::InvalidStructuredOutput] is returned
+  /// - Otherwise [EpisError::ProviderError] is returned
+  fn generate_stream(
+    &self,
+    model: &str,
+    messages: &[ChatMessage],
+    text_deltas: Sender<String>,
+  ) -> impl Future<Output = Result<GenerationResponse, EpisError>> + Send {
+    async move {
+      let generation_response = self.generate(model, messages).await?;
+      if text_deltas
+        .send(generation_response.text().to_string())
+        .await
+        .is_err()
+      {
+        trace!("Text deltas receiver is dropped");
+      }
+
+      Ok(generation_response)
+    }
+  }
+
+  /// Assess the CEFR level a learner demonstrates in a conversation, as instructed by the messages
+  ///
+  /// # Errors
+  /// - If a transient error occurs, e.g. a network error, [EpisError::TransientProviderError] is
+  ///   returned
+  /// - If the output doesn't match the structured output schema even after repairing it,
+  ///   [EpisError::InvalidStructuredOutput] is returned
+  /// - Otherwise [EpisError::ProviderError] is returned
+  fn assess_cefr_level(
+    &self,
+    model: &str,
+    messages: &[ChatMessage],
+  ) -> impl Future<Output = Result<CefrAssessment, EpisError>> + Send;
+
+  /// Summarize a conversation along with facts about the learner, as instructed by the messages
+  ///
+  /// # Errors
+  /// - If a transient error occurs, e.g. a network error, [EpisError::TransientProviderError] is
+  ///   returned
+  /// - If the output doesn't match the structured output schema even after repairing it,
+  ///   [EpisError::InvalidStructuredOutput] is returned
+  /// - Otherwise [EpisError::ProviderError] is returned
+  fn summarize_chat(
+    &self,
+    model: &str,
+    messages: &[ChatMessage],
+  ) -> impl Future<Output = Result<ChatSummary, EpisError>> + Send;
+
   /// Transcribe audio of a specific format
   ///
   /// # Errors
-  /// If any error occurs, [EpisError::ProviderError] is returned
+  /// - If a transient error occurs, e.g. a network error, [EpisError::TransientProviderError] is
+  ///   returned
+  /// - Otherwise [EpisError::ProviderError] is returned
   fn transcribe(
     &self,
     model: &str,
@@ -230,14 +752,29 @@ pub trait AiGateway: Clone + Send + Sync + 'static {
     instructions: Option<&str>,
   ) -> impl Future<Output = Result<TranscriptionResponse, EpisError>> + Send;
 
-  /// Convert text to speech with optional instructions
+  /// Convert text to speech with a voice and optional instructions, e.g. a speaking style
   ///
   /// # Errors
-  /// If any error occurs, [EpisError::ProviderError] is returned
+  /// - If a transient error occurs, e.g. a network error, [EpisError::TransientProviderError] is
+  ///   returned
+  /// - Otherwise [EpisError::ProviderError] is returned
   fn text_to_speech(
     &self,
     model: &str,
     text: String,
+    voice: &str,
     instructions: Option<&str>,
   ) -> impl Future<Output = Result<TextToSpeechResponse, EpisError>> + Send;
+
+  /// Embed texts into vectors, e.g. for semantic search
+  ///
+  /// # Errors
+  /// - If a transient error occurs, e.g. a network error, [EpisError::TransientProviderError] is
+  ///   returned
+  /// - Otherwise [EpisError::ProviderError] is returned
+  fn embed(
+    &self,
+    model: &str,
+    texts: &[String],
+  ) -> impl Future<Output = Result<EmbeddingResponse, EpisError>> + Send;
 }
@@ -0,0 +1,179 @@
+use tracing::{debug, info, warn};
+
+use crate::domain::{
+  models::{
+    AiUsage, CefrLevel, CefrProgressionMode, ChatMateLanguage, ChatMessage, ChatMessageRole,
+    EpisError, LevelChangeStatus, RealtimeAiAgentChatContext, VocabStats,
+  },
+  placement::{CEFR_LEVELS, language_name},
+  ports::{AiGateway, EpisRepository, UserManagement},
+};
+
+/// Number of user messages between two evaluations of the CEFR level of a learner
+pub const PROGRESSION_INTERVAL_TURNS: u64 = 20;
+/// Number of recent messages of a chat the evaluation is based on
+const PROGRESSION_HISTORY_SIZE: u8 = 40;
+
+/// CEFR level proposed for a learner at a level who is assessed at another level, moving one
+/// level at a time towards the assessed level, or none if the assessed level is the same
+pub fn proposed_level(cefr_level: &CefrLevel, assessed_level: &CefrLevel) -> Option<CefrLevel> {
+  if assessed_level > cefr_level {
+    CEFR_LEVELS
+      .iter()
+      .find(|level| *level > cefr_level)
+      .cloned()
+  } else if assessed_level < cefr_level {
+    CEFR_LEVELS
+      .iter()
+      .rev()
+      .find(|level| *level < cefr_level)
+      .cloned()
+  } else {
+    None
+  }
+}
+
+/// Generate instructions of evaluating the CEFR level of a learner at a level, given their vocab
+/// stats
+fn generate_progression_instructions(
+  language: &ChatMateLanguage,
+  cefr_level: &CefrLevel,
+  vocab_stats: &VocabStats,
+) -> String {
+  let language_name = language_name(language);
+  let learned = vocab_stats.learned();
+  let produced = vocab_stats.produced();
+  let mature = vocab_stats.mature();
+
+  format!(
+    r#"
+# Identity
+
+You are an experienced {language_name} examiner, evaluating the progress of learners in spoken conversations.
+
+# Instructions
+
+- The learner is currently considered to be at {cefr_level} CEFR level. Assess the CEFR level the learner demonstrates in their recent messages, based on range of vocabulary, grammatical accuracy, complexity of sentences and coherence.
+- The messages are transcriptions of speech, so ignore punctuation and capitalization.
+- Messages in another language than {language_name} demonstrate nothing about the level, unless most messages are so, which indicates a lower level.
+- Only assess a level different from {cefr_level} if the messages consistently demonstrate it. A single strong or weak message is not enough.
+- Return the level along with a brief rationale, addressed to the learner.
+
+# Context
+Learned vocab: {learned} words, {produced} of them produced by the learner, {mature} of them well memorized
+"#
+  )
+}
+
+/// Evaluate the CEFR level of a learner periodically, i.e. once in every
+/// [PROGRESSION_INTERVAL_TURNS] user messages, based on their recent messages and vocab stats. If
+/// the learner is assessed at another level, a change of one level is proposed, which is applied
+/// right away or after the learner confirms it, depending on the progression mode. No level is
+/// proposed while another one is pending. The usage of the evaluation is returned, which is empty
+/// if no evaluation is due. The evaluation is due by the stored user messages, so it's run after
+/// the last user message is stored.
+///
+/// # Errors
+/// - If evaluation fails, [EpisError::ProviderError] or [EpisError::InvalidStructuredOutput] is
+///   returned
+/// - If error is related to data store, [EpisError::RepoError] is returned
+/// - If setting the CEFR level fails, [EpisError::Unknown] is returned
+#[allow(clippy::too_many_arguments)]
+pub async fn evaluate_progression(
+  ai_gateway: &impl AiGateway,
+  user_management: &impl UserManagement,
+  epis_repo: &impl EpisRepository,
+  model: &str,
+  context: &RealtimeAiAgentChatContext,
+  language: &ChatMateLanguage,
+  cefr_level: &CefrLevel,
+  progression_mode: &CefrProgressionMode,
+) -> Result<AiUsage, EpisError> {
+  let user_messages = epis_repo
+    .count_user_messages(context.chatmate_id())
+    .await
+    .inspect_err(|error| warn!(%error, "Error while counting user messages"))
+    .map_err(|_| EpisError::RepoError)?;
+  if user_messages % PROGRESSION_INTERVAL_TURNS != 0 {
+    return Ok(AiUsage::default());
+  }
+
+  let level_changes = epis_repo
+    .get_level_changes(context.chatmate_id())
+    .await
+    .inspect_err(|error| warn!(%error, "Error while getting level changes"))
+    .map_err(|_| EpisError::RepoError)?;
+  if level_changes
+    .iter()
+    .any(|level_change| *level_change.status() == LevelChangeStatus::Pending)
+  {
+    debug!("Progression evaluation skipped, as a level change is pending");
+    return Ok(AiUsage::default());
+  }
+
+  let learner_messages = epis_repo
+    .get_chat_message_history(context.chatmate_id(), Some(PROGRESSION_HISTORY_SIZE))
+    .await
+    .inspect_err(|error| warn!(%error, "Error while getting chat message history"))
+    .map_err(|_| EpisError::RepoError)?
+    .into_iter()
+    .filter(|message| matches!(message.role(), ChatMessageRole::User))
+    .map(|message| format!("- {}", message.message()))
+    .collect::<Vec<_>>();
+  let vocab_stats = epis_repo
+    .get_vocab_stats(context.chatmate_id())
+    .await
+    .inspect_err(|error| warn!(%error, "Error while getting vocab stats"))
+    .map_err(|_| EpisError::RepoError)?;
+
+  let evaluation_input = vec![
+    ChatMessage::new(
+      ChatMessageRole::System,
+      generate_progression_instructions(language, cefr_level, &vocab_stats),
+    ),
+    ChatMessage::new(
+      ChatMessageRole::User,
+      format!("Recent learner messages:\n{}", learner_messages.join("\n")),
+    ),
+  ];
+  let (assessed_level, rationale, usage) = ai_gateway
+    .assess_cefr_level(model, &evaluation_input)
+    .await
+    .inspect_err(|error| warn!(%error, "Error during CEFR progression evaluation"))
+    .map_err(|error| match error {
+      EpisError::InvalidStructuredOutput => error,
+      _ => EpisError::ProviderError,
+    })?
+    .into_parts();
+
+  let Some(to_level) = proposed_level(cefr_level, &assessed_level) else {
+    debug!(%cefr_level, "Learner is kept at their CEFR level");
+    return Ok(usage);
+  };
+
+  let status = match progression_mode {
+    CefrProgressionMode::Auto => LevelChangeStatus::Applied,
+    CefrProgressionMode::Confirm => LevelChangeStatus::Pending,
+  };
+  epis_repo
+    .create_level_change(
+      context.chatmate_id(),
+      cefr_level,
+      &to_level,
+      &rationale,
+      &status,
+    )
+    .await
+    .inspect_err(|error| warn!(%error, "Error while storing level change"))
+    .map_err(|_| EpisError::RepoError)?;
+
+  if status == LevelChangeStatus::Applied {
+    user_management
+      .set_cefr_level(context.user_id(), language, &to_level)
+      .await
+      .inspect_err(|error| warn!(%error, "Error while setting user CEFR level"))?;
+  }
+  info!(from_level = %cefr_level, %to_level, ?status, "CEFR level change proposed");
+
+  Ok(usage)
+}
@@ -0,0 +1,123 @@
+use crate::domain::{
+  models::{PronunciationScore, TranscribedWord},
+  vocab_matcher::VocabMatcher,
+};
+
+/// Min similarity of a recognized word to a target vocab, to consider it an attempt at saying the
+/// target vocab
+const MIN_TARGET_SIMILARITY: f32 = 0.6;
+/// Min length of a recognized word, in chars, to compare it to target vocab, as short words are
+/// similar to too many words
+const MIN_TARGET_COMPARISON_LEN: usize = 4;
+/// Max pronunciation score of the words included in pronunciation reports
+pub const PRONUNCIATION_REPORT_MAX_SCORE: f32 = 0.8;
+
+/// A word of the target vocab, i.e. the vocab the learner is likely to say
+#[derive(Debug)]
+struct TargetWord {
+  /// Lowercase word
+  word: String,
+  /// Lemma of the word
+  lemma: String,
+}
+
+/// Levenshtein distance of two words
+fn edit_distance(word: &[char], other_word: &[char]) -> usize {
+  let mut previous_row = (0..=other_word.len()).collect::<Vec<_>>();
+  for (index, char) in word.iter().enumerate() {
+    let mut row = vec![index + 1];
+    for (other_index, other_char) in other_word.iter().enumerate() {
+      let substitution = previous_row[other_index] + usize::from(char != other_char);
+      row.push(
+        substitution
+          .min(previous_row[other_index + 1] + 1)
+          .min(row[other_index] + 1),
+      );
+    }
+    previous_row = row;
+  }
+
+  previous_row[other_word.len()]
+}
+
+/// Similarity of two words from 0 to 1, based on their edit distance relative to their length
+fn similarity(word: &str, other_word: &str) -> f32 {
+  let word = word.chars().collect::<Vec<_>>();
+  let other_word = other_word.chars().collect::<Vec<_>>();
+  let max_len = word.len().max(other_word.len());
+  if max_len == 0 {
+    return 0.0;
+  }
+
+  1.0 - edit_distance(&word, &other_word) as f32 / max_len as f32
+}
+
+/// Score the pronunciation of the words of a user message, comparing the recognized words against
+/// the target vocab the learner most likely intended to say.
+///
+/// A word is scored by how confidently it's recognized. A recognized word which is not a target
+/// vocab, but is similar to one, is considered a mispronounced attempt at it (e.g. "charler" for
+/// "charlar"), and its score is lowered by how far it is from the target.
+pub fn score_pronunciation(
+  vocab_matcher: &VocabMatcher,
+  words: &[TranscribedWord],
+  target_vocab: &[String],
+) -> Vec<PronunciationScore> {
+  let target_words = target_vocab
+    .iter()
+    .flat_map(|vocab| vocab.split_whitespace())
+    .map(|word| TargetWord {
+      word: word.to_lowercase(),
+      lemma: vocab_matcher.lemmatize(word),
+    })
+    .collect::<Vec<_>>();
+
+  words
+    .iter()
+    .filter_map(|transcribed_word| {
+      let word = transcribed_word
+        .word()
+        .trim_matches(|char: char| !char.is_alphanumeric())
+        .to_lowercase();
+      if word.is_empty() {
+        return None;
+      }
+      let lemma = vocab_matcher.lemmatize(&word);
+      let confidence = transcribed_word.confidence().clamp(0.0, 1.0);
+
+      let (intended, score) = if let Some(target_word) = target_words
+        .iter()
+        .find(|target_word| target_word.lemma == lemma)
+      {
+        (Some(target_word.word.clone()), confidence)
+      } else {
+        let closest_target = target_words
+          .iter()
+          .filter(|_| word.chars().count() >= MIN_TARGET_COMPARISON_LEN)
+          .map(|target_word| {
+            let target_similarity =
+              similarity(&word, &target_word.word).max(similarity(&lemma, &target_word.lemma));
+            (target_word, target_similarity)
+          })
+          .filter(|(_, target_similarity)| *target_similarity >= MIN_TARGET_SIMILARITY)
+          .max_by(|(_, similarity), (_, other_similarity)| similarity.total_cmp(other_similarity));
+
+        match closest_target {
+          Some((target_word, target_similarity)) => (
+            Some(target_word.word.clone()),
+            confidence * target_similarity,
+          ),
+          None => (None, confidence),
+        }
+      };
+
+      Some(PronunciationScore::new(
+        word,
+        intended,
+        score,
+        *transcribed_word.start(),
+        *transcribed_word.end(),
+      ))
+    })
+    .collect()
+}
@@ -0,0 +1,199 @@
+use std::sync::Arc;
+
+use derive_more::Constructor;
+use tracing::{debug, warn};
+
+use crate::domain::{
+  models::{
+    ChatMessage, ChatMessageRole, EpisError, Id, MessageEmbedding, PastExchange, RetrievedExchanges,
+  },
+  ports::{AiGateway, EpisRepository, HistoryRetriever},
+};
+
+/// Max number of past exchanges recalled for a user message
+pub const RECALL_LIMIT: u8 = 3;
+/// Max number of messages embedded at once
+const EMBEDDING_BATCH_SIZE: u8 = 32;
+/// Min cosine similarity of a past exchange to the user message to be recalled
+const MIN_RECALL_SIMILARITY: f32 = 0.3;
+
+/// Embed the messages of a chat not yet embedded by an embedding model, including the older
+/// messages of the chat stored before the model was configured. The usage of the embedding is
+/// accounted to the last embedded message.
+///
+/// # Errors
+/// - If embedding fails, [EpisError::ProviderError] is returned
+/// - If error is related to data store, [EpisError::RepoError] is returned
+pub async fn embed_messages(
+  ai_gateway: &impl AiGateway,
+  epis_repo: &impl EpisRepository,
+  model: &str,
+  chatmate_id: &Id,
+) -> Result<(), EpisError> {
+  let unembedded_messages = epis_repo
+    .get_unembedded_messages(chatmate_id, model, EMBEDDING_BATCH_SIZE)
+    .await
+    .inspect_err(|error| warn!(%error, "Error while getting unembedded messages"))
+    .map_err(|_| EpisError::RepoError)?;
+  let Some(last_message) = unembedded_messages.last() else {
+    return Ok(());
+  };
+  let last_message_id = last_message.id().clone();
+
+  let (message_ids, texts): (Vec<_>, Vec<_>) = unembedded_messages
+    .into_iter()
+    .map(|unembedded_message| {
+      let (id, chat_message) = unembedded_message.into_parts();
+      (id, chat_message.message().clone())
+    })
+    .unzip();
+  let (embeddings, usage) = ai_gateway
+    .embed(model, &texts)
+    .await
+    .inspect_err(|error| warn!(%error, "Error during message embedding"))
+    .map_err(|_| EpisError::ProviderError)?
+    .into_parts();
+  if embeddings.len() != message_ids.len() {
+    warn!(
+      messages = message_ids.len(),
+      embeddings = embeddings.len(),
+      "Number of embeddings doesn't match the number of messages"
+    );
+    return Err(EpisError::ProviderError);
+  }
+
+  let message_embeddings = message_ids
+    .into_iter()
+    .zip(embeddings)
+    .map(|(message_id, embedding)| MessageEmbedding::new(message_id, embedding))
+    .collect::<Vec<_>>();
+  epis_repo
+    .store_message_embeddings(model, &message_embeddings)
+    .await
+    .inspect_err(|error| warn!(%error, "Error while storing message embeddings"))
+    .map_err(|_| EpisError::RepoError)?;
+  epis_repo
+    .store_usage(&last_message_id, &usage)
+    .await
+    .inspect_err(|error| warn!(%error, "Error while storing message embedding usage"))
+    .map_err(|_| EpisError::RepoError)?;
+  debug!(
+    embedded_messages = message_embeddings.len(),
+    "Messages embedded"
+  );
+
+  Ok(())
+}
+
+/// Recall the past exchanges of a chat semantically related to a user message, excluding a number
+/// of the most recent messages, along with the usage of embedding the user message
+///
+/// # Errors
+/// - If embedding fails, [EpisError::ProviderError] is returned
+/// - If error is related to data store, [EpisError::RepoError] is returned
+pub async fn recall_exchanges(
+  ai_gateway: &impl AiGateway,
+  epis_repo: &impl EpisRepository,
+  model: &str,
+  chatmate_id: &Id,
+  user_message: &str,
+  recent_messages: u8,
+  limit: u8,
+) -> Result<RetrievedExchanges, EpisError> {
+  let (embeddings, usage) = ai_gateway
+    .embed(model, &[user_message.to_string()])
+    .await
+    .inspect_err(|error| warn!(%error, "Error during user message embedding"))
+    .map_err(|_| EpisError::ProviderError)?
+    .into_parts();
+  let Some(embedding) = embeddings.first() else {
+    warn!("No embedding is returned for the user message");
+    return Err(EpisError::ProviderError);
+  };
+
+  let past_exchanges = epis_repo
+    .find_related_exchanges(
+      chatmate_id,
+      model,
+      embedding,
+      recent_messages,
+      MIN_RECALL_SIMILARITY,
+      limit,
+    )
+    .await
+    .inspect_err(|error| warn!(%error, "Error while finding related exchanges"))
+    .map_err(|_| EpisError::RepoError)?;
+  debug!(
+    recalled_exchanges = past_exchanges.len(),
+    "Past exchanges recalled"
+  );
+
+  Ok(RetrievedExchanges::new(past_exchanges, usage))
+}
+
+/// A [HistoryRetriever] recalling semantically related past exchanges via the embeddings of the
+/// messages by an embedding model
+#[derive(Debug, Clone, Constructor)]
+pub struct EmbeddingHistoryRetriever<AG: AiGateway, ER: EpisRepository> {
+  /// Ai gateway, for embedding
+  ai_gateway: Arc<AG>,
+  /// Epis repository, for messages and their embeddings
+  epis_repo: Arc<ER>,
+  /// Embedding model
+  model: String,
+}
+
+impl<AG: AiGateway, ER: EpisRepository> HistoryRetriever for EmbeddingHistoryRetriever<AG, ER> {
+  async fn index(&self, chatmate_id: &Id) -> Result<(), EpisError> {
+    embed_messages(
+      self.ai_gateway.as_ref(),
+      self.epis_repo.as_ref(),
+      &self.model,
+      chatmate_id,
+    )
+    .await
+  }
+
+  async fn retrieve(
+    &self,
+    chatmate_id: &Id,
+    user_message: &str,
+    recent_messages: u8,
+    limit: u8,
+  ) -> Result<RetrievedExchanges, EpisError> {
+    recall_exchanges(
+      self.ai_gateway.as_ref(),
+      self.epis_repo.as_ref(),
+      &self.model,
+      chatmate_id,
+      user_message,
+      recent_messages,
+      limit,
+    )
+    .await
+  }
+}
+
+/// Generate a system message presenting recalled past exchanges to the llm, or none if there is no
+/// recalled exchange
+pub fn generate_recall_message(past_exchanges: &[PastExchange]) -> Option<ChatMessage> {
+  if past_exchanges.is_empty() {
+    return None;
+  }
+
+  let past_exchanges = past_exchanges
+    .iter()
+    .map(|past_exchange| match past_exchange.ai_reply() {
+      Some(ai_reply) => format!("User: {}\nYou: {ai_reply}", past_exchange.user_message()),
+      None => format!("User: {}", past_exchange.user_message()),
+    })
+    .collect::<Vec<_>>()
+    .join("\n\n");
+
+  Some(ChatMessage::new(
+    ChatMessageRole::System,
+    format!(
+      "Earlier exchanges with the user related to their last message, which you may bring up if it fits naturally:\n\n{past_exchanges}"
+    ),
+  ))
+}
@@ -0,0 +1,325 @@
+use crate::domain::models::{ReviewGrade, ReviewState};
+
+/// A spaced repetition algorithm, scheduling the reviews of learned words
+pub trait ReviewScheduler: Clone + Send + Sync + 'static {
+  /// Review state of a newly learned word, i.e. after its first successful use
+  fn initial_state(&self) -> ReviewState;
+
+  /// Schedule the next review of a word after reviewing it with a grade, some days after its last
+  /// review
+  fn schedule(
+    &self,
+    review_state: &ReviewState,
+    grade: ReviewGrade,
+    elapsed_days: f32,
+  ) -> ReviewState;
+}
+
+/// Initial SM-2 easiness factor
+const SM2_INITIAL_EASE: f32 = 2.5;
+/// Min SM-2 easiness factor, so that intervals never stop growing
+const SM2_MIN_EASE: f32 = 1.3;
+/// Interval after the second successful review in SM-2, in days
+const SM2_SECOND_INTERVAL_DAYS: f32 = 6.0;
+
+/// SuperMemo 2 scheduler, growing intervals by a per word easiness factor. A successful review
+/// before the word is due, e.g. using it again in the same session, doesn't advance its schedule,
+/// as SM-2 doesn't account for early reviews.
+#[derive(Debug, Clone, Default)]
+pub struct Sm2Scheduler;
+
+impl Sm2Scheduler {
+  /// SM-2 quality of response, from 0 to 5, of a grade
+  fn quality(grade: &ReviewGrade) -> f32 {
+    match grade {
+      ReviewGrade::Again => 1.0,
+      ReviewGrade::Good => 4.0,
+      ReviewGrade::Easy => 5.0,
+    }
+  }
+}
+
+impl ReviewScheduler for Sm2Scheduler {
+  fn initial_state(&self) -> ReviewState {
+    // The FSRS part of the state is kept at its defaults, so that the scheduler can be switched
+    ReviewState::new(SM2_INITIAL_EASE, 1.0, 5.0, 1.0, 1)
+  }
+
+  fn schedule(
+    &self,
+    review_state: &ReviewState,
+    grade: ReviewGrade,
+    elapsed_days: f32,
+  ) -> ReviewState {
+    if !matches!(grade, ReviewGrade::Again) && elapsed_days < *review_state.interval_days() {
+      return review_state.clone();
+    }
+
+    let quality = Self::quality(&grade);
+    let ease = (review_state.ease() + (0.1 - (5.0 - quality) * (0.08 + (5.0 - quality) * 0.02)))
+      .max(SM2_MIN_EASE);
+
+    let (interval_days, repetitions) = match grade {
+      ReviewGrade::Again => (1.0, 0),
+      ReviewGrade::Good | ReviewGrade::Easy => match review_state.repetitions() {
+        0 => (1.0, 1),
+        1 => (SM2_SECOND_INTERVAL_DAYS, 2),
+        repetitions => (
+          (review_state.interval_days() * review_state.ease()).round(),
+          repetitions + 1,
+        ),
+      },
+    };
+
+    ReviewState::new(
+      ease,
+      *review_state.stability(),
+      *review_state.difficulty(),
+      interval_days,
+      repetitions,
+    )
+  }
+}
+
+/// Default FSRS-4.5 model weights
+const FSRS_WEIGHTS: [f32; 17] = [
+  0.4872, 1.4003, 3.7145, 13.8206, 5.1618, 1.2298, 0.8975, 0.031, 1.6474, 0.1367, 1.0461, 2.1072,
+  0.0793, 0.3246, 1.587, 0.2272, 2.8755,
+];
+/// Decay of the FSRS forgetting curve
+const FSRS_DECAY: f32 = -0.5;
+/// Factor of the FSRS forgetting curve, so that recall probability is 90% after stability days
+const FSRS_FACTOR: f32 = 19.0 / 81.0;
+/// Recall probability at which FSRS schedules the next review
+const FSRS_DESIRED_RETENTION: f32 = 0.9;
+/// Min FSRS stability, in days
+const FSRS_MIN_STABILITY: f32 = 0.1;
+
+/// Free Spaced Repetition Scheduler (FSRS-4.5), modeling the memory of each word by its
+/// stability and difficulty, and scheduling its review when its recall probability drops to the
+/// desired retention
+#[derive(Debug, Clone, Default)]
+pub struct FsrsScheduler;
+
+impl FsrsScheduler {
+  /// FSRS rating, from 1 to 4, of a grade
+  fn rating(grade: &ReviewGrade) -> f32 {
+    match grade {
+      ReviewGrade::Again => 1.0,
+      ReviewGrade::Good => 3.0,
+      ReviewGrade::Easy => 4.0,
+    }
+  }
+
+  /// Initial difficulty of a word first reviewed with a rating
+  fn initial_difficulty(rating: f32) -> f32 {
+    (FSRS_WEIGHTS[4] - (rating - 3.0) * FSRS_WEIGHTS[5]).clamp(1.0, 10.0)
+  }
+
+  /// Probability of recalling a word with a stability after some days
+  fn retrievability(stability: f32, elapsed_days: f32) -> f32 {
+    (1.0 + FSRS_FACTOR * elapsed_days / stability).powf(FSRS_DECAY)
+  }
+
+  /// Days after which recall probability of a word with a stability drops to the desired
+  /// retention
+  fn interval_days(stability: f32) -> f32 {
+    (stability / FSRS_FACTOR * (FSRS_DESIRED_RETENTION.powf(1.0 / FSRS_DECAY) - 1.0))
+      .round()
+      .max(1.0)
+  }
+}
+
+impl ReviewScheduler for FsrsScheduler {
+  fn initial_state(&self) -> ReviewState {
+    let rating = Self::rating(&ReviewGrade::Good);
+    let stability = FSRS_WEIGHTS[rating as usize - 1];
+
+    ReviewState::new(
+      SM2_INITIAL_EASE,
+      stability,
+      Self::initial_difficulty(rating),
+      Self::interval_days(stability),
+      1,
+    )
+  }
+
+  fn schedule(
+    &self,
+    review_state: &ReviewState,
+    grade: ReviewGrade,
+    elapsed_days: f32,
+  ) -> ReviewState {
+    let rating = Self::rating(&grade);
+    let stability = review_state.stability().max(FSRS_MIN_STABILITY);
+    let difficulty = review_state.difficulty().clamp(1.0, 10.0);
+    let retrievability = Self::retrievability(stability, elapsed_days.max(0.0));
+
+    let next_stability = match grade {
+      ReviewGrade::Again => {
+        FSRS_WEIGHTS[11]
+          * difficulty.powf(-FSRS_WEIGHTS[12])
+          * ((stability + 1.0).powf(FSRS_WEIGHTS[13]) - 1.0)
+          * (FSRS_WEIGHTS[14] * (1.0 - retrievability)).exp()
+      }
+      ReviewGrade::Good | ReviewGrade::Easy => {
+        let easy_bonus = match grade {
+          ReviewGrade::Easy => FSRS_WEIGHTS[16],
+          _ => 1.0,
+        };
+        stability
+          * (FSRS_WEIGHTS[8].exp()
+            * (11.0 - difficulty)
+            * stability.powf(-FSRS_WEIGHTS[9])
+            * ((FSRS_WEIGHTS[10] * (1.0 - retrievability)).exp() - 1.0)
+            * easy_bonus
+            + 1.0)
+      }
+    }
+    .max(FSRS_MIN_STABILITY);
+
+    // Difficulty moves with the rating, and reverts to the initial difficulty of a good rating
+    let next_difficulty = (FSRS_WEIGHTS[7] * Self::initial_difficulty(3.0)
+      + (1.0 - FSRS_WEIGHTS[7]) * (difficulty - FSRS_WEIGHTS[6] * (rating - 3.0)))
+      .clamp(1.0, 10.0);
+
+    let repetitions = match grade {
+      ReviewGrade::Again => 0,
+      ReviewGrade::Good | ReviewGrade::Easy => review_state.repetitions() + 1,
+    };
+
+    ReviewState::new(
+      *review_state.ease(),
+      next_stability,
+      next_difficulty,
+      Self::interval_days(next_stability),
+      repetitions,
+    )
+  }
+}
+
+/// A [ReviewScheduler] backed by any of the supported algorithms
+#[derive(Debug, Clone)]
+#[allow(clippy::missing_docs_in_private_items)]
+pub enum AnyReviewScheduler {
+  Sm2(Sm2Scheduler),
+  Fsrs(FsrsScheduler),
+}
+
+impl ReviewScheduler for AnyReviewScheduler {
+  fn initial_state(&self) -> ReviewState {
+    match self {
+      Self::Sm2(scheduler) => scheduler.initial_state(),
+      Self::Fsrs(scheduler) => scheduler.initial_state(),
+    }
+  }
+
+  fn schedule(
+    &self,
+    review_state: &ReviewState,
+    grade: ReviewGrade,
+    elapsed_days: f32,
+  ) -> ReviewState {
+    match self {
+      Self::Sm2(scheduler) => scheduler.schedule(review_state, grade, elapsed_days),
+      Self::Fsrs(scheduler) => scheduler.schedule(review_state, grade, elapsed_days),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// Review a word with a grade on its due day
+  fn review(
+    scheduler: &impl ReviewScheduler,
+    review_state: &ReviewState,
+    grade: ReviewGrade,
+  ) -> ReviewState {
+    scheduler.schedule(review_state, grade, *review_state.interval_days())
+  }
+
+  /// SM-2 intervals grow on successful reviews, and faster on easy ones
+  #[test]
+  fn sm2_intervals_grow_on_good_and_easy() {
+    let scheduler = Sm2Scheduler;
+    let first = review(&scheduler, &scheduler.initial_state(), ReviewGrade::Good);
+    let good = review(&scheduler, &first, ReviewGrade::Good);
+    let easy = review(&scheduler, &first, ReviewGrade::Easy);
+
+    assert_eq!(*first.interval_days(), SM2_SECOND_INTERVAL_DAYS);
+    assert!(good.interval_days() > first.interval_days());
+    assert!(easy.ease() > good.ease());
+    assert!(
+      review(&scheduler, &easy, ReviewGrade::Good).interval_days()
+        > review(&scheduler, &good, ReviewGrade::Good).interval_days()
+    );
+    assert_eq!(*good.repetitions(), 3);
+  }
+
+  /// SM-2 intervals reset to a day on failed reviews, and the word gets harder
+  #[test]
+  fn sm2_intervals_reset_on_again() {
+    let scheduler = Sm2Scheduler;
+    let first = review(&scheduler, &scheduler.initial_state(), ReviewGrade::Good);
+    let good = review(&scheduler, &first, ReviewGrade::Good);
+    let again = review(&scheduler, &good, ReviewGrade::Again);
+
+    assert_eq!(*again.interval_days(), 1.0);
+    assert_eq!(*again.repetitions(), 0);
+    assert!(again.ease() < good.ease());
+    assert_eq!(
+      *review(&scheduler, &again, ReviewGrade::Good).interval_days(),
+      1.0
+    );
+  }
+
+  /// SM-2 intervals don't compound on successful reviews before the word is due
+  #[test]
+  fn sm2_intervals_do_not_grow_on_early_reviews() {
+    let scheduler = Sm2Scheduler;
+    let first = review(&scheduler, &scheduler.initial_state(), ReviewGrade::Good);
+    let second = review(&scheduler, &first, ReviewGrade::Good);
+
+    let produced_once = scheduler.schedule(&second, ReviewGrade::Easy, 0.1);
+    let produced_twice = scheduler.schedule(&produced_once, ReviewGrade::Easy, 0.2);
+
+    assert_eq!(produced_twice.interval_days(), second.interval_days());
+    assert_eq!(produced_twice.repetitions(), second.repetitions());
+    assert_eq!(produced_twice.ease(), second.ease());
+    assert_eq!(
+      *scheduler
+        .schedule(&produced_twice, ReviewGrade::Again, 0.3)
+        .interval_days(),
+      1.0
+    );
+  }
+
+  /// FSRS intervals grow on successful reviews, and faster on easy ones
+  #[test]
+  fn fsrs_intervals_grow_on_good_and_easy() {
+    let scheduler = FsrsScheduler;
+    let initial = scheduler.initial_state();
+    let good = review(&scheduler, &initial, ReviewGrade::Good);
+    let easy = review(&scheduler, &initial, ReviewGrade::Easy);
+
+    assert!(good.interval_days() > initial.interval_days());
+    assert!(easy.interval_days() > good.interval_days());
+    assert!(review(&scheduler, &good, ReviewGrade::Good).interval_days() > good.interval_days());
+    assert_eq!(*good.repetitions(), 2);
+  }
+
+  /// FSRS intervals drop on failed reviews, and the word gets harder
+  #[test]
+  fn fsrs_intervals_reset_on_again() {
+    let scheduler = FsrsScheduler;
+    let good = review(&scheduler, &scheduler.initial_state(), ReviewGrade::Good);
+    let again = review(&scheduler, &good, ReviewGrade::Again);
+
+    assert!(again.stability() < good.stability());
+    assert!(again.interval_days() < good.interval_days());
+    assert!(again.difficulty() > good.difficulty());
+    assert_eq!(*again.repetitions(), 0);
+  }
+}
@@ -0,0 +1,143 @@
+use tracing::debug;
+
+use crate::domain::models::{Scenario, ScenarioAssessment, ScenarioId, ScenarioProgress};
+
+/// Convert a list of string literals into owned strings
+fn owned(strs: &[&str]) -> Vec<String> {
+  strs.iter().map(|str| str.to_string()).collect()
+}
+
+/// Get the definition of a role-play scenario
+pub fn get_scenario(scenario_id: &ScenarioId) -> Scenario {
+  match scenario_id {
+    ScenarioId::Cafe => Scenario::new(
+      ScenarioId::Cafe,
+      "A busy café. You are the barista behind the counter, and the user is a customer.".to_string(),
+      owned(&[
+        "Greet the barista",
+        "Order a drink and something to eat",
+        "Ask about the price or an ingredient of something on the menu",
+        "Pay and say goodbye",
+      ]),
+      owned(&[
+        "menu", "order", "price", "bill", "pay", "cup", "sugar", "take away",
+      ]),
+      "The user has ordered and paid for their order.".to_string(),
+    ),
+    ScenarioId::Interview => Scenario::new(
+      ScenarioId::Interview,
+      "An office. You are interviewing the user for a job of their choice, and the user is the candidate."
+        .to_string(),
+      owned(&[
+        "Introduce themselves",
+        "Describe their experience or studies",
+        "Name one of their strengths and one of their weaknesses",
+        "Ask a question about the job",
+      ]),
+      owned(&[
+        "experience", "skill", "strength", "weakness", "team", "salary", "apply", "responsible",
+      ]),
+      "The user has answered the interview questions and asked about the job.".to_string(),
+    ),
+    ScenarioId::Doctor => Scenario::new(
+      ScenarioId::Doctor,
+      "A clinic. You are a doctor, and the user is a patient who feels unwell.".to_string(),
+      owned(&[
+        "Describe their symptoms",
+        "Say since when they have been feeling unwell",
+        "Answer a question about their allergies or medicine",
+        "Repeat the treatment you advise in their own words",
+      ]),
+      owned(&[
+        "pain", "fever", "cough", "headache", "allergy", "medicine", "prescription", "rest",
+      ]),
+      "The user has described their symptoms and understood the treatment.".to_string(),
+    ),
+  }
+}
+
+/// Generate the instructions of a role-play scenario, folded into the chat instructions so that the
+/// chatmate plays its role instead of small talk
+pub fn generate_scenario_instructions(scenario_progress: &ScenarioProgress) -> String {
+  let scenario = scenario_progress.scenario();
+  let goals = scenario
+    .goals()
+    .iter()
+    .enumerate()
+    .map(|(index, goal)| {
+      let status = if scenario_progress.completed_goals().contains(goal) {
+        "completed"
+      } else {
+        "not completed"
+      };
+      format!("{}. {goal} ({status})", index + 1)
+    })
+    .collect::<Vec<_>>()
+    .join("\n");
+  let target_vocab = scenario.target_vocab().join(", ");
+
+  format!(
+    r#"
+# Role-play scenario
+
+Instead of small talk, play the following scenario with the user. Stay in your role, lead the scenario step by step, and gently steer the user back if they go off-topic.
+
+Setting: {}
+
+Goals of the user:
+{goals}
+
+Target vocab, to be used in the language the user learns: {target_vocab}
+
+Success condition: {}
+
+- Use the target vocab naturally, and prompt the user to use it.
+- Return the numbers of the goals the user completed in their last message, and whether the success condition is met considering the whole conversation.
+- Once the success condition is met, wrap up the scenario in your answer.
+"#,
+    scenario.setting(),
+    scenario.success_condition(),
+  )
+}
+
+/// Advance the progress of a learner in a role-play scenario by the assessment of their last
+/// message, returning the new progress if any goal is newly completed or the scenario is completed,
+/// or none otherwise
+pub fn advance_scenario(
+  scenario_progress: &ScenarioProgress,
+  scenario_assessment: &ScenarioAssessment,
+) -> Option<ScenarioProgress> {
+  let scenario = scenario_progress.scenario();
+  let mut completed_goals = scenario_progress.completed_goals().clone();
+  // Goals are numbered from 1 in the instructions, and unknown numbers are ignored
+  for goal_number in scenario_assessment.completed_goals() {
+    if let Some(goal) = (*goal_number as usize)
+      .checked_sub(1)
+      .and_then(|index| scenario.goals().get(index))
+      && !completed_goals.contains(goal)
+    {
+      completed_goals.push(goal.clone());
+    }
+  }
+  // A scenario is completed by meeting its success condition, or by completing all of its goals
+  let completed =
+    *scenario_assessment.succeeded() || completed_goals.len() == scenario.goals().len();
+
+  if completed_goals.len() == scenario_progress.completed_goals().len()
+    && completed == *scenario_progress.completed()
+  {
+    return None;
+  }
+  debug!(
+    scenario_id = %scenario.id(),
+    completed_goals = completed_goals.len(),
+    completed,
+    "Scenario advanced"
+  );
+
+  Some(ScenarioProgress::new(
+    scenario.clone(),
+    completed_goals,
+    completed,
+  ))
+}
@@ -0,0 +1,531 @@
+use derive_getters::Getters;
+use derive_more::Constructor;
+
+use crate::domain::models::{ChatMateLanguage, VocabMatch};
+
+/// Min number of chars an English stem keeps after stripping a suffix
+const EN_MIN_STEM_CHARS: usize = 2;
+/// Min number of chars a Spanish stem keeps after stripping a suffix
+const ES_MIN_STEM_CHARS: usize = 3;
+/// Min number of chars a Spanish stem keeps after stripping a nominal suffix, as shorter words
+/// ending with one are mostly uninflected, e.g. "mesa" or "solo"
+const ES_MIN_NOMINAL_STEM_CHARS: usize = 4;
+/// Min number of chars a Turkish stem keeps after stripping a suffix
+const TR_MIN_STEM_CHARS: usize = 2;
+
+/// Irregular English forms along with their lemmas, as they cannot be stemmed by rules
+const EN_IRREGULAR_FORMS: [(&str, &str); 24] = [
+  ("am", "be"),
+  ("is", "be"),
+  ("are", "be"),
+  ("was", "be"),
+  ("were", "be"),
+  ("been", "be"),
+  ("has", "have"),
+  ("had", "have"),
+  ("does", "do"),
+  ("did", "do"),
+  ("done", "do"),
+  ("goes", "go"),
+  ("went", "go"),
+  ("gone", "go"),
+  ("made", "make"),
+  ("said", "say"),
+  ("ran", "run"),
+  ("came", "come"),
+  ("got", "get"),
+  ("took", "take"),
+  ("saw", "see"),
+  ("knew", "know"),
+  ("thought", "think"),
+  ("felt", "feel"),
+];
+
+/// Spanish inflectional suffixes of verbs, nouns and adjectives, without accents, longest first
+const ES_SUFFIXES: [&str; 72] = [
+  "ariamos", "eriamos", "iriamos", "ieramos", "iesemos", "aremos", "eremos", "iremos", "abamos",
+  "aramos", "asemos", "ariais", "ierais", "iendo", "yendo", "arian", "erian", "irian", "ieron",
+  "ieran", "iesen", "arias", "erias", "irias", "abais", "arais", "aseis", "amos", "emos", "imos",
+  "ando", "aron", "aban", "aran", "asen", "aria", "eria", "iria", "aras", "abas", "ados", "adas",
+  "idos", "idas", "aste", "iste", "ais", "eis", "aba", "ara", "ase", "ado", "ada", "ido", "ida",
+  "ian", "ias", "ar", "er", "ir", "an", "en", "as", "es", "os", "io", "ia", "a", "e", "o", "i",
+  "s",
+];
+
+/// Spanish suffixes of nouns and adjectives, which are also verb suffixes
+const ES_NOMINAL_SUFFIXES: [&str; 7] = ["as", "os", "es", "a", "o", "e", "s"];
+
+/// Turkish inflectional suffixes of verbs and nouns in all of their vowel harmony and buffer
+/// consonant variants, longest first. Stacked suffixes are stripped one per pass, each one only if
+/// it fits the stem it's attached to.
+const TR_SUFFIXES: [&str; 141] = [
+  "sınız", "siniz", "sunuz", "sünüz", "ecek", "acak", "iyor", "ıyor", "uyor", "üyor", "ımız",
+  "imiz", "umuz", "ümüz", "ınız", "iniz", "unuz", "ünüz", "ndan", "nden", "ları", "leri", "yor",
+  "mış", "miş", "muş", "müş", "mak", "mek", "dım", "dim", "dum", "düm", "tım", "tim", "tum", "tüm",
+  "dık", "dik", "duk", "dük", "tık", "tik", "tuk", "tük", "dın", "din", "dun", "dün", "tın", "tin",
+  "tun", "tün", "ler", "lar", "den", "dan", "ten", "tan", "nin", "nın", "nun", "nün", "yle", "yla",
+  "dır", "dir", "dur", "dür", "tır", "tir", "tur", "tür", "miz", "mız", "muz", "müz", "niz", "nız",
+  "nuz", "nüz", "sin", "sın", "sun", "sün", "nda", "nde", "yım", "yim", "yum", "yüm", "dı", "di",
+  "du", "dü", "tı", "ti", "tu", "tü", "de", "da", "te", "ta", "ye", "ya", "yi", "yı", "yu", "yü",
+  "le", "la", "in", "ın", "un", "ün", "si", "sı", "su", "sü", "im", "ım", "um", "üm", "na", "ne",
+  "nı", "ni", "nu", "nü", "ız", "iz", "uz", "üz", "i", "ı", "u", "ü", "e", "a", "m", "n",
+];
+
+/// English phrases of asking the meaning of a word, e.g. "what does it mean". Each marker is a list
+/// of phrases which all must be present in a text.
+const EN_MEANING_REQUEST_MARKERS: [&[&str]; 4] = [
+  &["what", "mean"],
+  &["meaning of"],
+  &["definition"],
+  &["translate"],
+];
+/// Spanish phrases of asking the meaning of a word, e.g. "qué significa"
+const ES_MEANING_REQUEST_MARKERS: [&[&str]; 3] =
+  [&["significado"], &["quiere decir"], &["traducir"]];
+/// Turkish phrases of asking the meaning of a word, e.g. "ne demek"
+const TR_MEANING_REQUEST_MARKERS: [&[&str]; 3] = [&["ne demek"], &["anlamı"], &["çevir"]];
+
+/// A word of a text represented by its lemma and its byte range in the text
+#[derive(Debug, Clone, Getters)]
+pub struct Token {
+  /// Lemma (or stem) of the word, shared by its inflected forms
+  lemma: String,
+  /// Byte index of the first char of the word
+  start: usize,
+  /// Byte index right after the last char of the word
+  end: usize,
+}
+
+/// Tokenizer and lemmatizer of a chatmate language, matching vocab in texts on word boundaries
+/// regardless of their inflection, e.g. "habló" for "hablar".
+///
+/// Lemmas are produced by rule-based stemmers, which are not always linguistically correct stems,
+/// but are consistent across the inflected forms of a word.
+#[derive(Debug, Clone, Constructor)]
+pub struct VocabMatcher {
+  /// Language of the texts
+  language: ChatMateLanguage,
+}
+
+impl VocabMatcher {
+  /// Split a text into its words, i.e. runs of alphanumeric chars, along with their lemmas
+  pub fn tokenize(&self, text: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut word_start = None;
+
+    for (index, char) in text.char_indices().chain([(text.len(), ' ')]) {
+      match (char.is_alphanumeric(), word_start) {
+        (true, None) => word_start = Some(index),
+        (false, Some(start)) => {
+          tokens.push(Token {
+            lemma: self.lemmatize(&text[start..index]),
+            start,
+            end: index,
+          });
+          word_start = None;
+        }
+        _ => {}
+      }
+    }
+
+    tokens
+  }
+
+  /// Lemmatize a single word
+  pub fn lemmatize(&self, word: &str) -> String {
+    match self.language {
+      ChatMateLanguage::En => stem_en(&word.to_lowercase()),
+      ChatMateLanguage::Es => stem_es(&word.to_lowercase()),
+      ChatMateLanguage::Tr => stem_tr(&lowercase_tr(word)),
+    }
+  }
+
+  /// Find the vocab whose meaning a text asks, e.g. "what does charlar mean?", among the vocab
+  /// matched in it. A text asks a meaning if it contains a common phrase of asking it in the
+  /// language, and the asked vocab is the one closest to the phrase.
+  pub fn find_asked_vocab<'a>(
+    &self,
+    text: &str,
+    vocab_matches: &'a [VocabMatch],
+  ) -> Option<&'a VocabMatch> {
+    let markers = match self.language {
+      ChatMateLanguage::En => EN_MEANING_REQUEST_MARKERS.as_slice(),
+      ChatMateLanguage::Es => ES_MEANING_REQUEST_MARKERS.as_slice(),
+      ChatMateLanguage::Tr => TR_MEANING_REQUEST_MARKERS.as_slice(),
+    };
+
+    let marker_matches = markers
+      .iter()
+      .flat_map(|phrases| {
+        let phrases = phrases
+          .iter()
+          .map(|phrase| phrase.to_string())
+          .collect::<Vec<_>>();
+        let phrase_matches = self.find_vocab(text, &phrases);
+
+        let asks = phrases.iter().all(|phrase| {
+          phrase_matches
+            .iter()
+            .any(|phrase_match| phrase_match.vocab() == phrase)
+        });
+        if asks { phrase_matches } else { Vec::new() }
+      })
+      .collect::<Vec<_>>();
+
+    vocab_matches
+      .iter()
+      .min_by_key(|vocab_match| {
+        marker_matches
+          .iter()
+          .map(|marker_match| {
+            vocab_match
+              .start()
+              .saturating_sub(*marker_match.end())
+              .max(marker_match.start().saturating_sub(*vocab_match.end()))
+          })
+          .min()
+          .unwrap_or(usize::MAX)
+      })
+      .filter(|_| !marker_matches.is_empty())
+  }
+
+  /// Find all occurrences of a list of vocab in a text, ordered by their position. Multi-word vocab
+  /// (e.g. idioms) match a sequence of words with the same lemmas.
+  pub fn find_vocab(&self, text: &str, vocab: &[String]) -> Vec<VocabMatch> {
+    let tokens = self.tokenize(text);
+
+    let mut vocab_matches = vocab
+      .iter()
+      .flat_map(|word| {
+        let word_lemmas = self
+          .tokenize(word)
+          .into_iter()
+          .map(|token| token.lemma)
+          .collect::<Vec<_>>();
+
+        tokens
+          .windows(word_lemmas.len().max(1))
+          .filter(|window| {
+            !word_lemmas.is_empty()
+              && window
+                .iter()
+                .zip(&word_lemmas)
+                .all(|(token, lemma)| &token.lemma == lemma)
+          })
+          .map(|window| {
+            VocabMatch::new(word.clone(), window[0].start, window[window.len() - 1].end)
+          })
+          .collect::<Vec<_>>()
+      })
+      .collect::<Vec<_>>();
+    vocab_matches.sort_by_key(|vocab_match| *vocab_match.start());
+
+    vocab_matches
+  }
+}
+
+/// Whether the char at an index of an English word is a consonant, as defined by Porter
+fn is_en_consonant(chars: &[char], index: usize) -> bool {
+  match chars[index] {
+    'a' | 'e' | 'i' | 'o' | 'u' => false,
+    'y' => index == 0 || !is_en_consonant(chars, index - 1),
+    _ => true,
+  }
+}
+
+/// Porter measure of an English stem, i.e. the number of its vowel-consonant sequences
+fn en_measure(chars: &[char]) -> usize {
+  (1..chars.len())
+    .filter(|&index| is_en_consonant(chars, index) && !is_en_consonant(chars, index - 1))
+    .count()
+}
+
+/// Whether an English stem contains a vowel
+fn en_has_vowel(chars: &[char]) -> bool {
+  (0..chars.len()).any(|index| !is_en_consonant(chars, index))
+}
+
+/// Whether an English stem ends with consonant-vowel-consonant, where the last consonant is not
+/// w, x or y, e.g. "hop"
+fn en_ends_cvc(chars: &[char]) -> bool {
+  let length = chars.len();
+  length >= 3
+    && is_en_consonant(chars, length - 3)
+    && !is_en_consonant(chars, length - 2)
+    && is_en_consonant(chars, length - 1)
+    && !matches!(chars[length - 1], 'w' | 'x' | 'y')
+}
+
+/// Stem a lowercase English word by the inflectional steps of the Porter stemmer (1a, 1b, 1c and
+/// 5a), after resolving common irregular forms
+fn stem_en(word: &str) -> String {
+  if let Some((_, lemma)) = EN_IRREGULAR_FORMS.iter().find(|(form, _)| *form == word) {
+    return lemma.to_string();
+  }
+
+  let mut chars = word.chars().collect::<Vec<_>>();
+  let ends_with = |chars: &[char], suffix: &str| {
+    chars.len() >= suffix.len() + EN_MIN_STEM_CHARS
+      && chars[chars.len() - suffix.len()..]
+        .iter()
+        .copied()
+        .eq(suffix.chars())
+  };
+
+  // Step 1a: plurals
+  if ends_with(&chars, "sses") || ends_with(&chars, "ies") {
+    chars.truncate(chars.len() - 2);
+  } else if ends_with(&chars, "s") && !chars.ends_with(&['s', 's']) && !chars.ends_with(&['u', 's'])
+  {
+    chars.truncate(chars.len() - 1);
+  }
+
+  // Step 1b: past and progressive forms
+  if chars.ends_with(&['e', 'e', 'd']) {
+    if en_measure(&chars[..chars.len() - 3]) > 0 {
+      chars.truncate(chars.len() - 1);
+    }
+  } else if let Some(suffix) = ["ed", "ing"]
+    .into_iter()
+    .find(|suffix| ends_with(&chars, suffix) && en_has_vowel(&chars[..chars.len() - suffix.len()]))
+  {
+    chars.truncate(chars.len() - suffix.len());
+    let length = chars.len();
+    if ends_with(&chars, "at") || ends_with(&chars, "bl") || ends_with(&chars, "iz") {
+      chars.push('e');
+    } else if length >= 2
+      && chars[length - 1] == chars[length - 2]
+      && is_en_consonant(&chars, length - 1)
+      && !matches!(chars[length - 1], 'l' | 's' | 'z')
+    {
+      chars.truncate(length - 1);
+    } else if en_measure(&chars) == 1 && en_ends_cvc(&chars) {
+      chars.push('e');
+    }
+  }
+
+  // Step 1c: final y
+  let length = chars.len();
+  if length > EN_MIN_STEM_CHARS && chars[length - 1] == 'y' && en_has_vowel(&chars[..length - 1]) {
+    chars[length - 1] = 'i';
+  }
+
+  // Step 5a: final e
+  let length = chars.len();
+  if length > EN_MIN_STEM_CHARS && chars[length - 1] == 'e' {
+    let stem = &chars[..length - 1];
+    let measure = en_measure(stem);
+    if measure > 1 || (measure == 1 && !en_ends_cvc(stem)) {
+      chars.truncate(length - 1);
+    }
+  }
+
+  chars.into_iter().collect()
+}
+
+/// Stem a lowercase Spanish word by stripping accents and its longest inflectional suffix, at most
+/// one suffix being stripped
+fn stem_es(word: &str) -> String {
+  let word = word
+    .chars()
+    .map(|char| match char {
+      'á' => 'a',
+      'é' => 'e',
+      'í' => 'i',
+      'ó' => 'o',
+      'ú' | 'ü' => 'u',
+      _ => char,
+    })
+    .collect::<String>();
+
+  ES_SUFFIXES
+    .iter()
+    .find_map(|suffix| {
+      let min_stem_chars = if ES_NOMINAL_SUFFIXES.contains(suffix) {
+        ES_MIN_NOMINAL_STEM_CHARS
+      } else {
+        ES_MIN_STEM_CHARS
+      };
+      word
+        .strip_suffix(suffix)
+        .filter(|stem| stem.chars().count() >= min_stem_chars)
+    })
+    .unwrap_or(&word)
+    .to_string()
+}
+
+/// Lowercase a Turkish word, respecting the dotted and dotless i
+fn lowercase_tr(word: &str) -> String {
+  word
+    .chars()
+    .map(|char| match char {
+      'I' => 'ı'.to_string(),
+      'İ' => 'i'.to_string(),
+      _ => char.to_lowercase().to_string(),
+    })
+    .collect()
+}
+
+/// Whether a char is a Turkish vowel
+fn is_tr_vowel(char: char) -> bool {
+  matches!(char, 'a' | 'e' | 'ı' | 'i' | 'o' | 'ö' | 'u' | 'ü')
+}
+
+/// Whether a Turkish suffix can be attached to a stem, i.e. the stem is long enough, the suffix
+/// starts with a buffer consonant (y or n) or is a single consonant only after a vowel, it starts
+/// with a vowel only after a consonant, and its first vowel follows the vowel harmony of the stem
+fn tr_suffix_fits(stem: &str, suffix: &str) -> bool {
+  let (Some(stem_last), Some(suffix_first)) = (stem.chars().last(), suffix.chars().next()) else {
+    return false;
+  };
+  let attaches = if is_tr_vowel(suffix_first) {
+    !is_tr_vowel(stem_last)
+  } else if matches!(suffix_first, 'y' | 'n') || suffix.chars().count() == 1 {
+    is_tr_vowel(stem_last)
+  } else {
+    true
+  };
+
+  let harmonizes = match (
+    stem.chars().rev().find(|char| is_tr_vowel(*char)),
+    suffix.chars().find(|char| is_tr_vowel(*char)),
+  ) {
+    (Some(stem_vowel), Some(suffix_vowel)) => {
+      let front = matches!(stem_vowel, 'e' | 'i' | 'ö' | 'ü');
+      let rounded = matches!(stem_vowel, 'o' | 'ö' | 'u' | 'ü');
+      match suffix_vowel {
+        'a' => !front,
+        'e' => front,
+        'ı' => !front && !rounded,
+        'i' => front && !rounded,
+        'u' => !front && rounded,
+        'ü' => front && rounded,
+        // The o of "yor" doesn't harmonize
+        _ => true,
+      }
+    }
+    _ => true,
+  };
+
+  stem.chars().count() >= TR_MIN_STEM_CHARS && attaches && harmonizes
+}
+
+/// Stem a lowercase Turkish word by stripping its inflectional suffixes from the outermost one,
+/// one suffix per pass, and then reverting the consonant softening of its stem, e.g. "kitabı" to
+/// "kitap"
+fn stem_tr(word: &str) -> String {
+  let mut stem = word.to_string();
+
+  while let Some(stripped) = TR_SUFFIXES.iter().find_map(|suffix| {
+    stem
+      .strip_suffix(suffix)
+      .filter(|stripped| tr_suffix_fits(stripped, suffix))
+  }) {
+    stem = stripped.to_string();
+  }
+
+  let softened = match stem.chars().last() {
+    Some('b') => Some('p'),
+    Some('c') => Some('ç'),
+    Some('d') => Some('t'),
+    Some('ğ') => Some('k'),
+    _ => None,
+  };
+  if let Some(hardened) = softened {
+    stem.pop();
+    stem.push(hardened);
+  }
+
+  stem
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// Whether all words of a language share the lemma of the first one
+  fn share_lemma(language: ChatMateLanguage, words: &[&str]) -> bool {
+    let vocab_matcher = VocabMatcher::new(language);
+    let lemma = vocab_matcher.lemmatize(words[0]);
+    words
+      .iter()
+      .all(|word| vocab_matcher.lemmatize(word) == lemma)
+  }
+
+  /// Inflected Turkish nouns and verbs share the lemma of their dictionary form
+  #[test]
+  fn tr_inflections_match() {
+    assert!(share_lemma(
+      ChatMateLanguage::Tr,
+      &["anne", "anneye", "annem", "annesi", "annesinde"]
+    ));
+    assert!(share_lemma(
+      ChatMateLanguage::Tr,
+      &["kitap", "kitabı", "kitaplar", "kitaplarımız"]
+    ));
+    assert!(share_lemma(
+      ChatMateLanguage::Tr,
+      &["ev", "eve", "evler", "evlerimizde"]
+    ));
+    assert!(share_lemma(
+      ChatMateLanguage::Tr,
+      &["gitmek", "gidiyorum", "gittim", "gidecek"]
+    ));
+  }
+
+  /// Turkish words ending like a suffix don't collide with shorter words
+  #[test]
+  fn tr_collisions_do_not_match() {
+    assert!(!share_lemma(ChatMateLanguage::Tr, &["anne", "an"]));
+    assert!(!share_lemma(ChatMateLanguage::Tr, &["kale", "kalmak"]));
+    assert!(!share_lemma(ChatMateLanguage::Tr, &["elma", "el"]));
+  }
+
+  /// Inflected Spanish nouns and verbs share the lemma of their dictionary form
+  #[test]
+  fn es_inflections_match() {
+    assert!(share_lemma(
+      ChatMateLanguage::Es,
+      &["hablar", "hablo", "habló", "hablamos", "hablando"]
+    ));
+    assert!(share_lemma(ChatMateLanguage::Es, &["casa", "casas"]));
+    assert!(share_lemma(ChatMateLanguage::Es, &["comer", "comemos"]));
+  }
+
+  /// Spanish words ending like a suffix don't collide with shorter words
+  #[test]
+  fn es_collisions_do_not_match() {
+    assert!(!share_lemma(ChatMateLanguage::Es, &["mesa", "mes"]));
+    assert!(!share_lemma(ChatMateLanguage::Es, &["solo", "sol"]));
+  }
+
+  /// Inflected English words share the lemma of their dictionary form
+  #[test]
+  fn en_inflections_match() {
+    assert!(share_lemma(
+      ChatMateLanguage::En,
+      &["run", "runs", "running", "ran"]
+    ));
+  }
+
+  /// Only the vocab closest to a meaning request phrase is asked about
+  #[test]
+  fn finds_asked_vocab_only() {
+    let vocab_matcher = VocabMatcher::new(ChatMateLanguage::Es);
+    let vocab = ["charlar".to_string(), "disfrutar".to_string()];
+
+    let text = "Me gusta charlar, pero ¿qué quiere decir disfrutar?";
+    let vocab_matches = vocab_matcher.find_vocab(text, &vocab);
+    let asked_vocab = vocab_matcher.find_asked_vocab(text, &vocab_matches);
+    assert_eq!(vocab_matches.len(), 2);
+    assert_eq!(asked_vocab.unwrap().vocab(), "disfrutar");
+
+    let text = "Me gusta charlar y disfrutar.";
+    let vocab_matches = vocab_matcher.find_vocab(text, &vocab);
+    assert!(
+      vocab_matcher
+        .find_asked_vocab(text, &vocab_matches)
+        .is_none()
+    );
+  }
+}
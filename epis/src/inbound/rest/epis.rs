@@ -9,8 +9,17 @@ use crate::{
   inbound::{
     http::AppState,
     rest::epis::handlers::{
+      create_learning_goal::{__path_create_learning_goal, create_learning_goal},
+      delete_learning_goal::{__path_delete_learning_goal, delete_learning_goal},
+      get_placement_progress::{__path_get_placement_progress, get_placement_progress},
+      get_pronunciation_report::{__path_get_pronunciation_report, get_pronunciation_report},
+      get_usage::{__path_get_usage, get_usage},
       handshake_chatmate::{__path_handshake_chatmate, handshake_chatmate},
       list_chatmates::{__path_list_chatmates, list_chatmates},
+      list_learning_goals::{__path_list_learning_goals, list_learning_goals},
+      list_level_changes::{__path_list_level_changes, list_level_changes},
+      resolve_level_change::{__path_resolve_level_change, resolve_level_change},
+      update_learning_goal::{__path_update_learning_goal, update_learning_goal},
     },
   },
 };
@@ -25,7 +34,15 @@ pub struct EpisRouter<E: Epis, UM: UserManagement>(OpenApiRouter<AppState<E, UM>
 impl<E: Epis, UM: UserManagement> EpisRouter<E, UM> {
   /// Construct Epis router
   pub fn new() -> Self {
-    let router = OpenApiRouter::new().routes(routes!(handshake_chatmate, list_chatmates));
+    let router = OpenApiRouter::new()
+      .routes(routes!(handshake_chatmate, list_chatmates))
+      .routes(routes!(get_placement_progress))
+      .routes(routes!(get_pronunciation_report))
+      .routes(routes!(list_level_changes))
+      .routes(routes!(resolve_level_change))
+      .routes(routes!(list_learning_goals, create_learning_goal))
+      .routes(routes!(update_learning_goal, delete_learning_goal))
+      .routes(routes!(get_usage));
 
     Self(router)
   }
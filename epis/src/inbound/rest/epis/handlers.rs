@@ -1,4 +1,13 @@
 //! Epis router handlers
 
+pub mod create_learning_goal;
+pub mod delete_learning_goal;
+pub mod get_placement_progress;
+pub mod get_pronunciation_report;
+pub mod get_usage;
 pub mod handshake_chatmate;
 pub mod list_chatmates;
+pub mod list_learning_goals;
+pub mod list_level_changes;
+pub mod resolve_level_change;
+pub mod update_learning_goal;
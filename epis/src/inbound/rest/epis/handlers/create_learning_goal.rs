@@ -0,0 +1,115 @@
+//! Epis create learning goal handler
+
+use axum::{
+  Extension, Json,
+  extract::{Path, State},
+  http::StatusCode,
+  response::IntoResponse,
+};
+use derive_getters::Getters;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use utoipa::ToSchema;
+
+use crate::{
+  domain::{
+    models::{CurriculumUnit, EpisError, Id, LearningGoalData, User},
+    ports::{Epis, UserManagement},
+  },
+  inbound::{
+    http::AppState,
+    rest::epis::{EPIS_CATEGORY, handlers::list_learning_goals::LearningGoalItem},
+  },
+};
+
+#[allow(clippy::missing_docs_in_private_items)]
+#[derive(Error, Debug)]
+pub enum CreateLearningGoalApiError {
+  #[error("Chatmate not found")]
+  NotFound,
+  #[error("Learning goal needs a description, and each curriculum unit needs a title")]
+  InvalidLearningGoal,
+  #[error("Unknown error while creating learning goal")]
+  Unknown,
+}
+
+impl IntoResponse for CreateLearningGoalApiError {
+  fn into_response(self) -> axum::response::Response {
+    match self {
+      Self::NotFound => (StatusCode::NOT_FOUND, Json(self.to_string())).into_response(),
+      Self::InvalidLearningGoal => {
+        (StatusCode::BAD_REQUEST, Json(self.to_string())).into_response()
+      }
+      Self::Unknown => (StatusCode::INTERNAL_SERVER_ERROR, Json(self.to_string())).into_response(),
+    }
+  }
+}
+
+/// Curriculum unit in the request body
+#[derive(Debug, Clone, Getters, Serialize, Deserialize, ToSchema)]
+pub struct CurriculumUnitRequestBody {
+  /// Title of the unit, e.g. "At the airport"
+  title: String,
+  /// Vocab taught in the unit, in base form and in the language of the chatmate
+  #[serde(default)]
+  target_vocab: Vec<String>,
+}
+
+/// Request body of this route
+#[derive(Debug, Clone, Getters, Serialize, Deserialize, ToSchema)]
+pub struct LearningGoalRequestBody {
+  /// What the learner wants to achieve, e.g. "travel to Istanbul in May"
+  description: String,
+  /// Units of the curriculum of the goal, in order
+  #[serde(default)]
+  curriculum: Vec<CurriculumUnitRequestBody>,
+}
+
+impl From<LearningGoalRequestBody> for LearningGoalData {
+  fn from(request: LearningGoalRequestBody) -> Self {
+    Self::new(
+      request.description,
+      request
+        .curriculum
+        .into_iter()
+        .map(|unit| CurriculumUnit::new(unit.title, unit.target_vocab))
+        .collect(),
+    )
+  }
+}
+
+/// Create learning goal handler
+#[utoipa::path(
+  post,
+  path = "/chatmate/{chatmate_id}/goals",
+  tag = EPIS_CATEGORY,
+  params(("chatmate_id" = String, Path, description = "Id of the chatmate")),
+  request_body = LearningGoalRequestBody,
+  responses(
+    (status = CREATED, body = LearningGoalItem, content_type = "application/json"),
+    (status = BAD_REQUEST, body = String, content_type = "application/json"),
+    (status = NOT_FOUND, body = String, content_type = "application/json"),
+    (status = INTERNAL_SERVER_ERROR, body = String, content_type = "application/json"),
+  )
+)]
+pub async fn create_learning_goal<E: Epis, UM: UserManagement>(
+  State(app_state): State<AppState<E, UM>>,
+  Extension(user): Extension<User>,
+  Path(chatmate_id): Path<Id>,
+  Json(request): Json<LearningGoalRequestBody>,
+) -> Result<(StatusCode, Json<LearningGoalItem>), CreateLearningGoalApiError> {
+  let learning_goal = app_state
+    .epis()
+    .create_learning_goal(user.id(), &chatmate_id, request.into())
+    .await
+    .map_err(|e| match e {
+      EpisError::InvalidLearningGoal => CreateLearningGoalApiError::InvalidLearningGoal,
+      _ => CreateLearningGoalApiError::Unknown,
+    })?
+    .ok_or(CreateLearningGoalApiError::NotFound)?;
+
+  Ok((
+    StatusCode::CREATED,
+    Json(LearningGoalItem::from(learning_goal)),
+  ))
+}
@@ -0,0 +1,65 @@
+//! Epis delete learning goal handler
+
+use axum::{
+  Extension, Json,
+  extract::{Path, State},
+  http::StatusCode,
+  response::IntoResponse,
+};
+use thiserror::Error;
+
+use crate::{
+  domain::{
+    models::{Id, User},
+    ports::{Epis, UserManagement},
+  },
+  inbound::{http::AppState, rest::epis::EPIS_CATEGORY},
+};
+
+#[allow(clippy::missing_docs_in_private_items)]
+#[derive(Error, Debug)]
+pub enum DeleteLearningGoalApiError {
+  #[error("Chatmate or learning goal not found")]
+  NotFound,
+  #[error("Unknown error while deleting learning goal")]
+  Unknown,
+}
+
+impl IntoResponse for DeleteLearningGoalApiError {
+  fn into_response(self) -> axum::response::Response {
+    match self {
+      Self::NotFound => (StatusCode::NOT_FOUND, Json(self.to_string())).into_response(),
+      Self::Unknown => (StatusCode::INTERNAL_SERVER_ERROR, Json(self.to_string())).into_response(),
+    }
+  }
+}
+
+/// Delete learning goal handler, deleting a learning goal along with its curriculum
+#[utoipa::path(
+  delete,
+  path = "/chatmate/{chatmate_id}/goals/{learning_goal_id}",
+  tag = EPIS_CATEGORY,
+  params(
+    ("chatmate_id" = String, Path, description = "Id of the chatmate"),
+    ("learning_goal_id" = String, Path, description = "Id of the learning goal"),
+  ),
+  responses(
+    (status = NO_CONTENT),
+    (status = NOT_FOUND, body = String, content_type = "application/json"),
+    (status = INTERNAL_SERVER_ERROR, body = String, content_type = "application/json"),
+  )
+)]
+pub async fn delete_learning_goal<E: Epis, UM: UserManagement>(
+  State(app_state): State<AppState<E, UM>>,
+  Extension(user): Extension<User>,
+  Path((chatmate_id, learning_goal_id)): Path<(Id, Id)>,
+) -> Result<StatusCode, DeleteLearningGoalApiError> {
+  app_state
+    .epis()
+    .delete_learning_goal(user.id(), &chatmate_id, &learning_goal_id)
+    .await
+    .map_err(|_| DeleteLearningGoalApiError::Unknown)?
+    .ok_or(DeleteLearningGoalApiError::NotFound)?;
+
+  Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,86 @@
+//! Epis get placement progress handler
+
+use axum::{
+  Extension, Json,
+  extract::{Path, State},
+  http::StatusCode,
+  response::IntoResponse,
+};
+use derive_more::Constructor;
+use serde::Serialize;
+use thiserror::Error;
+use utoipa::ToSchema;
+
+use crate::{
+  domain::{
+    models::{Id, User},
+    ports::{Epis, UserManagement},
+  },
+  inbound::{http::AppState, rest::epis::EPIS_CATEGORY},
+};
+
+#[allow(clippy::missing_docs_in_private_items)]
+#[derive(Error, Debug)]
+pub enum GetPlacementProgressApiError {
+  #[error("Chatmate not found")]
+  NotFound,
+  #[error("Unknown error while getting placement progress")]
+  Unknown,
+}
+
+impl IntoResponse for GetPlacementProgressApiError {
+  fn into_response(self) -> axum::response::Response {
+    match self {
+      Self::NotFound => (StatusCode::NOT_FOUND, Json(self.to_string())).into_response(),
+      Self::Unknown => (StatusCode::INTERNAL_SERVER_ERROR, Json(self.to_string())).into_response(),
+    }
+  }
+}
+
+/// Body of the response
+#[derive(Debug, Clone, Constructor, Serialize, ToSchema)]
+pub struct GetPlacementProgressResponse {
+  /// Whether the learner is still being placed, i.e. has no CEFR level yet
+  in_progress: bool,
+  /// Number of chat turns assessed so far
+  assessed_turns: u32,
+  /// Number of chat turns assessed before placing the learner
+  total_turns: u32,
+  /// CEFR level the learner is placed at, if placement is done
+  cefr_level: Option<String>,
+}
+
+/// Get placement progress handler
+#[utoipa::path(
+  get,
+  path = "/chatmate/{chatmate_id}/placement",
+  tag = EPIS_CATEGORY,
+  params(("chatmate_id" = String, Path, description = "Id of the chatmate")),
+  responses(
+    (status = OK, body = GetPlacementProgressResponse, content_type = "application/json"),
+    (status = NOT_FOUND, body = String, content_type = "application/json"),
+    (status = INTERNAL_SERVER_ERROR, body = String, content_type = "application/json"),
+  )
+)]
+pub async fn get_placement_progress<E: Epis, UM: UserManagement>(
+  State(app_state): State<AppState<E, UM>>,
+  Extension(user): Extension<User>,
+  Path(chatmate_id): Path<Id>,
+) -> Result<Json<GetPlacementProgressResponse>, GetPlacementProgressApiError> {
+  let placement_progress = app_state
+    .epis()
+    .get_placement_progress(user.id(), &chatmate_id)
+    .await
+    .map_err(|_| GetPlacementProgressApiError::Unknown)?
+    .ok_or(GetPlacementProgressApiError::NotFound)?;
+
+  Ok(Json(GetPlacementProgressResponse::new(
+    placement_progress.cefr_level().is_none(),
+    *placement_progress.assessed_turns(),
+    *placement_progress.total_turns(),
+    placement_progress
+      .cefr_level()
+      .as_ref()
+      .map(ToString::to_string),
+  )))
+}
@@ -0,0 +1,99 @@
+//! Epis get pronunciation report handler
+
+use axum::{
+  Extension, Json,
+  extract::{Path, State},
+  http::StatusCode,
+  response::IntoResponse,
+};
+use derive_more::Constructor;
+use serde::Serialize;
+use thiserror::Error;
+use utoipa::ToSchema;
+
+use crate::{
+  domain::{
+    models::{Id, User},
+    ports::{Epis, UserManagement},
+  },
+  inbound::{http::AppState, rest::epis::EPIS_CATEGORY},
+};
+
+#[allow(clippy::missing_docs_in_private_items)]
+#[derive(Error, Debug)]
+pub enum GetPronunciationReportApiError {
+  #[error("Chatmate not found")]
+  NotFound,
+  #[error("Unknown error while getting pronunciation report")]
+  Unknown,
+}
+
+impl IntoResponse for GetPronunciationReportApiError {
+  fn into_response(self) -> axum::response::Response {
+    match self {
+      Self::NotFound => (StatusCode::NOT_FOUND, Json(self.to_string())).into_response(),
+      Self::Unknown => (StatusCode::INTERNAL_SERVER_ERROR, Json(self.to_string())).into_response(),
+    }
+  }
+}
+
+/// Word pronunciation item in the response
+#[derive(Debug, Clone, Constructor, Serialize, ToSchema)]
+pub struct WordPronunciationItem {
+  /// The word the learner intended to say
+  word: String,
+  /// Average score of pronouncing the word, from 0 to 1
+  average_score: f32,
+  /// Number of times the word is said
+  attempts: u32,
+  /// Other words the word is recognized as
+  heard_as: Vec<String>,
+}
+
+/// Body of the response
+#[derive(Debug, Clone, Constructor, Serialize, ToSchema)]
+pub struct GetPronunciationReportResponse {
+  /// Words the learner mumbles or mispronounces, the worst pronounced first
+  words: Vec<WordPronunciationItem>,
+}
+
+/// Get pronunciation report handler
+#[utoipa::path(
+  get,
+  path = "/chatmate/{chatmate_id}/pronunciation",
+  tag = EPIS_CATEGORY,
+  params(("chatmate_id" = String, Path, description = "Id of the chatmate")),
+  responses(
+    (status = OK, body = GetPronunciationReportResponse, content_type = "application/json"),
+    (status = NOT_FOUND, body = String, content_type = "application/json"),
+    (status = INTERNAL_SERVER_ERROR, body = String, content_type = "application/json"),
+  )
+)]
+pub async fn get_pronunciation_report<E: Epis, UM: UserManagement>(
+  State(app_state): State<AppState<E, UM>>,
+  Extension(user): Extension<User>,
+  Path(chatmate_id): Path<Id>,
+) -> Result<Json<GetPronunciationReportResponse>, GetPronunciationReportApiError> {
+  let word_pronunciations = app_state
+    .epis()
+    .get_pronunciation_report(user.id(), &chatmate_id)
+    .await
+    .map_err(|_| GetPronunciationReportApiError::Unknown)?
+    .ok_or(GetPronunciationReportApiError::NotFound)?;
+
+  let word_pronunciation_items = word_pronunciations
+    .into_iter()
+    .map(|word_pronunciation| {
+      WordPronunciationItem::new(
+        word_pronunciation.word().clone(),
+        *word_pronunciation.average_score(),
+        *word_pronunciation.attempts(),
+        word_pronunciation.heard_as().clone(),
+      )
+    })
+    .collect();
+
+  Ok(Json(GetPronunciationReportResponse::new(
+    word_pronunciation_items,
+  )))
+}
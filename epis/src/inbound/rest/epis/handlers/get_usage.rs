@@ -0,0 +1,71 @@
+//! Epis get usage handler
+
+use axum::{Extension, Json, extract::State, http::StatusCode, response::IntoResponse};
+use derive_more::Constructor;
+use serde::Serialize;
+use thiserror::Error;
+use utoipa::ToSchema;
+
+use crate::{
+  domain::{
+    models::User,
+    ports::{Epis, UserManagement},
+  },
+  inbound::{http::AppState, rest::epis::EPIS_CATEGORY},
+};
+
+#[allow(clippy::missing_docs_in_private_items)]
+#[derive(Error, Debug)]
+pub enum GetUsageApiError {
+  #[error("Unknown error while getting usage")]
+  Unknown,
+}
+
+impl IntoResponse for GetUsageApiError {
+  fn into_response(self) -> axum::response::Response {
+    match self {
+      Self::Unknown => (StatusCode::INTERNAL_SERVER_ERROR, Json(self.to_string())).into_response(),
+    }
+  }
+}
+
+/// Body of the response
+#[derive(Debug, Clone, Constructor, Serialize, ToSchema)]
+pub struct GetUsageResponse {
+  /// Input tokens of generation and token billed transcription
+  input_tokens: u64,
+  /// Output tokens of generation and token billed transcription
+  output_tokens: u64,
+  /// Seconds of transcribed audio
+  audio_seconds: f64,
+  /// Characters converted to speech
+  characters: u64,
+}
+
+/// Get usage handler, reporting the total ai usage of the user across all of their chatmates
+#[utoipa::path(
+  get,
+  path = "/usage",
+  tag = EPIS_CATEGORY,
+  responses(
+    (status = OK, body = GetUsageResponse, content_type = "application/json"),
+    (status = INTERNAL_SERVER_ERROR, body = String, content_type = "application/json"),
+  )
+)]
+pub async fn get_usage<E: Epis, UM: UserManagement>(
+  State(app_state): State<AppState<E, UM>>,
+  Extension(user): Extension<User>,
+) -> Result<Json<GetUsageResponse>, GetUsageApiError> {
+  let usage_report = app_state
+    .epis()
+    .get_usage(user.id())
+    .await
+    .map_err(|_| GetUsageApiError::Unknown)?;
+
+  Ok(Json(GetUsageResponse::new(
+    *usage_report.input_tokens(),
+    *usage_report.output_tokens(),
+    *usage_report.audio_seconds(),
+    *usage_report.characters(),
+  )))
+}
@@ -11,7 +11,10 @@ use utoipa::ToSchema;
 
 use crate::{
   domain::{
-    models::{ChatMateLanguage, EpisError, User},
+    models::{
+      ChatMateLanguage, ChatMateVoice, DEFAULT_CHATMATE_VOICE, DEFAULT_NATIVE_LANGUAGE, EpisError,
+      NativeLanguage, User,
+    },
     ports::{Epis, UserManagement},
   },
   inbound::{http::AppState, rest::epis::EPIS_CATEGORY},
@@ -24,6 +27,12 @@ pub enum HandshakeChatmateApiError {
   AlreadyHandshaken,
   #[error("Language is not supported")]
   UnsupportedLanguage,
+  #[error("Voice is not supported")]
+  UnsupportedVoice,
+  #[error("Speaking style is too long")]
+  SpeakingStyleTooLong,
+  #[error("Native language is not supported")]
+  UnsupportedNativeLanguage,
   #[error("unknown error while handshaking with chatmate")]
   Unknown,
 }
@@ -31,7 +40,10 @@ impl IntoResponse for HandshakeChatmateApiError {
   fn into_response(self) -> axum::response::Response {
     match self {
       Self::AlreadyHandshaken => (StatusCode::BAD_REQUEST, Json(self.to_string())).into_response(),
-      Self::UnsupportedLanguage => {
+      Self::UnsupportedLanguage
+      | Self::UnsupportedVoice
+      | Self::SpeakingStyleTooLong
+      | Self::UnsupportedNativeLanguage => {
         (StatusCode::BAD_REQUEST, Json(self.to_string())).into_response()
       }
       Self::Unknown => (StatusCode::INTERNAL_SERVER_ERROR, Json(self.to_string())).into_response(),
@@ -45,6 +57,19 @@ pub struct HandshakeChatmateRequestBody {
   /// Language of chatmate
   /// We use [String] and try to parse it to a [ChatMateLanguage]
   language: String,
+  /// Voice identifier of chatmate, one of
+  /// [CHATMATE_VOICES](crate::domain::models::CHATMATE_VOICES), defaulting to
+  /// [DEFAULT_CHATMATE_VOICE]
+  #[serde(default)]
+  voice: Option<String>,
+  /// Speaking style instructions of chatmate, e.g. "Cheerful and a bit playful", up to
+  /// [MAX_SPEAKING_STYLE_CHARS](crate::domain::models::MAX_SPEAKING_STYLE_CHARS) characters
+  #[serde(default)]
+  speaking_style: Option<String>,
+  /// Native language of the learner as an ISO 639-1 code, e.g. fa, used for explanations and
+  /// translations, defaulting to [DEFAULT_NATIVE_LANGUAGE]
+  #[serde(default)]
+  native_language: Option<String>,
 }
 
 /// Body of the response
@@ -77,10 +102,24 @@ pub async fn handshake_chatmate<E: Epis, UM: UserManagement>(
       user.id(),
       &ChatMateLanguage::from_str(&request.language)
         .map_err(|_| HandshakeChatmateApiError::UnsupportedLanguage)?,
+      &ChatMateVoice::new(
+        request
+          .voice
+          .unwrap_or_else(|| DEFAULT_CHATMATE_VOICE.to_string()),
+        request.speaking_style,
+      ),
+      &NativeLanguage::new(
+        request
+          .native_language
+          .unwrap_or_else(|| DEFAULT_NATIVE_LANGUAGE.to_string()),
+      ),
     )
     .await
     .map_err(|e| match e {
       EpisError::AlreadyHandshaken => HandshakeChatmateApiError::AlreadyHandshaken,
+      EpisError::UnsupportedVoice => HandshakeChatmateApiError::UnsupportedVoice,
+      EpisError::SpeakingStyleTooLong => HandshakeChatmateApiError::SpeakingStyleTooLong,
+      EpisError::UnsupportedNativeLanguage => HandshakeChatmateApiError::UnsupportedNativeLanguage,
       _ => HandshakeChatmateApiError::Unknown,
     })?;
 
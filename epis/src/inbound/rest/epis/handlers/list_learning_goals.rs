@@ -0,0 +1,118 @@
+//! Epis list learning goals handler
+
+use axum::{
+  Extension, Json,
+  extract::{Path, State},
+  http::StatusCode,
+  response::IntoResponse,
+};
+use derive_more::Constructor;
+use serde::Serialize;
+use thiserror::Error;
+use utoipa::ToSchema;
+
+use crate::{
+  domain::{
+    models::{CurriculumUnit, Id, LearningGoal, User},
+    ports::{Epis, UserManagement},
+  },
+  inbound::{http::AppState, rest::epis::EPIS_CATEGORY},
+};
+
+#[allow(clippy::missing_docs_in_private_items)]
+#[derive(Error, Debug)]
+pub enum ListLearningGoalsApiError {
+  #[error("Chatmate not found")]
+  NotFound,
+  #[error("Unknown error while listing learning goals")]
+  Unknown,
+}
+
+impl IntoResponse for ListLearningGoalsApiError {
+  fn into_response(self) -> axum::response::Response {
+    match self {
+      Self::NotFound => (StatusCode::NOT_FOUND, Json(self.to_string())).into_response(),
+      Self::Unknown => (StatusCode::INTERNAL_SERVER_ERROR, Json(self.to_string())).into_response(),
+    }
+  }
+}
+
+/// Curriculum unit item in the response
+#[derive(Debug, Clone, Constructor, Serialize, ToSchema)]
+pub struct CurriculumUnitItem {
+  /// Title of the unit
+  title: String,
+  /// Vocab taught in the unit
+  target_vocab: Vec<String>,
+}
+
+impl From<CurriculumUnit> for CurriculumUnitItem {
+  fn from(unit: CurriculumUnit) -> Self {
+    Self::new(unit.title().clone(), unit.target_vocab().clone())
+  }
+}
+
+/// Learning goal item in the response
+#[derive(Debug, Clone, Constructor, Serialize, ToSchema)]
+pub struct LearningGoalItem {
+  /// Id of the learning goal
+  learning_goal_id: String,
+  /// What the learner wants to achieve
+  description: String,
+  /// Units of the curriculum of the goal, in order
+  curriculum: Vec<CurriculumUnitItem>,
+}
+
+impl From<LearningGoal> for LearningGoalItem {
+  fn from(learning_goal: LearningGoal) -> Self {
+    let (id, description, curriculum) = learning_goal.into_parts();
+
+    Self::new(
+      id.to_string(),
+      description,
+      curriculum
+        .into_iter()
+        .map(CurriculumUnitItem::from)
+        .collect(),
+    )
+  }
+}
+
+/// Body of the response
+#[derive(Debug, Clone, Constructor, Serialize, ToSchema)]
+pub struct ListLearningGoalsResponse {
+  /// List of learning goals, in the order they are created
+  learning_goals: Vec<LearningGoalItem>,
+}
+
+/// List learning goals handler
+#[utoipa::path(
+  get,
+  path = "/chatmate/{chatmate_id}/goals",
+  tag = EPIS_CATEGORY,
+  params(("chatmate_id" = String, Path, description = "Id of the chatmate")),
+  responses(
+    (status = OK, body = ListLearningGoalsResponse, content_type = "application/json"),
+    (status = NOT_FOUND, body = String, content_type = "application/json"),
+    (status = INTERNAL_SERVER_ERROR, body = String, content_type = "application/json"),
+  )
+)]
+pub async fn list_learning_goals<E: Epis, UM: UserManagement>(
+  State(app_state): State<AppState<E, UM>>,
+  Extension(user): Extension<User>,
+  Path(chatmate_id): Path<Id>,
+) -> Result<Json<ListLearningGoalsResponse>, ListLearningGoalsApiError> {
+  let learning_goals = app_state
+    .epis()
+    .list_learning_goals(user.id(), &chatmate_id)
+    .await
+    .map_err(|_| ListLearningGoalsApiError::Unknown)?
+    .ok_or(ListLearningGoalsApiError::NotFound)?;
+
+  Ok(Json(ListLearningGoalsResponse::new(
+    learning_goals
+      .into_iter()
+      .map(LearningGoalItem::from)
+      .collect(),
+  )))
+}
@@ -0,0 +1,111 @@
+//! Epis list level changes handler
+
+use axum::{
+  Extension, Json,
+  extract::{Path, State},
+  http::StatusCode,
+  response::IntoResponse,
+};
+use derive_more::Constructor;
+use serde::Serialize;
+use thiserror::Error;
+use utoipa::ToSchema;
+
+use crate::{
+  domain::{
+    models::{Id, LevelChange, LevelChangeStatus, User},
+    ports::{Epis, UserManagement},
+  },
+  inbound::{http::AppState, rest::epis::EPIS_CATEGORY},
+};
+
+#[allow(clippy::missing_docs_in_private_items)]
+#[derive(Error, Debug)]
+pub enum ListLevelChangesApiError {
+  #[error("Chatmate not found")]
+  NotFound,
+  #[error("Unknown error while listing level changes")]
+  Unknown,
+}
+
+impl IntoResponse for ListLevelChangesApiError {
+  fn into_response(self) -> axum::response::Response {
+    match self {
+      Self::NotFound => (StatusCode::NOT_FOUND, Json(self.to_string())).into_response(),
+      Self::Unknown => (StatusCode::INTERNAL_SERVER_ERROR, Json(self.to_string())).into_response(),
+    }
+  }
+}
+
+/// Level change item in the response
+#[derive(Debug, Clone, Constructor, Serialize, ToSchema)]
+pub struct LevelChangeItem {
+  /// Id of the level change
+  level_change_id: String,
+  /// CEFR level before the change
+  from_level: String,
+  /// Proposed CEFR level
+  to_level: String,
+  /// Why the change is proposed
+  rationale: String,
+  /// Status of the change, one of pending, applied or rejected
+  status: String,
+}
+
+impl From<LevelChange> for LevelChangeItem {
+  fn from(level_change: LevelChange) -> Self {
+    let (id, from_level, to_level, rationale, status) = level_change.into_parts();
+    let status = match status {
+      LevelChangeStatus::Pending => "pending",
+      LevelChangeStatus::Applied => "applied",
+      LevelChangeStatus::Rejected => "rejected",
+    };
+
+    Self::new(
+      id.to_string(),
+      from_level.to_string(),
+      to_level.to_string(),
+      rationale,
+      status.to_string(),
+    )
+  }
+}
+
+/// Body of the response
+#[derive(Debug, Clone, Constructor, Serialize, ToSchema)]
+pub struct ListLevelChangesResponse {
+  /// List of level changes, the latest first
+  level_changes: Vec<LevelChangeItem>,
+}
+
+/// List level changes handler
+#[utoipa::path(
+  get,
+  path = "/chatmate/{chatmate_id}/level-changes",
+  tag = EPIS_CATEGORY,
+  params(("chatmate_id" = String, Path, description = "Id of the chatmate")),
+  responses(
+    (status = OK, body = ListLevelChangesResponse, content_type = "application/json"),
+    (status = NOT_FOUND, body = String, content_type = "application/json"),
+    (status = INTERNAL_SERVER_ERROR, body = String, content_type = "application/json"),
+  )
+)]
+pub async fn list_level_changes<E: Epis, UM: UserManagement>(
+  State(app_state): State<AppState<E, UM>>,
+  Extension(user): Extension<User>,
+  Path(chatmate_id): Path<Id>,
+) -> Result<Json<ListLevelChangesResponse>, ListLevelChangesApiError> {
+  let level_changes = app_state
+    .epis()
+    .list_level_changes(user.id(), &chatmate_id)
+    .await
+    .map_err(|_| ListLevelChangesApiError::Unknown)?
+    .ok_or(ListLevelChangesApiError::NotFound)?;
+
+  Ok(Json(ListLevelChangesResponse::new(
+    level_changes
+      .into_iter()
+      .map(LevelChangeItem::from)
+      .collect(),
+  )))
+}
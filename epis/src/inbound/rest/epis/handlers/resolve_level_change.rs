@@ -0,0 +1,87 @@
+//! Epis resolve level change handler
+
+use axum::{
+  Extension, Json,
+  extract::{Path, State},
+  http::StatusCode,
+  response::IntoResponse,
+};
+use derive_getters::Getters;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use utoipa::ToSchema;
+
+use crate::{
+  domain::{
+    models::{EpisError, Id, User},
+    ports::{Epis, UserManagement},
+  },
+  inbound::{
+    http::AppState,
+    rest::epis::{EPIS_CATEGORY, handlers::list_level_changes::LevelChangeItem},
+  },
+};
+
+#[allow(clippy::missing_docs_in_private_items)]
+#[derive(Error, Debug)]
+pub enum ResolveLevelChangeApiError {
+  #[error("Chatmate or level change not found")]
+  NotFound,
+  #[error("Level change already resolved")]
+  AlreadyResolved,
+  #[error("Unknown error while resolving level change")]
+  Unknown,
+}
+
+impl IntoResponse for ResolveLevelChangeApiError {
+  fn into_response(self) -> axum::response::Response {
+    match self {
+      Self::NotFound => (StatusCode::NOT_FOUND, Json(self.to_string())).into_response(),
+      Self::AlreadyResolved => (StatusCode::CONFLICT, Json(self.to_string())).into_response(),
+      Self::Unknown => (StatusCode::INTERNAL_SERVER_ERROR, Json(self.to_string())).into_response(),
+    }
+  }
+}
+
+/// Request body of this route
+#[derive(Debug, Clone, Getters, Serialize, Deserialize, ToSchema)]
+pub struct ResolveLevelChangeRequestBody {
+  /// Whether the learner confirms the level change, applying it, or rejects it
+  confirmed: bool,
+}
+
+/// Resolve level change handler, confirming or rejecting a pending level change
+#[utoipa::path(
+  post,
+  path = "/chatmate/{chatmate_id}/level-changes/{level_change_id}",
+  tag = EPIS_CATEGORY,
+  params(
+    ("chatmate_id" = String, Path, description = "Id of the chatmate"),
+    ("level_change_id" = String, Path, description = "Id of the level change"),
+  ),
+  request_body = ResolveLevelChangeRequestBody,
+  responses(
+    (status = OK, body = LevelChangeItem, content_type = "application/json"),
+    (status = NOT_FOUND, body = String, content_type = "application/json"),
+    (status = CONFLICT, body = String, content_type = "application/json"),
+    (status = INTERNAL_SERVER_ERROR, body = String, content_type = "application/json"),
+  )
+)]
+pub async fn resolve_level_change<E: Epis, UM: UserManagement>(
+  State(app_state): State<AppState<E, UM>>,
+  Extension(user): Extension<User>,
+  Path((chatmate_id, level_change_id)): Path<(Id, Id)>,
+  Json(request): Json<ResolveLevelChangeRequestBody>,
+) -> Result<Json<LevelChangeItem>, ResolveLevelChangeApiError> {
+  let level_change = app_state
+    .epis()
+    .resolve_level_change(user.id(), &chatmate_id, &level_change_id, request.confirmed)
+    .await
+    .map_err(|e| match e {
+      EpisError::LevelChangeAlreadyResolved => ResolveLevelChangeApiError::AlreadyResolved,
+      _ => ResolveLevelChangeApiError::Unknown,
+    })?
+    .ok_or(ResolveLevelChangeApiError::NotFound)?;
+
+  Ok(Json(LevelChangeItem::from(level_change)))
+}
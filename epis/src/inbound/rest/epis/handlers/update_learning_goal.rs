@@ -0,0 +1,84 @@
+//! Epis update learning goal handler
+
+use axum::{
+  Extension, Json,
+  extract::{Path, State},
+  http::StatusCode,
+  response::IntoResponse,
+};
+use thiserror::Error;
+
+use crate::{
+  domain::{
+    models::{EpisError, Id, User},
+    ports::{Epis, UserManagement},
+  },
+  inbound::{
+    http::AppState,
+    rest::epis::{
+      EPIS_CATEGORY,
+      handlers::{
+        create_learning_goal::LearningGoalRequestBody, list_learning_goals::LearningGoalItem,
+      },
+    },
+  },
+};
+
+#[allow(clippy::missing_docs_in_private_items)]
+#[derive(Error, Debug)]
+pub enum UpdateLearningGoalApiError {
+  #[error("Chatmate or learning goal not found")]
+  NotFound,
+  #[error("Learning goal needs a description, and each curriculum unit needs a title")]
+  InvalidLearningGoal,
+  #[error("Unknown error while updating learning goal")]
+  Unknown,
+}
+
+impl IntoResponse for UpdateLearningGoalApiError {
+  fn into_response(self) -> axum::response::Response {
+    match self {
+      Self::NotFound => (StatusCode::NOT_FOUND, Json(self.to_string())).into_response(),
+      Self::InvalidLearningGoal => {
+        (StatusCode::BAD_REQUEST, Json(self.to_string())).into_response()
+      }
+      Self::Unknown => (StatusCode::INTERNAL_SERVER_ERROR, Json(self.to_string())).into_response(),
+    }
+  }
+}
+
+/// Update learning goal handler, replacing the description and the curriculum of a learning goal
+#[utoipa::path(
+  put,
+  path = "/chatmate/{chatmate_id}/goals/{learning_goal_id}",
+  tag = EPIS_CATEGORY,
+  params(
+    ("chatmate_id" = String, Path, description = "Id of the chatmate"),
+    ("learning_goal_id" = String, Path, description = "Id of the learning goal"),
+  ),
+  request_body = LearningGoalRequestBody,
+  responses(
+    (status = OK, body = LearningGoalItem, content_type = "application/json"),
+    (status = BAD_REQUEST, body = String, content_type = "application/json"),
+    (status = NOT_FOUND, body = String, content_type = "application/json"),
+    (status = INTERNAL_SERVER_ERROR, body = String, content_type = "application/json"),
+  )
+)]
+pub async fn update_learning_goal<E: Epis, UM: UserManagement>(
+  State(app_state): State<AppState<E, UM>>,
+  Extension(user): Extension<User>,
+  Path((chatmate_id, learning_goal_id)): Path<(Id, Id)>,
+  Json(request): Json<LearningGoalRequestBody>,
+) -> Result<Json<LearningGoalItem>, UpdateLearningGoalApiError> {
+  let learning_goal = app_state
+    .epis()
+    .update_learning_goal(user.id(), &chatmate_id, &learning_goal_id, request.into())
+    .await
+    .map_err(|e| match e {
+      EpisError::InvalidLearningGoal => UpdateLearningGoalApiError::InvalidLearningGoal,
+      _ => UpdateLearningGoalApiError::Unknown,
+    })?
+    .ok_or(UpdateLearningGoalApiError::NotFound)?;
+
+  Ok(Json(LearningGoalItem::from(learning_goal)))
+}
@@ -3,14 +3,99 @@
 use std::sync::Arc;
 
 use axum::extract::ws::{Message, WebSocket};
+use serde::Serialize;
 use tokio::sync::Mutex;
 use tracing::{instrument, trace, warn};
 
 use crate::domain::{
-  models::{EpisError, SimpleBytes},
+  models::{ChatEvent, Correction, CorrectionCategory, EpisError, ScenarioProgress, SimpleBytes},
   ports::AudioDuplex,
 };
 
+/// Serialized correction of a user message
+#[derive(Debug, Serialize)]
+#[allow(clippy::missing_docs_in_private_items)]
+struct WsCorrection {
+  original: String,
+  corrected: String,
+  category: &'static str,
+  explanation: String,
+}
+
+impl From<Correction> for WsCorrection {
+  fn from(correction: Correction) -> Self {
+    let category = match correction.category() {
+      CorrectionCategory::Grammar => "grammar",
+      CorrectionCategory::Vocabulary => "vocabulary",
+      CorrectionCategory::WordOrder => "word_order",
+      CorrectionCategory::Usage => "usage",
+    };
+
+    Self {
+      original: correction.original().clone(),
+      corrected: correction.corrected().clone(),
+      category,
+      explanation: correction.explanation().clone(),
+    }
+  }
+}
+
+/// Serialized progress of a role-play scenario
+#[derive(Debug, Serialize)]
+#[allow(clippy::missing_docs_in_private_items)]
+struct WsScenarioProgress {
+  scenario_id: String,
+  completed_goals: Vec<String>,
+  remaining_goals: Vec<String>,
+  completed: bool,
+}
+
+impl From<ScenarioProgress> for WsScenarioProgress {
+  fn from(scenario_progress: ScenarioProgress) -> Self {
+    let (scenario, completed_goals, completed) = (
+      scenario_progress.scenario(),
+      scenario_progress.completed_goals(),
+      *scenario_progress.completed(),
+    );
+    let remaining_goals = scenario
+      .goals()
+      .iter()
+      .filter(|goal| !completed_goals.contains(goal))
+      .cloned()
+      .collect();
+
+    Self {
+      scenario_id: scenario.id().to_string().to_lowercase(),
+      completed_goals: completed_goals.clone(),
+      remaining_goals,
+      completed,
+    }
+  }
+}
+
+/// Serialized [ChatEvent], sent as a json text message tagged by its type, so that it can be told
+/// apart from the binary audio messages
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+#[allow(clippy::missing_docs_in_private_items)]
+enum WsChatEvent {
+  Corrections { corrections: Vec<WsCorrection> },
+  ScenarioProgress(WsScenarioProgress),
+}
+
+impl From<ChatEvent> for WsChatEvent {
+  fn from(event: ChatEvent) -> Self {
+    match event {
+      ChatEvent::Corrections(corrections) => Self::Corrections {
+        corrections: corrections.into_iter().map(WsCorrection::from).collect(),
+      },
+      ChatEvent::ScenarioProgress(scenario_progress) => {
+        Self::ScenarioProgress(scenario_progress.into())
+      }
+    }
+  }
+}
+
 impl AudioDuplex for Arc<Mutex<WebSocket>> {
   #[instrument(skip_all)]
   async fn send(&mut self, audio_message: SimpleBytes) -> Result<(), EpisError> {
@@ -23,6 +108,21 @@ impl AudioDuplex for Arc<Mutex<WebSocket>> {
       .map_err(|_| EpisError::DuplexError)
   }
 
+  #[instrument(skip_all)]
+  async fn send_event(&mut self, event: ChatEvent) -> Result<(), EpisError> {
+    let event = serde_json::to_string(&WsChatEvent::from(event))
+      .inspect_err(|error| warn!(%error, "Failed to serialize chat event"))
+      .map_err(|_| EpisError::DuplexError)?;
+
+    self
+      .lock()
+      .await
+      .send(Message::Text(event.into()))
+      .await
+      .inspect_err(|error| warn!(%error, "Failed to send chat event to user"))
+      .map_err(|_| EpisError::DuplexError)
+  }
+
   #[instrument(skip_all)]
   async fn receive(&mut self) -> Result<SimpleBytes, EpisError> {
     if let Some(raw_message) = self.lock().await.recv().await {
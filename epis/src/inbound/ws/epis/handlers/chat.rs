@@ -14,7 +14,7 @@ use tracing::{debug, instrument, warn};
 
 use crate::{
   domain::{
-    models::{EpisAudioMessageFormat, Id, User, UserId},
+    models::{EpisAudioMessageFormat, Id, ScenarioId, User, UserId},
     ports::{Epis, UserManagement},
   },
   inbound::http::AppState,
@@ -25,6 +25,8 @@ use crate::{
 pub struct VoiceChatQueryParams {
   /// Format of audio messages
   audio_format: Option<String>,
+  /// Role-play scenario of the chat session, e.g. cafe, played instead of small talk
+  scenario_id: Option<String>,
   /// JWT for authorization
   #[allow(dead_code)]
   jwt: String,
@@ -43,13 +45,31 @@ pub async fn chat<E: Epis, UM: UserManagement>(
     .audio_format
     .unwrap_or(EpisAudioMessageFormat::default().to_string());
 
+  let scenario_id = match query.scenario_id.as_deref().map(ScenarioId::from_str) {
+    None => None,
+    Some(Ok(scenario_id)) => Some(scenario_id),
+    Some(Err(_)) => {
+      debug!(user_id="", %chatmate_id, scenario_id=?query.scenario_id, "Chat session did not start because of unknown scenario");
+
+      return (StatusCode::BAD_REQUEST, "Unknown scenario").into_response();
+    }
+  };
+
   if let Ok(audio_format) = EpisAudioMessageFormat::from_str(&audio_format) {
     let user_id = user.id().to_string();
 
-    debug!(%user_id, %chatmate_id, %audio_format, "Chat session started");
+    debug!(%user_id, %chatmate_id, %audio_format, ?scenario_id, "Chat session started");
 
-    return ws
-      .on_upgrade(|socket| handle_socket(socket, app_state, user_id, chatmate_id, audio_format));
+    return ws.on_upgrade(|socket| {
+      handle_socket(
+        socket,
+        app_state,
+        user_id,
+        chatmate_id,
+        audio_format,
+        scenario_id,
+      )
+    });
   }
 
   debug!(user_id="", %chatmate_id, %audio_format, "Chat session did not start because of invalid audio format");
@@ -68,12 +88,19 @@ async fn handle_socket<E: Epis, UM: UserManagement>(
   user_id: UserId,
   chatmate_id: Id,
   audio_format: EpisAudioMessageFormat,
+  scenario_id: Option<ScenarioId>,
 ) {
   let mut duplex = Arc::new(Mutex::new(socket));
 
   app_state
     .epis()
-    .chat(&user_id, &chatmate_id, &mut duplex, &audio_format)
+    .chat(
+      &user_id,
+      &chatmate_id,
+      &mut duplex,
+      &audio_format,
+      scenario_id.as_ref(),
+    )
     .await
     .inspect_err(|error| warn!(%error, "Epis chat loop returned with an error"))
     .unwrap_or_default()
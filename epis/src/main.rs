@@ -3,19 +3,33 @@
 //! This application provides an interactive interface for learning and knowledge acquisition,
 //! currently supporting language learning through LLM-powered conversations.
 
-use anyhow::Result;
-use clerk_rs::{ClerkConfiguration, clerk::Clerk};
-use std::{net::SocketAddr, sync::Arc};
-use tracing::info;
+use anyhow::{Context, Result, anyhow};
+use clerk_rs::ClerkConfiguration;
+use std::{net::SocketAddr, str::FromStr, sync::Arc};
+use tracing::{info, warn};
 
 use crate::{
   config::Config,
   domain::{
     epis::Epis,
+    models::CefrProgressionMode,
+    ports::RealtimeAiAgent as RealtimeAiAgentService,
     realtime_ai_agent::{RealtimeAiAgent, RealtimeAiAgentModels},
+    recall::EmbeddingHistoryRetriever,
+    review_scheduler::{AnyReviewScheduler, FsrsScheduler, Sm2Scheduler},
   },
   inbound::http::HttpServer,
-  outbound::postgres::Postgres,
+  outbound::{
+    clerk::Clerk,
+    fake::FakeUserManagement,
+    history_retriever::{AnyHistoryRetriever, Bm25HistoryRetriever},
+    moderation::ProviderModeration,
+    openai_realtime::OpenAiRealtimeAiAgent,
+    postgres::Postgres,
+    provider_registry::RoutedAiGateway,
+    tts_cache::TtsCache,
+    user_management::AnyUserManagement,
+  },
 };
 
 mod config;
@@ -23,38 +37,126 @@ mod domain;
 mod inbound;
 mod outbound;
 
-/// Main entry point for the Epis application
-#[tokio::main]
-async fn main() -> Result<()> {
-  tracing_subscriber::fmt::init();
+/// Env variable for overriding the config file path, e.g. for using a dev config
+const CONFIG_PATH_ENV: &str = "EPIS_CONFIG_PATH";
 
-  let config = Config::init::<&str>(None);
+/// Implementations of the realtime ai agent
+#[derive(Debug, Clone)]
+enum RealtimeAiAgentImplementation {
+  /// Chaining the stt, llm and tts ai models
+  Chained,
+  /// A speech to speech OpenAI Realtime api session per chat
+  OpenAiRealtime,
+}
 
-  let clerk_config = ClerkConfiguration::new(None, None, Some(config.clerk_sk().to_string()), None);
+impl FromStr for RealtimeAiAgentImplementation {
+  type Err = anyhow::Error;
 
-  let postgres = Arc::new(Postgres::try_new(config.database_url()).await?);
-  let openai = Arc::new(crate::outbound::openai::OpenAi::new(
-    config.openai_api_key(),
-    None,
-  ));
-  let clerk = Arc::new(crate::outbound::clerk::Clerk::new(Clerk::new(clerk_config)));
-  let realtime_ai_agent = Arc::new(RealtimeAiAgent::new(
-    openai.clone(),
-    clerk.clone(),
-    postgres.clone(),
-    RealtimeAiAgentModels::new(
-      config.ai_models().llm().model().to_string(),
-      config.ai_models().stt().model().to_string(),
-      config.ai_models().tts().model().to_string(),
-    ),
+  fn from_str(implementation: &str) -> Result<Self, Self::Err> {
+    match implementation {
+      "chained" => Ok(Self::Chained),
+      "openai-realtime" => Ok(Self::OpenAiRealtime),
+      unknown => Err(anyhow!(
+        "Unknown realtime ai agent implementation `{unknown}`, expected one of: chained, openai-realtime"
+      )),
+    }
+  }
+}
+
+/// Spaced repetition algorithms scheduling vocab reviews
+#[derive(Debug, Clone)]
+enum ReviewSchedulerAlgorithm {
+  /// SuperMemo 2
+  Sm2,
+  /// Free Spaced Repetition Scheduler
+  Fsrs,
+}
+
+impl FromStr for ReviewSchedulerAlgorithm {
+  type Err = anyhow::Error;
+
+  fn from_str(algorithm: &str) -> Result<Self, Self::Err> {
+    match algorithm {
+      "sm2" => Ok(Self::Sm2),
+      "fsrs" => Ok(Self::Fsrs),
+      unknown => Err(anyhow!(
+        "Unknown review scheduler `{unknown}`, expected one of: sm2, fsrs"
+      )),
+    }
+  }
+}
+
+impl From<ReviewSchedulerAlgorithm> for AnyReviewScheduler {
+  fn from(algorithm: ReviewSchedulerAlgorithm) -> Self {
+    match algorithm {
+      ReviewSchedulerAlgorithm::Sm2 => Self::Sm2(Sm2Scheduler),
+      ReviewSchedulerAlgorithm::Fsrs => Self::Fsrs(FsrsScheduler),
+    }
+  }
+}
+
+/// Build the retriever of past exchanges, retrieving them by embeddings if an embedding model is
+/// configured, or by BM25 otherwise
+fn history_retriever(
+  config: &Config,
+  ai_gateway: Arc<RoutedAiGateway>,
+  postgres: Arc<Postgres>,
+) -> AnyHistoryRetriever<RoutedAiGateway, Postgres> {
+  match config.ai_models().embedding() {
+    Some(embedding) => AnyHistoryRetriever::Embedding(EmbeddingHistoryRetriever::new(
+      ai_gateway,
+      postgres,
+      embedding.model().to_string(),
+    )),
+    None => {
+      info!("No embedding model is configured, retrieving past exchanges by BM25");
+      AnyHistoryRetriever::Bm25(Bm25HistoryRetriever::new(postgres))
+    }
+  }
+}
+
+/// Build the user management from the config, authenticating every request as the dev user if
+/// it's set, and by Clerk otherwise
+///
+/// # Errors
+/// If no dev user is set and there is no Clerk secret, an error is returned
+fn user_management(config: &Config) -> Result<AnyUserManagement> {
+  if let Some(dev_user_id) = config.dev_user_id() {
+    warn!(%dev_user_id, "Auth is bypassed, authenticating every request as the dev user");
+    return Ok(AnyUserManagement::Fake(FakeUserManagement::new(
+      dev_user_id.clone(),
+    )));
+  }
+
+  let clerk_sk = config
+    .clerk_sk()
+    .clone()
+    .context("A Clerk secret is required unless a dev user is set")?;
+  let clerk_config = ClerkConfiguration::new(None, None, Some(clerk_sk), None);
+
+  Ok(AnyUserManagement::Clerk(Clerk::new(
+    clerk_rs::clerk::Clerk::new(clerk_config),
+  )))
+}
+
+/// Start the http server with a realtime ai agent
+async fn serve<RAA: RealtimeAiAgentService>(
+  config: &Config,
+  postgres: Arc<Postgres>,
+  user_management: Arc<AnyUserManagement>,
+  realtime_ai_agent: Arc<RAA>,
+) -> Result<()> {
+  let epis = Arc::new(Epis::new(
+    postgres,
+    user_management.clone(),
+    realtime_ai_agent,
   ));
-  let epis = Arc::new(Epis::new(postgres.clone(), realtime_ai_agent.clone()));
 
   HttpServer::try_new(
     SocketAddr::from(([0, 0, 0, 0], config.port().to_owned())),
     config.app_url(),
     epis,
-    clerk,
+    user_management,
   )?
   .start()
   .await?;
@@ -63,3 +165,89 @@ async fn main() -> Result<()> {
 
   Ok(())
 }
+
+/// Main entry point for the Epis application
+#[tokio::main]
+async fn main() -> Result<()> {
+  tracing_subscriber::fmt::init();
+
+  let config = Config::init(std::env::var(CONFIG_PATH_ENV).ok());
+
+  let postgres = Arc::new(Postgres::try_new(config.database_url()).await?);
+  let user_management = Arc::new(user_management(&config)?);
+
+  let review_scheduler = Arc::new(AnyReviewScheduler::from(
+    ReviewSchedulerAlgorithm::from_str(config.review_scheduler().as_deref().unwrap_or("fsrs"))?,
+  ));
+
+  let cefr_progression = config.cefr_progression().as_deref().unwrap_or("auto");
+  let cefr_progression_mode = CefrProgressionMode::from_str(cefr_progression).map_err(|_| {
+    anyhow!("Unknown CEFR progression mode `{cefr_progression}`, expected one of: auto, confirm")
+  })?;
+
+  let realtime_ai_agent_config = config.realtime_ai_agent();
+  match RealtimeAiAgentImplementation::from_str(realtime_ai_agent_config.implementation())? {
+    RealtimeAiAgentImplementation::Chained => {
+      let mut ai_gateway =
+        RoutedAiGateway::try_from_config(config.ai_models(), config.openai_api_key().as_deref())?;
+      if let Some(tts_cache_config) = config.tts_cache() {
+        ai_gateway = ai_gateway
+          .with_tts_cache(TtsCache::try_from_config(tts_cache_config, postgres.clone()).await?);
+      }
+      let moderation = ProviderModeration::try_from_config(
+        config.moderation().as_ref(),
+        config.openai_api_key().as_deref(),
+      )?;
+      let ai_gateway = Arc::new(ai_gateway);
+      let history_retriever = history_retriever(&config, ai_gateway.clone(), postgres.clone());
+      let realtime_ai_agent = Arc::new(RealtimeAiAgent::new(
+        ai_gateway,
+        user_management.clone(),
+        postgres.clone(),
+        Arc::new(moderation),
+        review_scheduler,
+        Arc::new(history_retriever),
+        cefr_progression_mode,
+        RealtimeAiAgentModels::new(
+          config.ai_models().llm().model().to_string(),
+          config.ai_models().stt().model().to_string(),
+          config.ai_models().tts().model().to_string(),
+        ),
+      ));
+      serve(&config, postgres, user_management, realtime_ai_agent).await?;
+    }
+    RealtimeAiAgentImplementation::OpenAiRealtime => {
+      let moderation = ProviderModeration::try_from_config(
+        config.moderation().as_ref(),
+        config.openai_api_key().as_deref(),
+      )?;
+      let ai_gateway = Arc::new(RoutedAiGateway::try_from_config(
+        config.ai_models(),
+        config.openai_api_key().as_deref(),
+      )?);
+      let history_retriever = history_retriever(&config, ai_gateway.clone(), postgres.clone());
+      let api_key = realtime_ai_agent_config
+        .api_key()
+        .clone()
+        .or(config.openai_api_key().clone())
+        .context("An api key is required for openai-realtime realtime ai agent")?;
+      let realtime_ai_agent = Arc::new(OpenAiRealtimeAiAgent::new(
+        ai_gateway,
+        user_management.clone(),
+        postgres.clone(),
+        Arc::new(moderation),
+        review_scheduler,
+        Arc::new(history_retriever),
+        cefr_progression_mode,
+        config.ai_models().llm().model().to_string(),
+        realtime_ai_agent_config.model().clone(),
+        realtime_ai_agent_config.transcription_model().clone(),
+        realtime_ai_agent_config.base_url().clone(),
+        api_key,
+      ));
+      serve(&config, postgres, user_management, realtime_ai_agent).await?;
+    }
+  }
+
+  Ok(())
+}
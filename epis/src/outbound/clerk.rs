@@ -130,4 +130,45 @@ impl UserManagement for Clerk {
       None => None,
     })
   }
+
+  async fn set_cefr_level(
+    &self,
+    user_id: &UserId,
+    language: &ChatMateLanguage,
+    cefr_level: &CefrLevel,
+  ) -> Result<(), EpisError> {
+    let mut user_metadata = self
+      .get_user_metadata(user_id)
+      .await
+      .inspect_err(|error| {
+        warn!(%error, "Error while getting user metadata");
+      })
+      .map_err(|_| EpisError::Unknown)?;
+
+    user_metadata
+      .cefr_level
+      .retain(|cefr_item| cefr_item.language != language.to_string());
+    user_metadata.cefr_level.push(UserCefrLevel {
+      cefr_level: cefr_level.to_string(),
+      language: language.to_string(),
+    });
+
+    ClerkUserApi::update_user_metadata(
+      &self.0,
+      user_id,
+      Some(clerk_rs::models::UpdateUserMetadataRequest {
+        public_metadata: Some(json!({
+            "credit": user_metadata.credit,
+            "cefr_level": user_metadata.cefr_level,
+        })),
+        private_metadata: None,
+        unsafe_metadata: None,
+      }),
+    )
+    .await
+    .inspect_err(|error| warn!(%error, "Error while updating user CEFR level"))
+    .map_err(|_| EpisError::Unknown)?;
+
+    Ok(())
+  }
 }
@@ -0,0 +1,331 @@
+//! Fake AI provider, returning scripted responses from a fixture file, and fake user management,
+//! authenticating every request as a single user
+
+use std::{
+  collections::HashMap,
+  hash::{DefaultHasher, Hash, Hasher},
+  io::Cursor,
+  path::{Path, PathBuf},
+  sync::{
+    Arc, Mutex,
+    atomic::{AtomicUsize, Ordering},
+  },
+};
+
+use anyhow::Context;
+use figment::{
+  Figment,
+  providers::{Format, Yaml},
+};
+use hound::{SampleFormat, WavSpec, WavWriter};
+use serde::Deserialize;
+use tracing::{debug, warn};
+
+use crate::{
+  domain::{
+    models::{
+      AiUsage, AuthStatus, CefrAssessment, CefrLevel, ChatMateLanguage, ChatMessage, ChatSummary,
+      CreditAuthStatus, EmbeddingResponse, EpisAudioMessageFormat, EpisError, GenerationResponse,
+      SimpleBytes, TextToSpeechResponse, TranscribedWord, TranscriptionResponse, User, UserId,
+    },
+    ports::{AiGateway, UserManagement},
+  },
+  outbound::structured_output::{ApiCefrAssessment, ApiChatSummary, ApiResponse},
+};
+
+/// Sample rate of the silent speech generated when no speech file is provided
+const SILENCE_SAMPLE_RATE: u32 = 24000;
+/// Dimensions of the embeddings
+const EMBEDDING_DIMENSIONS: usize = 64;
+/// Credit of the fake user, which is never spent
+const FAKE_USER_CREDIT: i32 = i32::MAX;
+
+/// Fixture of a [FakeAiGateway]. Transcriptions, generations, assessments and summaries are
+/// replayed in order, starting over when exhausted.
+#[derive(Debug, Clone, Deserialize)]
+pub struct FakeAiGatewayFixture {
+  /// Scripted transcriptions
+  transcriptions: Vec<String>,
+  /// Scripted generations, in the same format the LLM is asked to reply with
+  generations: Vec<ApiResponse>,
+  /// Scripted CEFR level assessments, in the same format the LLM is asked to reply with
+  #[serde(default)]
+  cefr_assessments: Vec<ApiCefrAssessment>,
+  /// Scripted chat summaries, in the same format the LLM is asked to reply with
+  #[serde(default)]
+  chat_summaries: Vec<ApiChatSummary>,
+  /// Audio file returned for every text to speech call, defaulting to one second of silence
+  #[serde(default)]
+  speech_path: Option<PathBuf>,
+}
+
+/// A deterministic implementation of [AiGateway], useful for development and tests. Embeddings are
+/// not scripted, but computed by hashing words.
+#[derive(Debug, Clone)]
+pub struct FakeAiGateway {
+  /// Scripted transcriptions
+  transcriptions: Arc<Vec<String>>,
+  /// Scripted generations
+  generations: Arc<Vec<ApiResponse>>,
+  /// Scripted CEFR level assessments
+  cefr_assessments: Arc<Vec<ApiCefrAssessment>>,
+  /// Scripted chat summaries
+  chat_summaries: Arc<Vec<ApiChatSummary>>,
+  /// Audio bytes returned for every text to speech call
+  speech: Arc<SimpleBytes>,
+  /// Index of the next transcription to return
+  next_transcription: Arc<AtomicUsize>,
+  /// Index of the next generation to return
+  next_generation: Arc<AtomicUsize>,
+  /// Index of the next CEFR level assessment to return
+  next_cefr_assessment: Arc<AtomicUsize>,
+  /// Index of the next chat summary to return
+  next_chat_summary: Arc<AtomicUsize>,
+}
+
+impl FakeAiGateway {
+  /// Construct a [FakeAiGateway] from a yaml fixture file
+  ///
+  /// # Errors
+  /// If the fixture or the speech file cannot be read, or the fixture is invalid, an error is
+  /// returned
+  pub fn try_from_fixture<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
+    let fixture: FakeAiGatewayFixture = Figment::from(Yaml::file_exact(path.as_ref()))
+      .extract()
+      .with_context(|| {
+        format!(
+          "Invalid fake ai gateway fixture {}",
+          path.as_ref().display()
+        )
+      })?;
+
+    let speech = match fixture.speech_path {
+      Some(speech_path) => std::fs::read(&speech_path)
+        .with_context(|| format!("Cannot read speech file {}", speech_path.display()))?,
+      None => silence()?,
+    };
+
+    Ok(Self {
+      transcriptions: Arc::new(fixture.transcriptions),
+      generations: Arc::new(fixture.generations),
+      cefr_assessments: Arc::new(fixture.cefr_assessments),
+      chat_summaries: Arc::new(fixture.chat_summaries),
+      speech: Arc::new(speech),
+      next_transcription: Arc::new(AtomicUsize::new(0)),
+      next_generation: Arc::new(AtomicUsize::new(0)),
+      next_cefr_assessment: Arc::new(AtomicUsize::new(0)),
+      next_chat_summary: Arc::new(AtomicUsize::new(0)),
+    })
+  }
+}
+
+/// Get the next scripted item, starting over when exhausted
+///
+/// # Errors
+/// If there is no scripted item, [EpisError::ProviderError] is returned
+fn next_scripted<T: Clone>(items: &[T], next: &AtomicUsize) -> Result<T, EpisError> {
+  if items.is_empty() {
+    warn!("Fake ai gateway fixture has no scripted item for this operation");
+    return Err(EpisError::ProviderError);
+  }
+
+  let index = next.fetch_add(1, Ordering::Relaxed) % items.len();
+  Ok(items[index].clone())
+}
+
+/// Embed a text by hashing its lowercase words into a normalized bag of words vector, so that texts
+/// sharing words are similar
+fn hashed_embedding(text: &str) -> Vec<f32> {
+  let mut embedding = vec![0.0; EMBEDDING_DIMENSIONS];
+  for word in text
+    .split(|char: char| !char.is_alphanumeric())
+    .filter(|word| !word.is_empty())
+  {
+    let mut hasher = DefaultHasher::new();
+    word.to_lowercase().hash(&mut hasher);
+    embedding[hasher.finish() as usize % EMBEDDING_DIMENSIONS] += 1.0;
+  }
+
+  let norm = embedding
+    .iter()
+    .map(|value| value * value)
+    .sum::<f32>()
+    .sqrt();
+  if norm > 0.0 {
+    embedding.iter_mut().for_each(|value| *value /= norm);
+  }
+
+  embedding
+}
+
+/// Generate one second of silent mono wav audio
+///
+/// # Errors
+/// If wav cannot be written, an error is returned
+fn silence() -> anyhow::Result<SimpleBytes> {
+  let mut cursor = Cursor::new(Vec::new());
+  let mut writer = WavWriter::new(
+    &mut cursor,
+    WavSpec {
+      channels: 1,
+      sample_rate: SILENCE_SAMPLE_RATE,
+      bits_per_sample: 16,
+      sample_format: SampleFormat::Int,
+    },
+  )?;
+  for _ in 0..SILENCE_SAMPLE_RATE {
+    writer.write_sample(0i16)?;
+  }
+  writer.finalize()?;
+
+  Ok(cursor.into_inner())
+}
+
+impl AiGateway for FakeAiGateway {
+  async fn generate(
+    &self,
+    _model: &str,
+    _messages: &[ChatMessage],
+  ) -> Result<GenerationResponse, EpisError> {
+    let ai_reply = next_scripted(&self.generations, &self.next_generation)?;
+    debug!("Scripted generation returned");
+
+    Ok(ai_reply.into_generation_response(AiUsage::default()))
+  }
+
+  async fn assess_cefr_level(
+    &self,
+    _model: &str,
+    _messages: &[ChatMessage],
+  ) -> Result<CefrAssessment, EpisError> {
+    let cefr_assessment = next_scripted(&self.cefr_assessments, &self.next_cefr_assessment)?;
+    debug!("Scripted CEFR level assessment returned");
+
+    Ok(cefr_assessment.into_cefr_assessment(AiUsage::default()))
+  }
+
+  async fn summarize_chat(
+    &self,
+    _model: &str,
+    _messages: &[ChatMessage],
+  ) -> Result<ChatSummary, EpisError> {
+    let chat_summary = next_scripted(&self.chat_summaries, &self.next_chat_summary)?;
+    debug!("Scripted chat summary returned");
+
+    Ok(chat_summary.into_chat_summary(AiUsage::default()))
+  }
+
+  async fn transcribe(
+    &self,
+    _model: &str,
+    _audio_bytes: SimpleBytes,
+    _audio_format: EpisAudioMessageFormat,
+    _instructions: Option<&str>,
+  ) -> Result<TranscriptionResponse, EpisError> {
+    let transcription = next_scripted(&self.transcriptions, &self.next_transcription)?;
+    debug!("Scripted transcription returned");
+
+    // Scripted words are recognized with full confidence, without timestamps
+    let words = transcription
+      .split_whitespace()
+      .map(|word| TranscribedWord::new(word.to_string(), None, None, 1.0))
+      .collect();
+
+    Ok(TranscriptionResponse::new(
+      transcription,
+      words,
+      AiUsage::default(),
+    ))
+  }
+
+  async fn text_to_speech(
+    &self,
+    _model: &str,
+    _text: String,
+    _voice: &str,
+    _instructions: Option<&str>,
+  ) -> Result<TextToSpeechResponse, EpisError> {
+    debug!("Scripted speech returned");
+
+    Ok(TextToSpeechResponse::new(
+      self.speech.as_ref().clone(),
+      AiUsage::default(),
+    ))
+  }
+
+  async fn embed(&self, _model: &str, texts: &[String]) -> Result<EmbeddingResponse, EpisError> {
+    debug!("Hashed embeddings returned");
+
+    Ok(EmbeddingResponse::new(
+      texts.iter().map(|text| hashed_embedding(text)).collect(),
+      AiUsage::default(),
+    ))
+  }
+}
+
+/// A [UserManagement] authenticating any jwt as a single user with unlimited credit, and keeping
+/// CEFR levels in memory, so that no auth provider is needed. Only meant for development.
+#[derive(Debug, Clone)]
+pub struct FakeUserManagement {
+  /// Id of the user every jwt is authenticated as
+  user_id: UserId,
+  /// CEFR levels by user and language
+  cefr_levels: Arc<Mutex<HashMap<(UserId, String), CefrLevel>>>,
+}
+
+impl FakeUserManagement {
+  /// Construct a [FakeUserManagement] authenticating every jwt as a user
+  pub fn new(user_id: UserId) -> Self {
+    Self {
+      user_id,
+      cefr_levels: Arc::new(Mutex::new(HashMap::new())),
+    }
+  }
+}
+
+impl UserManagement for FakeUserManagement {
+  async fn authenticate_jwt(&self, _jwt: &str) -> Result<AuthStatus, EpisError> {
+    Ok(AuthStatus::Authenticated(User::new(
+      self.user_id.clone(),
+      FAKE_USER_CREDIT,
+    )))
+  }
+
+  async fn authorize_by_credit(&self, _user_id: &UserId) -> Result<CreditAuthStatus, EpisError> {
+    Ok(CreditAuthStatus::Authorized)
+  }
+
+  async fn spend_credit(&self, _user_id: &UserId) -> Result<(), EpisError> {
+    Ok(())
+  }
+
+  async fn get_cefr_level(
+    &self,
+    user_id: &UserId,
+    language: &ChatMateLanguage,
+  ) -> Result<Option<CefrLevel>, EpisError> {
+    Ok(
+      self
+        .cefr_levels
+        .lock()
+        .expect("Cefr levels lock is not poisoned")
+        .get(&(user_id.clone(), language.to_string()))
+        .cloned(),
+    )
+  }
+
+  async fn set_cefr_level(
+    &self,
+    user_id: &UserId,
+    language: &ChatMateLanguage,
+    cefr_level: &CefrLevel,
+  ) -> Result<(), EpisError> {
+    self
+      .cefr_levels
+      .lock()
+      .expect("Cefr levels lock is not poisoned")
+      .insert((user_id.clone(), language.to_string()), cefr_level.clone());
+
+    Ok(())
+  }
+}
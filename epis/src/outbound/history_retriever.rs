@@ -0,0 +1,242 @@
+//! History retrievers, retrieving the past exchanges of chats related to a user message
+
+use std::{
+  collections::HashMap,
+  sync::{Arc, Mutex},
+  time::Instant,
+};
+
+use bm25::{Document, LanguageMode, SearchEngine, SearchEngineBuilder};
+use tracing::{debug, warn};
+use uuid::Uuid;
+
+use crate::domain::{
+  models::{AiUsage, EpisError, Id, PastExchange, RetrievedExchanges},
+  ports::{AiGateway, EpisRepository, HistoryRetriever},
+  recall::EmbeddingHistoryRetriever,
+};
+
+/// Max number of the last past exchanges of a chat indexed when building its BM25 index
+const BM25_MAX_EXCHANGES: u16 = 1000;
+/// Max number of chats whose BM25 index is kept in memory, the least recently used ones being
+/// dropped beyond it
+const BM25_MAX_INDEXED_CHATS: usize = 256;
+
+/// Text of a past exchange indexed by BM25, so that an exchange matches a user message by any of
+/// its messages
+fn bm25_document_text(past_exchange: &PastExchange) -> String {
+  match past_exchange.ai_reply() {
+    Some(ai_reply) => format!("{}\n{ai_reply}", past_exchange.user_message()),
+    None => past_exchange.user_message().clone(),
+  }
+}
+
+/// In-memory BM25 index of the past exchanges of a chat
+#[derive(Debug)]
+struct Bm25ChatIndex {
+  /// Search engine over the exchanges, keyed by their position
+  search_engine: SearchEngine<usize>,
+  /// Indexed exchanges, in ascending order
+  past_exchanges: Vec<PastExchange>,
+  /// When the index was last used
+  last_used: Instant,
+}
+
+impl Bm25ChatIndex {
+  /// Build the index of past exchanges. Learners mix the language they learn with the languages
+  /// they know, so the language of each text is detected.
+  fn new(past_exchanges: Vec<PastExchange>) -> Self {
+    let search_engine = SearchEngineBuilder::<usize>::with_documents(
+      LanguageMode::Detect,
+      past_exchanges
+        .iter()
+        .enumerate()
+        .map(|(position, past_exchange)| {
+          Document::new(position, bm25_document_text(past_exchange))
+        }),
+    )
+    .build();
+
+    Self {
+      search_engine,
+      past_exchanges,
+      last_used: Instant::now(),
+    }
+  }
+
+  /// Add new past exchanges to the index
+  fn extend(&mut self, past_exchanges: Vec<PastExchange>) {
+    for past_exchange in past_exchanges {
+      self.search_engine.upsert(Document::new(
+        self.past_exchanges.len(),
+        bm25_document_text(&past_exchange),
+      ));
+      self.past_exchanges.push(past_exchange);
+    }
+  }
+}
+
+/// A [HistoryRetriever] ranking past exchanges lexically by BM25, needing no embedding model. The
+/// index of each chat is kept in memory, built from the repository on demand, and extended with
+/// the new exchanges of the chat.
+#[derive(Debug, Clone)]
+pub struct Bm25HistoryRetriever<ER: EpisRepository> {
+  /// Epis repository, for past exchanges
+  epis_repo: Arc<ER>,
+  /// Index of each chat, by chatmate id
+  indices: Arc<Mutex<HashMap<Uuid, Bm25ChatIndex>>>,
+}
+
+impl<ER: EpisRepository> Bm25HistoryRetriever<ER> {
+  /// Construct a [Bm25HistoryRetriever] with no index built yet
+  pub fn new(epis_repo: Arc<ER>) -> Self {
+    Self {
+      epis_repo,
+      indices: Arc::new(Mutex::new(HashMap::new())),
+    }
+  }
+
+  /// Lock the indices, recovering them if a thread panicked while holding the lock, as indices are
+  /// always left consistent
+  fn lock_indices(&self) -> std::sync::MutexGuard<'_, HashMap<Uuid, Bm25ChatIndex>> {
+    self
+      .indices
+      .lock()
+      .unwrap_or_else(|poisoned| poisoned.into_inner())
+  }
+
+  /// Build the index of a chat from its last past exchanges, unless it's already built
+  ///
+  /// # Errors
+  /// If getting past exchanges fails, [EpisError::RepoError] is returned
+  async fn ensure_index(&self, chatmate_id: &Id) -> Result<(), EpisError> {
+    if self.lock_indices().contains_key(chatmate_id.as_ref()) {
+      return Ok(());
+    }
+
+    let past_exchanges = self
+      .epis_repo
+      .get_past_exchanges(chatmate_id, None, BM25_MAX_EXCHANGES)
+      .await
+      .inspect_err(|error| warn!(%error, "Error while getting past exchanges"))
+      .map_err(|_| EpisError::RepoError)?;
+    debug!(
+      indexed_exchanges = past_exchanges.len(),
+      "Chat BM25 index built"
+    );
+
+    let mut indices = self.lock_indices();
+    if indices.len() >= BM25_MAX_INDEXED_CHATS
+      && let Some(least_recently_used) = indices
+        .iter()
+        .min_by_key(|(_, index)| index.last_used)
+        .map(|(chatmate_id, _)| *chatmate_id)
+    {
+      indices.remove(&least_recently_used);
+    }
+    indices
+      .entry(*chatmate_id.as_ref())
+      .or_insert_with(|| Bm25ChatIndex::new(past_exchanges));
+
+    Ok(())
+  }
+}
+
+impl<ER: EpisRepository> HistoryRetriever for Bm25HistoryRetriever<ER> {
+  async fn index(&self, chatmate_id: &Id) -> Result<(), EpisError> {
+    // An index not built yet includes the new exchanges once it's built
+    let Some(last_message_id) = self.lock_indices().get(chatmate_id.as_ref()).map(|index| {
+      index
+        .past_exchanges
+        .last()
+        .map(|past_exchange| past_exchange.id().clone())
+    }) else {
+      return Ok(());
+    };
+
+    let past_exchanges = self
+      .epis_repo
+      .get_past_exchanges(chatmate_id, last_message_id.as_ref(), BM25_MAX_EXCHANGES)
+      .await
+      .inspect_err(|error| warn!(%error, "Error while getting new past exchanges"))
+      .map_err(|_| EpisError::RepoError)?;
+    if let Some(index) = self.lock_indices().get_mut(chatmate_id.as_ref()) {
+      index.extend(past_exchanges);
+    }
+
+    Ok(())
+  }
+
+  async fn retrieve(
+    &self,
+    chatmate_id: &Id,
+    user_message: &str,
+    recent_messages: u8,
+    limit: u8,
+  ) -> Result<RetrievedExchanges, EpisError> {
+    self.ensure_index(chatmate_id).await?;
+
+    let mut indices = self.lock_indices();
+    let Some(index) = indices.get_mut(chatmate_id.as_ref()) else {
+      return Ok(RetrievedExchanges::default());
+    };
+    index.last_used = Instant::now();
+
+    // Each exchange consists of two messages, and the recent ones are already in the chat history
+    let recent_exchanges = recent_messages as usize / 2;
+    let max_position = index.past_exchanges.len().saturating_sub(recent_exchanges);
+    let past_exchanges = index
+      .search_engine
+      .search(user_message, limit as usize + recent_exchanges)
+      .into_iter()
+      .filter(|search_result| search_result.document.id < max_position)
+      .take(limit as usize)
+      .filter_map(|search_result| index.past_exchanges.get(search_result.document.id).cloned())
+      .collect::<Vec<_>>();
+    debug!(
+      retrieved_exchanges = past_exchanges.len(),
+      "Past exchanges retrieved by BM25"
+    );
+
+    // BM25 runs locally, so retrieval costs nothing
+    Ok(RetrievedExchanges::new(past_exchanges, AiUsage::default()))
+  }
+}
+
+/// A [HistoryRetriever] backed by any of the supported retrievers
+#[derive(Debug, Clone)]
+#[allow(clippy::missing_docs_in_private_items)]
+pub enum AnyHistoryRetriever<AG: AiGateway, ER: EpisRepository> {
+  Embedding(EmbeddingHistoryRetriever<AG, ER>),
+  Bm25(Bm25HistoryRetriever<ER>),
+}
+
+impl<AG: AiGateway, ER: EpisRepository> HistoryRetriever for AnyHistoryRetriever<AG, ER> {
+  async fn index(&self, chatmate_id: &Id) -> Result<(), EpisError> {
+    match self {
+      Self::Embedding(retriever) => retriever.index(chatmate_id).await,
+      Self::Bm25(retriever) => retriever.index(chatmate_id).await,
+    }
+  }
+
+  async fn retrieve(
+    &self,
+    chatmate_id: &Id,
+    user_message: &str,
+    recent_messages: u8,
+    limit: u8,
+  ) -> Result<RetrievedExchanges, EpisError> {
+    match self {
+      Self::Embedding(retriever) => {
+        retriever
+          .retrieve(chatmate_id, user_message, recent_messages, limit)
+          .await
+      }
+      Self::Bm25(retriever) => {
+        retriever
+          .retrieve(chatmate_id, user_message, recent_messages, limit)
+          .await
+      }
+    }
+  }
+}
@@ -1,5 +1,15 @@
 //! All outbound adapters
 
 pub mod clerk;
+pub mod fake;
+pub mod history_retriever;
+pub mod moderation;
+pub mod ollama;
 pub mod openai;
+pub mod openai_realtime;
 pub mod postgres;
+pub mod provider_registry;
+pub mod resilient;
+pub mod structured_output;
+pub mod tts_cache;
+pub mod user_management;
@@ -0,0 +1,196 @@
+//! Moderation providers, checking chat texts for unsafe content
+
+use std::str::FromStr;
+
+use anyhow::{Context, anyhow};
+use async_openai::{
+  Client,
+  config::OpenAIConfig,
+  types::moderations::{CreateModerationRequest, ModerationInput},
+};
+use regex::{RegexSet, RegexSetBuilder};
+use serde_json::Value;
+use tracing::{info, warn};
+
+use crate::{
+  config::ModerationConfig,
+  domain::{
+    models::{EpisError, ModerationVerdict},
+    ports::ModerationPort,
+  },
+};
+
+/// Moderation model used by the openai provider if no model is configured
+const DEFAULT_OPENAI_MODERATION_MODEL: &str = "omni-moderation-latest";
+
+/// All supported moderation providers
+#[derive(Debug, Clone)]
+pub enum ModerationProvider {
+  /// A list of regex patterns of unsafe content
+  Keyword,
+  /// OpenAI moderation api
+  OpenAi,
+}
+
+impl FromStr for ModerationProvider {
+  type Err = anyhow::Error;
+
+  fn from_str(provider: &str) -> Result<Self, Self::Err> {
+    match provider {
+      "keyword" => Ok(Self::Keyword),
+      "openai" => Ok(Self::OpenAi),
+      unknown => Err(anyhow!(
+        "Unknown moderation provider `{unknown}`, expected one of: keyword, openai"
+      )),
+    }
+  }
+}
+
+/// Keyword moderation, flagging texts matching any of a list of case insensitive regex patterns.
+/// The matched patterns are reported as the categories.
+#[derive(Debug, Clone)]
+pub struct KeywordModeration {
+  /// Compiled patterns
+  patterns: RegexSet,
+}
+
+impl KeywordModeration {
+  /// Build a [KeywordModeration] from a list of regex patterns
+  ///
+  /// # Errors
+  /// If any pattern is not a valid regex, an error is returned
+  pub fn try_new(patterns: &[String]) -> anyhow::Result<Self> {
+    let patterns = RegexSetBuilder::new(patterns)
+      .case_insensitive(true)
+      .build()
+      .context("Invalid moderation pattern")?;
+
+    Ok(Self { patterns })
+  }
+}
+
+impl ModerationPort for KeywordModeration {
+  async fn moderate(&self, text: &str) -> Result<ModerationVerdict, EpisError> {
+    let categories = self
+      .patterns
+      .matches(text)
+      .into_iter()
+      .map(|index| self.patterns.patterns()[index].clone())
+      .collect::<Vec<_>>();
+
+    Ok(ModerationVerdict::new(!categories.is_empty(), categories))
+  }
+}
+
+/// OpenAI moderation, flagging texts via the OpenAI moderation api
+#[derive(Debug, Clone)]
+pub struct OpenAiModeration {
+  /// [async_openai] client
+  client: Client<OpenAIConfig>,
+  /// Moderation model
+  model: String,
+}
+
+impl OpenAiModeration {
+  /// Construct an [OpenAiModeration] with an api key, defaulting to the latest moderation model
+  pub fn new(api_key: &str, model: Option<String>) -> Self {
+    let client = Client::with_config(OpenAIConfig::default().with_api_key(api_key));
+    Self {
+      client,
+      model: model.unwrap_or(DEFAULT_OPENAI_MODERATION_MODEL.to_string()),
+    }
+  }
+}
+
+impl ModerationPort for OpenAiModeration {
+  async fn moderate(&self, text: &str) -> Result<ModerationVerdict, EpisError> {
+    let response = self
+      .client
+      .moderations()
+      .create(CreateModerationRequest {
+        input: ModerationInput::String(text.to_string()),
+        model: Some(self.model.clone()),
+      })
+      .await
+      .map_err(|error| {
+        warn!(%error, "Cannot moderate text");
+        EpisError::ProviderError
+      })?;
+
+    let result = response.results.into_iter().next().ok_or_else(|| {
+      warn!("Moderation response has no result");
+      EpisError::ProviderError
+    })?;
+
+    // Categories are serialized by their api names, e.g. self-harm/intent
+    let categories = match serde_json::to_value(&result.categories) {
+      Ok(Value::Object(categories)) => categories
+        .into_iter()
+        .filter(|(_, flagged)| flagged.as_bool().unwrap_or_default())
+        .map(|(category, _)| category)
+        .collect(),
+      _ => Vec::new(),
+    };
+
+    Ok(ModerationVerdict::new(result.flagged, categories))
+  }
+}
+
+/// A [ModerationPort] backed by any of the supported providers, or by none if moderation is
+/// disabled
+#[derive(Debug, Clone)]
+#[allow(clippy::missing_docs_in_private_items)]
+pub enum ProviderModeration {
+  Disabled,
+  Keyword(KeywordModeration),
+  OpenAi(Box<OpenAiModeration>),
+}
+
+impl ProviderModeration {
+  /// Build a [ProviderModeration] from the moderation config, disabling moderation if there is no
+  /// config
+  ///
+  /// # Errors
+  /// - If provider is unknown, an error is returned
+  /// - If provider config is invalid, e.g. an invalid pattern or a missing api key, an error is
+  ///   returned
+  pub fn try_from_config(
+    moderation_config: Option<&ModerationConfig>,
+    openai_api_key: Option<&str>,
+  ) -> anyhow::Result<Self> {
+    let Some(moderation_config) = moderation_config else {
+      warn!("Moderation is disabled");
+      return Ok(Self::Disabled);
+    };
+
+    let moderation = match ModerationProvider::from_str(moderation_config.provider())? {
+      ModerationProvider::Keyword => {
+        Self::Keyword(KeywordModeration::try_new(moderation_config.patterns())?)
+      }
+      ModerationProvider::OpenAi => Self::OpenAi(Box::new(OpenAiModeration::new(
+        moderation_config
+          .api_key()
+          .as_deref()
+          .or(openai_api_key)
+          .context("An api key is required for openai moderation provider")?,
+        moderation_config.model().clone(),
+      ))),
+    };
+    info!(
+      provider = moderation_config.provider(),
+      "Moderation built successfully"
+    );
+
+    Ok(moderation)
+  }
+}
+
+impl ModerationPort for ProviderModeration {
+  async fn moderate(&self, text: &str) -> Result<ModerationVerdict, EpisError> {
+    match self {
+      Self::Disabled => Ok(ModerationVerdict::default()),
+      Self::Keyword(moderation) => moderation.moderate(text).await,
+      Self::OpenAi(moderation) => moderation.moderate(text).await,
+    }
+  }
+}
@@ -0,0 +1,459 @@
+//! Ollama AI provider
+
+use futures::StreamExt;
+use ollama_rs::{
+  error::OllamaError,
+  generation::{
+    chat::{
+      ChatMessage as OllamaChatMessage, ChatMessageFinalResponseData, request::ChatMessageRequest,
+    },
+    embeddings::request::{EmbeddingsInput, GenerateEmbeddingsRequest},
+    parameters::{FormatType, JsonStructure},
+  },
+};
+use schemars::JsonSchema;
+use tokio::sync::mpsc::Sender;
+use tracing::{debug, warn};
+
+use crate::{
+  domain::{
+    models::{
+      AiUsage, CefrAssessment, ChatMessage, ChatMessageRole, ChatSummary, EmbeddingResponse,
+      EpisAudioMessageFormat, EpisError, GenerationResponse, TextToSpeechResponse,
+      TranscriptionResponse,
+    },
+    ports::AiGateway,
+  },
+  outbound::structured_output::{
+    ApiCefrAssessment, ApiChatSummary, ApiResponse, ResponseTextExtractor, parse_json_output,
+    parse_or_repair, parse_or_repair_generation,
+  },
+};
+
+/// Implementation of [AiGateway] for Ollama
+///
+/// # Notes
+/// Ollama only supports text generation and embedding, so transcription and text to speech always
+/// fail, and Ollama is rejected as an stt or tts provider at startup. Ollama doesn't report the
+/// usage of embedding, so it's not accounted.
+#[derive(Debug, Clone)]
+pub struct Ollama {
+  /// [ollama_rs] client
+  client: ollama_rs::Ollama,
+  /// Max number of corrective re-prompts after a generation not matching the schema
+  max_repair_attempts: u32,
+}
+
+impl Ollama {
+  /// Construct an [Ollama] for a base url, defaulting to the local Ollama instance, and repairing
+  /// invalid generations up to a max number of attempts
+  ///
+  /// # Errors
+  /// If base url is not a valid url, an error is returned
+  pub fn try_new(base_url: Option<String>, max_repair_attempts: u32) -> anyhow::Result<Self> {
+    let client = match base_url {
+      Some(base_url) => ollama_rs::Ollama::try_new(base_url)?,
+      None => ollama_rs::Ollama::default(),
+    };
+    Ok(Self {
+      client,
+      max_repair_attempts,
+    })
+  }
+
+  /// Build a chat request with the structured json format of a schema
+  fn build_request<T: JsonSchema>(model: &str, messages: &[ChatMessage]) -> ChatMessageRequest {
+    let format = FormatType::StructuredJson(Box::new(JsonStructure::new::<T>()));
+    ChatMessageRequest::new(
+      model.to_string(),
+      messages.iter().map(OllamaChatMessage::from).collect(),
+    )
+    .format(format)
+  }
+
+  /// Generate a structured output text of a schema, along with the usage of its generation
+  ///
+  /// # Errors
+  /// - If a transient error occurs, [EpisError::TransientProviderError] is returned
+  /// - Otherwise [EpisError::ProviderError] is returned
+  async fn create_output_text<T: JsonSchema>(
+    &self,
+    model: &str,
+    messages: &[ChatMessage],
+  ) -> Result<(String, AiUsage), EpisError> {
+    let request = Self::build_request::<T>(model, messages);
+
+    let response = self
+      .client
+      .send_chat_messages(request)
+      .await
+      .map_err(|error| {
+        warn!(%error, "Cannot generate a response");
+        provider_error(&error)
+      })?;
+
+    let generation_usage = response.final_data.as_ref().map(usage).unwrap_or_default();
+    Ok((response.message.content, generation_usage))
+  }
+}
+
+/// Map an [OllamaError] of a request to the provider error, distinguishing transient errors
+fn provider_error(error: &OllamaError) -> EpisError {
+  match error {
+    OllamaError::ReqwestError(_) => EpisError::TransientProviderError,
+    _ => EpisError::ProviderError,
+  }
+}
+
+/// Extract the usage of a generation from the data of its final response
+fn usage(final_data: &ChatMessageFinalResponseData) -> AiUsage {
+  AiUsage::new(
+    final_data.prompt_eval_count as u32,
+    final_data.eval_count as u32,
+    0.0,
+    0,
+  )
+}
+
+impl From<&ChatMessage> for OllamaChatMessage {
+  fn from(chat_message: &ChatMessage) -> Self {
+    let content = chat_message.message().to_string();
+    match chat_message.role() {
+      ChatMessageRole::User => OllamaChatMessage::user(content),
+      ChatMessageRole::Ai => OllamaChatMessage::assistant(content),
+      ChatMessageRole::System => OllamaChatMessage::system(content),
+    }
+  }
+}
+
+impl AiGateway for Ollama {
+  async fn text_to_speech(
+    &self,
+    _model: &str,
+    _text: String,
+    _voice: &str,
+    _instructions: Option<&str>,
+  ) -> Result<TextToSpeechResponse, EpisError> {
+    warn!("Tts is not supported by Ollama");
+    Err(EpisError::ProviderError)
+  }
+
+  async fn transcribe(
+    &self,
+    _model: &str,
+    _audio_bytes: Vec<u8>,
+    _audio_format: EpisAudioMessageFormat,
+    _instructions: Option<&str>,
+  ) -> Result<TranscriptionResponse, EpisError> {
+    warn!("Transcription is not supported by Ollama");
+    Err(EpisError::ProviderError)
+  }
+
+  async fn embed(&self, model: &str, texts: &[String]) -> Result<EmbeddingResponse, EpisError> {
+    let response = self
+      .client
+      .generate_embeddings(GenerateEmbeddingsRequest::new(
+        model.to_string(),
+        EmbeddingsInput::Multiple(texts.to_vec()),
+      ))
+      .await
+      .map_err(|error| {
+        warn!(%error, "Cannot generate embeddings");
+        provider_error(&error)
+      })?;
+    debug!("Embedding was done successfully");
+
+    Ok(EmbeddingResponse::new(
+      response.embeddings,
+      AiUsage::default(),
+    ))
+  }
+
+  async fn generate(
+    &self,
+    model: &str,
+    messages: &[ChatMessage],
+  ) -> Result<GenerationResponse, EpisError> {
+    let (output_text, generation_usage) = self
+      .create_output_text::<ApiResponse>(model, messages)
+      .await?;
+    let generation_response = parse_or_repair_generation(
+      output_text,
+      generation_usage,
+      messages,
+      self.max_repair_attempts,
+      |repair_messages| async move {
+        self
+          .create_output_text::<ApiResponse>(model, &repair_messages)
+          .await
+      },
+    )
+    .await?;
+    debug!("Response generation was done successfully");
+
+    Ok(generation_response)
+  }
+
+  async fn assess_cefr_level(
+    &self,
+    model: &str,
+    messages: &[ChatMessage],
+  ) -> Result<CefrAssessment, EpisError> {
+    let (output_text, usage) = self
+      .create_output_text::<ApiCefrAssessment>(model, messages)
+      .await?;
+    let (api_cefr_assessment, usage) = parse_or_repair(
+      output_text,
+      usage,
+      messages,
+      self.max_repair_attempts,
+      parse_json_output::<ApiCefrAssessment>,
+      |repair_messages| async move {
+        self
+          .create_output_text::<ApiCefrAssessment>(model, &repair_messages)
+          .await
+      },
+    )
+    .await?;
+    debug!("CEFR level assessment was done successfully");
+
+    Ok(api_cefr_assessment.into_cefr_assessment(usage))
+  }
+
+  async fn summarize_chat(
+    &self,
+    model: &str,
+    messages: &[ChatMessage],
+  ) -> Result<ChatSummary, EpisError> {
+    let (output_text, usage) = self
+      .create_output_text::<ApiChatSummary>(model, messages)
+      .await?;
+    let (api_chat_summary, usage) = parse_or_repair(
+      output_text,
+      usage,
+      messages,
+      self.max_repair_attempts,
+      parse_json_output::<ApiChatSummary>,
+      |repair_messages| async move {
+        self
+          .create_output_text::<ApiChatSummary>(model, &repair_messages)
+          .await
+      },
+    )
+    .await?;
+    debug!("Chat summarization was done successfully");
+
+    Ok(api_chat_summary.into_chat_summary(usage))
+  }
+
+  async fn generate_stream(
+    &self,
+    model: &str,
+    messages: &[ChatMessage],
+    text_deltas: Sender<String>,
+  ) -> Result<GenerationResponse, EpisError> {
+    let request = Self::build_request::<ApiResponse>(model, messages);
+
+    let mut stream = self
+      .client
+      .send_chat_messages_stream(request)
+      .await
+      .map_err(|error| {
+        warn!(%error, "Cannot start generating a response stream");
+        provider_error(&error)
+      })?;
+
+    let mut extractor = ResponseTextExtractor::default();
+    let mut generation_usage = AiUsage::default();
+    while let Some(chunk) = stream.next().await {
+      // Ollama only reports stream read failures, which are transient
+      let chunk = chunk.map_err(|_| {
+        warn!("Error in response stream");
+        EpisError::TransientProviderError
+      })?;
+      extractor
+        .forward(&chunk.message.content, &text_deltas)
+        .await;
+      if let Some(final_data) = &chunk.final_data {
+        generation_usage = usage(final_data);
+      }
+    }
+
+    // Repairs are not streamed, as the forwarded text is reconciled with the repaired one
+    let generation_response = parse_or_repair_generation(
+      extractor.raw().to_string(),
+      generation_usage,
+      messages,
+      self.max_repair_attempts,
+      |repair_messages| async move {
+        self
+          .create_output_text::<ApiResponse>(model, &repair_messages)
+          .await
+      },
+    )
+    .await?;
+    let generation_response = extractor.reconcile(generation_response, &text_deltas).await;
+    debug!("Streaming response generation was done successfully");
+
+    Ok(generation_response)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use axum::{Json, Router, routing::post};
+  use serde_json::{Value, json};
+  use tokio::{net::TcpListener, sync::mpsc};
+
+  use super::*;
+
+  /// Json output of a generation, as returned by the llm
+  const OUTPUT_TEXT: &str = r#"{"response":"¡Hola! ¿Te gusta charlar?","learned_material":{"vocab":[{"vocab":"charlar","part_of_speech":"verb","translation":"to chat","definition":"Hablar de manera informal.","example":"¿Te gusta charlar?"}]},"corrections":[],"scenario_progress":{"completed_goals":[],"succeeded":false}}"#;
+
+  /// A chat response of the Ollama api, along with its final data if it's done
+  fn chat_response(content: &str, done: bool) -> Value {
+    let mut response = json!({
+      "model": "llama3",
+      "created_at": "2025-10-17T00:00:00Z",
+      "message": { "role": "assistant", "content": content },
+      "done": done,
+    });
+    if done {
+      response.as_object_mut().unwrap().extend([
+        ("total_duration".to_string(), json!(1)),
+        ("load_duration".to_string(), json!(1)),
+        ("prompt_eval_count".to_string(), json!(12)),
+        ("prompt_eval_duration".to_string(), json!(1)),
+        ("eval_count".to_string(), json!(7)),
+        ("eval_duration".to_string(), json!(1)),
+      ]);
+    }
+    response
+  }
+
+  /// Serve a mock Ollama api on a random local port, returning its base url. Chat requests are
+  /// answered by [OUTPUT_TEXT], split into chunks if streamed.
+  async fn serve_mock_ollama() -> String {
+    let router = Router::new()
+      .route(
+        "/api/chat",
+        post(|Json(request): Json<Value>| async move {
+          if request["stream"] == json!(true) {
+            let (head, tail) = OUTPUT_TEXT.split_at(30);
+            [
+              chat_response(head, false),
+              chat_response(tail, false),
+              chat_response("", true),
+            ]
+            .iter()
+            .map(|chunk| format!("{chunk}\n"))
+            .collect::<String>()
+          } else {
+            chat_response(OUTPUT_TEXT, true).to_string()
+          }
+        }),
+      )
+      .route(
+        "/api/embed",
+        post(|Json(request): Json<Value>| async move {
+          let embeddings = request["input"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|_| json!([0.5, 0.25]))
+            .collect::<Vec<_>>();
+          Json(json!({ "embeddings": embeddings }))
+        }),
+      );
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, router).await });
+
+    format!("http://{address}/")
+  }
+
+  /// Messages of a chat with a single user message
+  fn messages() -> Vec<ChatMessage> {
+    vec![ChatMessage::new(ChatMessageRole::User, "Hola".to_string())]
+  }
+
+  /// A generation is parsed from the structured output, along with its usage
+  #[tokio::test]
+  async fn generates_a_response() {
+    let ollama = Ollama::try_new(Some(serve_mock_ollama().await), 0).unwrap();
+
+    let generation_response = ollama.generate("llama3", &messages()).await.unwrap();
+
+    assert_eq!(generation_response.text(), "¡Hola! ¿Te gusta charlar?");
+    assert_eq!(generation_response.learned_vocab().len(), 1);
+    assert_eq!(generation_response.learned_vocab()[0].vocab(), "charlar");
+    assert_eq!(
+      generation_response.learned_vocab()[0]
+        .details()
+        .translation(),
+      "to chat"
+    );
+    assert_eq!(*generation_response.usage().input_tokens(), 12);
+    assert_eq!(*generation_response.usage().output_tokens(), 7);
+  }
+
+  /// A streamed generation forwards the response text as deltas, and is parsed once done
+  #[tokio::test]
+  async fn streams_a_response() {
+    let ollama = Ollama::try_new(Some(serve_mock_ollama().await), 0).unwrap();
+    let (text_deltas, mut text_deltas_receiver) = mpsc::channel(16);
+
+    let generation_response = ollama
+      .generate_stream("llama3", &messages(), text_deltas)
+      .await
+      .unwrap();
+
+    let mut streamed_text = String::new();
+    while let Some(text_delta) = text_deltas_receiver.recv().await {
+      streamed_text.push_str(&text_delta);
+    }
+    assert_eq!(streamed_text, "¡Hola! ¿Te gusta charlar?");
+    assert_eq!(generation_response.text(), "¡Hola! ¿Te gusta charlar?");
+    assert_eq!(*generation_response.usage().output_tokens(), 7);
+  }
+
+  /// Each text gets an embedding
+  #[tokio::test]
+  async fn embeds_texts() {
+    let ollama = Ollama::try_new(Some(serve_mock_ollama().await), 0).unwrap();
+
+    let embedding_response = ollama
+      .embed("nomic-embed-text", &["uno".to_string(), "dos".to_string()])
+      .await
+      .unwrap();
+
+    assert_eq!(embedding_response.embeddings().len(), 2);
+    assert_eq!(embedding_response.embeddings()[0], vec![0.5, 0.25]);
+  }
+
+  /// An unreachable Ollama instance is a transient error, so that it's retried
+  #[tokio::test]
+  async fn unreachable_instance_is_transient() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    drop(listener);
+    let ollama = Ollama::try_new(Some(format!("http://{address}/")), 0).unwrap();
+
+    let error = ollama.generate("llama3", &messages()).await.unwrap_err();
+
+    assert!(matches!(error, EpisError::TransientProviderError));
+  }
+
+  /// Speech is not supported
+  #[tokio::test]
+  async fn speech_is_not_supported() {
+    let ollama = Ollama::try_new(Some(serve_mock_ollama().await), 0).unwrap();
+
+    let error = ollama
+      .text_to_speech("llama3", "Hola".to_string(), "alloy", None)
+      .await
+      .unwrap_err();
+
+    assert!(matches!(error, EpisError::ProviderError));
+  }
+}
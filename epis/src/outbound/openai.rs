@@ -1,47 +1,482 @@
 //! OpenAI AI provider
 
+use std::str::FromStr;
+
+use anyhow::anyhow;
 use async_openai::{
   Client,
   config::{OPENAI_API_BASE, OpenAIConfig},
+  error::{ApiError, OpenAIError, WrappedError},
   types::{
+    LogProbProperties,
     audio::{
-      AudioInput, CreateSpeechRequestArgs, CreateTranscriptionRequestArgs, SpeechModel, Voice,
+      AudioInput, AudioResponseFormat, CreateSpeechRequestArgs, CreateTranscriptionRequestArgs,
+      SpeechModel, TimestampGranularity, TranscriptionInclude, TranscriptionSegment,
+      TranscriptionUsage, TranscriptionWord, Voice,
+    },
+    chat::{
+      ChatCompletionRequestAssistantMessage, ChatCompletionRequestMessage,
+      ChatCompletionRequestSystemMessage, ChatCompletionRequestUserMessage,
+      ChatCompletionStreamOptions, CompletionUsage, CreateChatCompletionRequest,
+      CreateChatCompletionRequestArgs, ReasoningEffort, ResponseFormat, ResponseFormatJsonSchema,
     },
-    chat::{ReasoningEffort, ResponseFormatJsonSchema},
+    embeddings::{CreateEmbeddingRequest, EmbeddingInput},
     evals::EasyInputMessage,
     responses::{
-      CreateResponseArgs, EasyInputContent, EasyInputMessageArgs, InputItem, InputParam, Reasoning,
-      ResponseTextParam, Role, TextResponseFormatConfiguration, Verbosity,
+      CreateResponse, CreateResponseArgs, EasyInputContent, EasyInputMessageArgs, InputItem,
+      InputParam, Reasoning, ResponseStreamEvent, ResponseTextParam, ResponseUsage, Role,
+      TextResponseFormatConfiguration, Verbosity,
     },
   },
 };
-use schemars::{JsonSchema, schema_for};
-use serde::Deserialize;
+use futures::StreamExt;
+use schemars::schema_for;
+use serde_json::Value;
+use tokio::sync::mpsc::Sender;
 use tracing::{debug, warn};
 
-use crate::domain::{
-  models::{
-    ChatMessage, ChatMessageRole, EpisAudioMessageFormat, EpisError, GenerationResponse,
-    TextToSpeechResponse, TranscriptionResponse,
+use crate::{
+  domain::{
+    models::{
+      AiUsage, CefrAssessment, ChatMessage, ChatMessageRole, ChatSummary, EmbeddingResponse,
+      EpisAudioMessageFormat, EpisError, GenerationResponse, TextToSpeechResponse, TranscribedWord,
+      TranscriptionResponse,
+    },
+    ports::AiGateway,
+  },
+  outbound::structured_output::{
+    ApiCefrAssessment, ApiChatSummary, ApiResponse, ResponseTextExtractor, parse_json_output,
+    parse_or_repair, parse_or_repair_generation,
   },
-  ports::AiGateway,
 };
 
-/// Implementation of [AiGateway] for OpenAI
+/// Prefix of the whisper transcription models, which provide word timestamps
+const WHISPER_MODEL_PREFIX: &str = "whisper";
+
+/// The api used for text generation
+#[derive(Debug, Clone, Default)]
+pub enum ApiFlavour {
+  /// Responses api, only implemented by OpenAI itself
+  #[default]
+  Responses,
+  /// Chat Completions api with a json schema response format
+  ChatCompletions,
+  /// Chat Completions api with the json schema described in the prompt, for servers that don't
+  /// support json schema response formats
+  ChatCompletionsPromptedJson,
+}
+
+impl FromStr for ApiFlavour {
+  type Err = anyhow::Error;
+
+  fn from_str(api_flavour: &str) -> Result<Self, Self::Err> {
+    match api_flavour {
+      "responses" => Ok(Self::Responses),
+      "chat-completions" => Ok(Self::ChatCompletions),
+      "chat-completions-prompted-json" => Ok(Self::ChatCompletionsPromptedJson),
+      unknown => Err(anyhow!(
+        "Unknown api flavour `{unknown}`, expected one of: responses, chat-completions, chat-completions-prompted-json"
+      )),
+    }
+  }
+}
+
+/// Implementation of [AiGateway] for OpenAI and OpenAI-compatible servers
 #[derive(Debug, Clone)]
 pub struct OpenAi {
   /// [async_openai] client
   client: Client<OpenAIConfig>,
+  /// The api used for text generation
+  api_flavour: ApiFlavour,
+  /// Max number of corrective re-prompts after a generation not matching the schema
+  max_repair_attempts: u32,
 }
 
 impl OpenAi {
-  /// Construct an [OpenAi] with an api key for a base url
-  pub fn new(api_key: &str, base_url: Option<String>) -> Self {
+  /// Construct an [OpenAi] with an api key for a base url, generating text via an api flavour and
+  /// repairing invalid generations up to a max number of attempts
+  pub fn new(
+    api_key: &str,
+    base_url: Option<String>,
+    api_flavour: ApiFlavour,
+    max_repair_attempts: u32,
+  ) -> Self {
     let config = OpenAIConfig::default()
       .with_api_base(base_url.unwrap_or(OPENAI_API_BASE.into()))
       .with_api_key(api_key);
     let client = Client::with_config(config);
-    Self { client }
+    Self {
+      client,
+      api_flavour,
+      max_repair_attempts,
+    }
+  }
+
+  /// Build a Responses api request with a json schema text format
+  fn build_response_request(
+    model: &str,
+    messages: &[ChatMessage],
+    schema_value: Value,
+  ) -> CreateResponse {
+    let input = InputParam::Items(
+      messages
+        .iter()
+        .map(|message| InputItem::EasyMessage(message.into()))
+        .collect::<Vec<_>>(),
+    );
+
+    CreateResponseArgs::default()
+      // TODO: Set max tokens based on data
+      // https://github.com/mkermani144/epis/issues/10
+      .max_output_tokens(10000u32)
+      .model(model)
+      .text(ResponseTextParam {
+        format: TextResponseFormatConfiguration::JsonSchema(ResponseFormatJsonSchema {
+          description: None,
+          name: "ai_response".to_string(),
+          strict: Some(true),
+          schema: Some(schema_value),
+        }),
+        verbosity: Some(Verbosity::Low),
+      })
+      .reasoning(Reasoning {
+        effort: Some(ReasoningEffort::Low),
+        summary: None,
+      })
+      .input(input)
+      .build()
+      .expect("Responses request can be built from the provided args")
+  }
+
+  /// Build a Chat Completions api request, either with a json schema response format or with the
+  /// schema described in the prompt
+  fn build_chat_completion_request(
+    model: &str,
+    messages: &[ChatMessage],
+    schema_value: Value,
+    prompted_json: bool,
+  ) -> CreateChatCompletionRequest {
+    let mut chat_messages = messages
+      .iter()
+      .map(ChatCompletionRequestMessage::from)
+      .collect::<Vec<_>>();
+
+    let mut request_args = CreateChatCompletionRequestArgs::default();
+    // TODO: Set max tokens based on data
+    // https://github.com/mkermani144/epis/issues/10
+    request_args.max_completion_tokens(10000u32).model(model);
+
+    if prompted_json {
+      chat_messages.push(
+        ChatCompletionRequestSystemMessage::from(format!(
+          "Reply only with a json object, without any other text, matching this json schema:\n{schema_value}"
+        ))
+        .into(),
+      );
+    } else {
+      request_args.response_format(ResponseFormat::JsonSchema {
+        json_schema: ResponseFormatJsonSchema {
+          description: None,
+          name: "ai_response".to_string(),
+          strict: Some(true),
+          schema: Some(schema_value),
+        },
+      });
+    }
+
+    request_args
+      .messages(chat_messages)
+      .build()
+      .expect("Chat completion request can be built from the provided args")
+  }
+
+  /// Generate a structured output text, along with the usage of its generation
+  ///
+  /// # Errors
+  /// - If a transient error occurs, [EpisError::TransientProviderError] is returned
+  /// - Otherwise [EpisError::ProviderError] is returned
+  async fn create_output_text(
+    &self,
+    model: &str,
+    messages: &[ChatMessage],
+    schema_value: Value,
+  ) -> Result<(String, AiUsage), EpisError> {
+    match self.api_flavour {
+      ApiFlavour::Responses => {
+        let request = Self::build_response_request(model, messages, schema_value);
+        let response = self
+          .client
+          .responses()
+          .create(request)
+          .await
+          .map_err(|error| {
+            warn!(%error, "Cannot generate a response");
+            provider_error(&error)
+          })?;
+
+        // A missing output text is repaired like any other invalid output
+        let output_text = response.output_text().unwrap_or_else(|| {
+          warn!("Response has no output text");
+          String::new()
+        });
+        Ok((
+          output_text,
+          response.usage.map(AiUsage::from).unwrap_or_default(),
+        ))
+      }
+      ApiFlavour::ChatCompletions | ApiFlavour::ChatCompletionsPromptedJson => {
+        let request = Self::build_chat_completion_request(
+          model,
+          messages,
+          schema_value,
+          matches!(self.api_flavour, ApiFlavour::ChatCompletionsPromptedJson),
+        );
+        let response = self.client.chat().create(request).await.map_err(|error| {
+          warn!(%error, "Cannot generate a chat completion");
+          provider_error(&error)
+        })?;
+
+        let output_text = response
+          .choices
+          .into_iter()
+          .next()
+          .and_then(|choice| choice.message.content)
+          .unwrap_or_else(|| {
+            warn!("Chat completion has no content");
+            String::new()
+          });
+        Ok((
+          output_text,
+          response.usage.map(AiUsage::from).unwrap_or_default(),
+        ))
+      }
+    }
+  }
+
+  /// Generate a structured output text via streaming, sending the response text deltas through a
+  /// channel as they are extracted by an extractor, and returning the text along with the usage of
+  /// its generation
+  ///
+  /// # Errors
+  /// - If a transient error occurs, [EpisError::TransientProviderError] is returned
+  /// - Otherwise [EpisError::ProviderError] is returned
+  async fn create_output_text_stream(
+    &self,
+    model: &str,
+    messages: &[ChatMessage],
+    schema_value: Value,
+    extractor: &mut ResponseTextExtractor,
+    text_deltas: &Sender<String>,
+  ) -> Result<(String, AiUsage), EpisError> {
+    let mut usage = AiUsage::default();
+
+    match self.api_flavour {
+      ApiFlavour::Responses => {
+        let request = Self::build_response_request(model, messages, schema_value);
+        let mut stream = self
+          .client
+          .responses()
+          .create_stream(request)
+          .await
+          .map_err(|error| {
+            warn!(%error, "Cannot start generating a response stream");
+            provider_error(&error)
+          })?;
+
+        while let Some(event) = stream.next().await {
+          match event {
+            Ok(ResponseStreamEvent::ResponseOutputTextDelta(text_delta_event)) => {
+              extractor
+                .forward(&text_delta_event.delta, text_deltas)
+                .await;
+            }
+            Ok(ResponseStreamEvent::ResponseCompleted(completed_event)) => {
+              usage = completed_event
+                .response
+                .usage
+                .map(AiUsage::from)
+                .unwrap_or_default();
+            }
+            Ok(ResponseStreamEvent::ResponseFailed(_))
+            | Ok(ResponseStreamEvent::ResponseIncomplete(_)) => {
+              warn!("Response stream did not complete");
+              return Err(EpisError::ProviderError);
+            }
+            Ok(_) => {}
+            Err(error) => {
+              warn!(%error, "Error in response stream");
+              return Err(provider_error(&error));
+            }
+          }
+        }
+      }
+      ApiFlavour::ChatCompletions | ApiFlavour::ChatCompletionsPromptedJson => {
+        let mut request = Self::build_chat_completion_request(
+          model,
+          messages,
+          schema_value,
+          matches!(self.api_flavour, ApiFlavour::ChatCompletionsPromptedJson),
+        );
+        request.stream_options = Some(ChatCompletionStreamOptions {
+          include_usage: Some(true),
+          include_obfuscation: None,
+        });
+        let mut stream = self
+          .client
+          .chat()
+          .create_stream(request)
+          .await
+          .map_err(|error| {
+            warn!(%error, "Cannot start generating a chat completion stream");
+            provider_error(&error)
+          })?;
+
+        while let Some(chunk) = stream.next().await {
+          let chunk = chunk.map_err(|error| {
+            warn!(%error, "Error in chat completion stream");
+            provider_error(&error)
+          })?;
+
+          // Usage is only included in the last chunk, which has no choices
+          if let Some(chunk_usage) = chunk.usage {
+            usage = chunk_usage.into();
+          }
+          if let Some(content) = chunk
+            .choices
+            .into_iter()
+            .next()
+            .and_then(|choice| choice.delta.content)
+          {
+            extractor.forward(&content, text_deltas).await;
+          }
+        }
+      }
+    }
+
+    Ok((extractor.raw().to_string(), usage))
+  }
+}
+
+/// Types and codes of OpenAI api errors returned on rate limiting or server errors
+const TRANSIENT_API_ERRORS: &[&str] = &[
+  "rate_limit_exceeded",
+  "requests",
+  "tokens",
+  "server_error",
+  "service_unavailable",
+  "engine_overloaded",
+];
+
+/// Check if an api error is of a 429 or 5xx response, identified by its type or code, as the
+/// status of the response is not exposed
+fn is_transient_api_error(api_error: &ApiError) -> bool {
+  [&api_error.r#type, &api_error.code]
+    .into_iter()
+    .flatten()
+    .any(|kind| TRANSIENT_API_ERRORS.contains(&kind.as_str()))
+}
+
+/// Map an [OpenAIError] of a request to the provider error, distinguishing transient errors.
+/// Errors of other responses, e.g. of a misconfigured base url, are permanent, so that they are not
+/// retried.
+fn provider_error(error: &OpenAIError) -> EpisError {
+  match error {
+    OpenAIError::Reqwest(_) | OpenAIError::StreamError(_) => EpisError::TransientProviderError,
+    // Json errors of 4xx responses are unwrapped, and 429 ones are identified by their type or
+    // code. An insufficient quota is also a 429, but it's not transient. Errors of 5xx responses
+    // carry their raw body instead, as they are not guaranteed to be json, so an error body as
+    // their message identifies them. A 5xx without a json body cannot be told apart from a 4xx, so
+    // it fails over without being retried.
+    OpenAIError::ApiError(api_error)
+      if is_transient_api_error(api_error)
+        || serde_json::from_str::<WrappedError>(&api_error.message).is_ok() =>
+    {
+      EpisError::TransientProviderError
+    }
+    _ => EpisError::ProviderError,
+  }
+}
+
+/// Parse a voice identifier into a [Voice], falling back to the default voice if it's unknown
+fn parse_voice(voice: &str) -> Voice {
+  serde_json::from_value(Value::String(voice.to_string())).unwrap_or_else(|error| {
+    warn!(%error, voice, "Unknown voice, falling back to the default one");
+    Voice::default()
+  })
+}
+
+impl From<ResponseUsage> for AiUsage {
+  fn from(usage: ResponseUsage) -> Self {
+    AiUsage::new(usage.input_tokens, usage.output_tokens, 0.0, 0)
+  }
+}
+
+impl From<CompletionUsage> for AiUsage {
+  fn from(usage: CompletionUsage) -> Self {
+    AiUsage::new(usage.prompt_tokens, usage.completion_tokens, 0.0, 0)
+  }
+}
+
+/// Words of a whisper transcription with their timestamps, each one recognized as confidently as
+/// its segment, as whisper doesn't provide word-level confidence
+fn words_from_timestamps(
+  words: Vec<TranscriptionWord>,
+  segments: &[TranscriptionSegment],
+) -> Vec<TranscribedWord> {
+  words
+    .into_iter()
+    .map(|word| {
+      let confidence = segments
+        .iter()
+        .find(|segment| segment.start <= word.start && word.start < segment.end)
+        .map(|segment| segment.avg_logprob.exp().clamp(0.0, 1.0))
+        .unwrap_or(1.0);
+
+      TranscribedWord::new(word.word, Some(word.start), Some(word.end), confidence)
+    })
+    .collect()
+}
+
+/// Words of a transcription from the log probabilities of its tokens, each one recognized with
+/// the mean probability of its tokens. A token starting with a whitespace starts a new word.
+pub fn words_from_logprobs(logprobs: &[LogProbProperties]) -> Vec<TranscribedWord> {
+  let mut words = Vec::new();
+  let mut word = String::new();
+  let mut word_logprobs = Vec::new();
+  let mut push_word = |word: &mut String, word_logprobs: &mut Vec<f64>| {
+    if !word.trim().is_empty() {
+      let mean_logprob = word_logprobs.iter().sum::<f64>() / word_logprobs.len() as f64;
+      words.push(TranscribedWord::new(
+        word.trim().to_string(),
+        None,
+        None,
+        mean_logprob.exp().clamp(0.0, 1.0) as f32,
+      ));
+    }
+    word.clear();
+    word_logprobs.clear();
+  };
+
+  for logprob in logprobs {
+    if logprob.token.starts_with(char::is_whitespace) {
+      push_word(&mut word, &mut word_logprobs);
+    }
+    word.push_str(&logprob.token);
+    word_logprobs.push(logprob.logprob);
+  }
+  push_word(&mut word, &mut word_logprobs);
+
+  words
+}
+
+impl From<TranscriptionUsage> for AiUsage {
+  fn from(usage: TranscriptionUsage) -> Self {
+    match usage {
+      TranscriptionUsage::Tokens(tokens) => {
+        AiUsage::new(tokens.input_tokens, tokens.output_tokens, 0.0, 0)
+      }
+      TranscriptionUsage::Duration(duration) => AiUsage::new(0, 0, duration.seconds, 0),
+    }
   }
 }
 
@@ -60,21 +495,15 @@ impl From<&ChatMessage> for EasyInputMessage {
   }
 }
 
-/// Deserialized learned material returned by API
-#[derive(Debug, Clone, JsonSchema, Deserialize)]
-#[serde(deny_unknown_fields)]
-#[allow(clippy::missing_docs_in_private_items)]
-pub struct ApiLearnedMaterial {
-  vocab: Vec<String>,
-}
-
-/// Deserialized generation API response
-#[derive(Debug, Clone, JsonSchema, Deserialize)]
-#[serde(deny_unknown_fields)]
-#[allow(clippy::missing_docs_in_private_items)]
-pub struct ApiResponse {
-  response: String,
-  learned_material: ApiLearnedMaterial,
+impl From<&ChatMessage> for ChatCompletionRequestMessage {
+  fn from(chat_message: &ChatMessage) -> Self {
+    let content = chat_message.message().to_string();
+    match chat_message.role() {
+      ChatMessageRole::User => ChatCompletionRequestUserMessage::from(content).into(),
+      ChatMessageRole::Ai => ChatCompletionRequestAssistantMessage::from(content).into(),
+      ChatMessageRole::System => ChatCompletionRequestSystemMessage::from(content).into(),
+    }
+  }
 }
 
 impl AiGateway for OpenAi {
@@ -82,13 +511,15 @@ impl AiGateway for OpenAi {
     &self,
     model: &str,
     text: String,
+    voice: &str,
     instructions: Option<&str>,
   ) -> Result<TextToSpeechResponse, EpisError> {
+    let usage = AiUsage::new(0, 0, 0.0, text.chars().count() as u32);
     let request = CreateSpeechRequestArgs::default()
-      .input(text.to_string())
+      .input(text)
       .model(SpeechModel::Other(model.into()))
       .instructions(instructions.unwrap_or_default())
-      .voice(Voice::Alloy)
+      .voice(parse_voice(voice))
       .build()
       .expect("Speech request can be built from text");
     debug!("Speech request built");
@@ -99,11 +530,41 @@ impl AiGateway for OpenAi {
       .speech()
       .create(request)
       .await
-      .inspect_err(|error| warn!(%error, "Tts request failed"))
-      .map_err(|_| EpisError::ProviderError)?;
+      .map_err(|error| {
+        warn!(%error, "Tts request failed");
+        provider_error(&error)
+      })?;
     debug!("Speech request done successfully");
 
-    Ok(response.bytes.to_vec())
+    Ok(TextToSpeechResponse::new(response.bytes.to_vec(), usage))
+  }
+
+  async fn embed(&self, model: &str, texts: &[String]) -> Result<EmbeddingResponse, EpisError> {
+    let response = self
+      .client
+      .embeddings()
+      .create(CreateEmbeddingRequest {
+        model: model.to_string(),
+        input: EmbeddingInput::StringArray(texts.to_vec()),
+        ..Default::default()
+      })
+      .await
+      .map_err(|error| {
+        warn!(%error, "Embedding request failed");
+        provider_error(&error)
+      })?;
+    debug!("Embedding was done successfully");
+
+    let mut data = response.data;
+    data.sort_by_key(|embedding| embedding.index);
+
+    Ok(EmbeddingResponse::new(
+      data
+        .into_iter()
+        .map(|embedding| embedding.embedding)
+        .collect(),
+      AiUsage::new(response.usage.prompt_tokens, 0, 0.0, 0),
+    ))
   }
 
   async fn transcribe(
@@ -113,33 +574,65 @@ impl AiGateway for OpenAi {
     audio_format: EpisAudioMessageFormat,
     instructions: Option<&str>,
   ) -> Result<TranscriptionResponse, EpisError> {
-    let request = CreateTranscriptionRequestArgs::default()
+    let mut request_args = CreateTranscriptionRequestArgs::default();
+    request_args
       .file(AudioInput::from_vec_u8(
         format!("input.{audio_format}"),
         audio_bytes,
       ))
       .model(model)
-      .prompt(instructions.unwrap_or(""))
-      .build()
-      .map_err(|error| {
-        warn!(%error, "Cannot build transcription request");
-        EpisError::ProviderError
-      })?;
+      .prompt(instructions.unwrap_or(""));
+    // Whisper provides word timestamps only in verbose json, while newer models provide token
+    // log probabilities only in json
+    let is_whisper = model.starts_with(WHISPER_MODEL_PREFIX);
+    if is_whisper {
+      request_args
+        .response_format(AudioResponseFormat::VerboseJson)
+        .timestamp_granularities(vec![
+          TimestampGranularity::Word,
+          TimestampGranularity::Segment,
+        ]);
+    } else {
+      request_args.include(vec![TranscriptionInclude::Logprobs]);
+    }
+    let request = request_args.build().map_err(|error| {
+      warn!(%error, "Cannot build transcription request");
+      EpisError::ProviderError
+    })?;
     debug!("Transcription request built");
 
-    let response = self
-      .client
-      .audio()
-      .transcription()
-      .create(request)
-      .await
-      .map_err(|error| {
+    let audio = self.client.audio();
+    let transcription = audio.transcription();
+    let transcription_response = if is_whisper {
+      let response = transcription
+        .create_verbose_json(request)
+        .await
+        .map_err(|error| {
+          warn!(%error, "Transcription request failed");
+          provider_error(&error)
+        })?;
+      let words = words_from_timestamps(
+        response.words.unwrap_or_default(),
+        &response.segments.unwrap_or_default(),
+      );
+
+      TranscriptionResponse::new(
+        response.text,
+        words,
+        AiUsage::new(0, 0, response.usage.seconds, 0),
+      )
+    } else {
+      let response = transcription.create(request).await.map_err(|error| {
         warn!(%error, "Transcription request failed");
-        EpisError::ProviderError
+        provider_error(&error)
       })?;
+      let words = words_from_logprobs(&response.logprobs.unwrap_or_default());
+
+      TranscriptionResponse::new(response.text, words, response.usage.into())
+    };
     debug!("Transcription was done successfully");
 
-    Ok(response.text)
+    Ok(transcription_response)
   }
 
   async fn generate(
@@ -147,61 +640,193 @@ impl AiGateway for OpenAi {
     model: &str,
     messages: &[ChatMessage],
   ) -> Result<GenerationResponse, EpisError> {
-    let input = InputParam::Items(
-      messages
-        .iter()
-        .map(|message| InputItem::EasyMessage(message.into()))
-        .collect::<Vec<_>>(),
-    );
+    let schema = schema_for!(ApiResponse);
+    let schema_value = serde_json::to_value(schema).map_err(|_| EpisError::ProviderError)?;
+
+    let (output_text, usage) = self
+      .create_output_text(model, messages, schema_value.clone())
+      .await?;
+    let generation_response = parse_or_repair_generation(
+      output_text,
+      usage,
+      messages,
+      self.max_repair_attempts,
+      |repair_messages| {
+        let schema_value = schema_value.clone();
+        async move {
+          self
+            .create_output_text(model, &repair_messages, schema_value)
+            .await
+        }
+      },
+    )
+    .await?;
+    debug!("Response generation was done successfully");
+
+    Ok(generation_response)
+  }
 
+  async fn assess_cefr_level(
+    &self,
+    model: &str,
+    messages: &[ChatMessage],
+  ) -> Result<CefrAssessment, EpisError> {
+    let schema = schema_for!(ApiCefrAssessment);
+    let schema_value = serde_json::to_value(schema).map_err(|_| EpisError::ProviderError)?;
+
+    let (output_text, usage) = self
+      .create_output_text(model, messages, schema_value.clone())
+      .await?;
+    let (api_cefr_assessment, usage) = parse_or_repair(
+      output_text,
+      usage,
+      messages,
+      self.max_repair_attempts,
+      parse_json_output::<ApiCefrAssessment>,
+      |repair_messages| {
+        let schema_value = schema_value.clone();
+        async move {
+          self
+            .create_output_text(model, &repair_messages, schema_value)
+            .await
+        }
+      },
+    )
+    .await?;
+    debug!("CEFR level assessment was done successfully");
+
+    Ok(api_cefr_assessment.into_cefr_assessment(usage))
+  }
+
+  async fn summarize_chat(
+    &self,
+    model: &str,
+    messages: &[ChatMessage],
+  ) -> Result<ChatSummary, EpisError> {
+    let schema = schema_for!(ApiChatSummary);
+    let schema_value = serde_json::to_value(schema).map_err(|_| EpisError::ProviderError)?;
+
+    let (output_text, usage) = self
+      .create_output_text(model, messages, schema_value.clone())
+      .await?;
+    let (api_chat_summary, usage) = parse_or_repair(
+      output_text,
+      usage,
+      messages,
+      self.max_repair_attempts,
+      parse_json_output::<ApiChatSummary>,
+      |repair_messages| {
+        let schema_value = schema_value.clone();
+        async move {
+          self
+            .create_output_text(model, &repair_messages, schema_value)
+            .await
+        }
+      },
+    )
+    .await?;
+    debug!("Chat summarization was done successfully");
+
+    Ok(api_chat_summary.into_chat_summary(usage))
+  }
+
+  async fn generate_stream(
+    &self,
+    model: &str,
+    messages: &[ChatMessage],
+    text_deltas: Sender<String>,
+  ) -> Result<GenerationResponse, EpisError> {
     let schema = schema_for!(ApiResponse);
     let schema_value = serde_json::to_value(schema).map_err(|_| EpisError::ProviderError)?;
 
-    let request = CreateResponseArgs::default()
-      // TODO: Set max tokens based on data
-      // https://github.com/mkermani144/epis/issues/10
-      .max_output_tokens(10000u32)
-      .model(model)
-      .text(ResponseTextParam {
-        format: TextResponseFormatConfiguration::JsonSchema(ResponseFormatJsonSchema {
-          description: None,
-          name: "ai_response".to_string(),
-          strict: Some(true),
-          schema: Some(schema_value),
-        }),
-        verbosity: Some(Verbosity::Low),
-      })
-      .reasoning(Reasoning {
-        effort: Some(ReasoningEffort::Low),
-        summary: None,
-      })
-      .input(input)
-      .build()
-      .expect("Responses request can be built from the provided args");
+    let mut extractor = ResponseTextExtractor::default();
+    let (output_text, usage) = self
+      .create_output_text_stream(
+        model,
+        messages,
+        schema_value.clone(),
+        &mut extractor,
+        &text_deltas,
+      )
+      .await?;
+    // Repairs are not streamed, as the forwarded text is reconciled with the repaired one
+    let generation_response = parse_or_repair_generation(
+      output_text,
+      usage,
+      messages,
+      self.max_repair_attempts,
+      |repair_messages| {
+        let schema_value = schema_value.clone();
+        async move {
+          self
+            .create_output_text(model, &repair_messages, schema_value)
+            .await
+        }
+      },
+    )
+    .await?;
+    let generation_response = extractor.reconcile(generation_response, &text_deltas).await;
+    debug!("Streaming response generation was done successfully");
 
-    let response = self
-      .client
-      .responses()
-      .create(request)
-      .await
-      .map_err(|error| {
-        warn!(%error, "Cannot generate a response");
-        EpisError::ProviderError
-      })?;
+    Ok(generation_response)
+  }
+}
 
-    if let Some(output_text) = response.output_text() {
-      let ai_reply: ApiResponse = serde_json::from_str(&output_text).map_err(|error| {
-        warn!(%error, "Cannot deserialize llm output");
-        EpisError::ProviderError
-      })?;
-      debug!("Response generation was done successfully");
+#[cfg(test)]
+mod tests {
+  use super::*;
 
-      Ok(GenerationResponse::new(
-        ai_reply.response,
-        ai_reply.learned_material.vocab,
-      ))
-    } else {
-      Err(EpisError::ProviderError)
+  /// Build an api error with a message along with a type and a code
+  fn api_error(message: &str, r#type: Option<&str>, code: Option<&str>) -> OpenAIError {
+    OpenAIError::ApiError(ApiError {
+      message: message.to_string(),
+      r#type: r#type.map(str::to_string),
+      param: None,
+      code: code.map(str::to_string),
+    })
+  }
+
+  /// Rate limiting and server errors are transient, whether they are unwrapped or carry their raw
+  /// body
+  #[test]
+  fn classifies_rate_limits_and_server_errors_as_transient() {
+    let rate_limit = api_error(
+      "Rate limit reached",
+      Some("requests"),
+      Some("rate_limit_exceeded"),
+    );
+    let server_error = api_error(
+      r#"{"error":{"message":"The server had an error","type":"server_error"}}"#,
+      None,
+      None,
+    );
+    let overloaded = api_error(r#"{"error":{"message":"Overloaded"}}"#, None, None);
+
+    for error in [rate_limit, server_error, overloaded] {
+      assert!(matches!(
+        provider_error(&error),
+        EpisError::TransientProviderError
+      ));
+    }
+  }
+
+  /// Errors of misconfigured requests are permanent, even without a type, a code or a json body,
+  /// e.g. a 404 of a wrong base url
+  #[test]
+  fn classifies_request_errors_as_permanent() {
+    let insufficient_quota = api_error(
+      "You exceeded your current quota",
+      Some("insufficient_quota"),
+      Some("insufficient_quota"),
+    );
+    let not_found = api_error("Not found", None, None);
+    let html_not_found = OpenAIError::JSONDeserialize(
+      serde_json::from_str::<Value>("<html>404 Not Found</html>").unwrap_err(),
+      "<html>404 Not Found</html>".to_string(),
+    );
+
+    for error in [insufficient_quota, not_found, html_not_found] {
+      assert!(matches!(provider_error(&error), EpisError::ProviderError));
     }
   }
 }
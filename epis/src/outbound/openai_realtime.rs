@@ -0,0 +1,1215 @@
+//! OpenAI Realtime api implementation of the realtime ai agent, keeping a speech to speech
+//! websocket session per chat
+
+use std::{
+  collections::HashMap,
+  io::Cursor,
+  sync::{Arc, Mutex},
+  time::{Duration, Instant},
+};
+
+use async_openai::types::{LogProbProperties, audio::TranscriptionUsage};
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+use futures::{SinkExt, StreamExt};
+use hound::{SampleFormat, WavReader, WavSpec, WavWriter};
+use schemars::schema_for;
+use serde::Deserialize;
+use serde_json::{Value, json};
+use tokio::{net::TcpStream, sync::Mutex as AsyncMutex, sync::mpsc::Sender, time::timeout};
+use tokio_tungstenite::{
+  MaybeTlsStream, WebSocketStream, connect_async,
+  tungstenite::{
+    Message,
+    client::IntoClientRequest,
+    http::{HeaderValue, header::AUTHORIZATION},
+  },
+};
+use tracing::{debug, trace, warn};
+
+use crate::{
+  domain::{
+    curriculum::get_learning_focus,
+    memory::CHAT_HISTORY_SIZE,
+    models::{
+      AiUsage, CefrLevel, CefrProgressionMode, ChatEvent, ChatMessage, ChatMessageRole,
+      CreditAuthStatus, EpisAudioMessage, EpisAudioMessageFormat, EpisError, GenerationResponse,
+      ModerationAction, ModerationStage, RealtimeAiAgentChatContext, SimpleBytes,
+      TranscriptionResponse,
+    },
+    placement::{assess_placement_turn, generate_placement_instructions, probe_level},
+    ports::{
+      AiGateway, EpisRepository, HistoryRetriever, ModerationPort, RealtimeAiAgent, UserManagement,
+    },
+    realtime_ai_agent::{
+      ChatTurn, generate_canned_reply, generate_instructions, generate_speech_instructions,
+      moderate, record_chat_turn, spawn_memory_update, spawn_progression_evaluation,
+    },
+    recall::{RECALL_LIMIT, generate_recall_message},
+    review_scheduler::ReviewScheduler,
+    scenario::advance_scenario,
+  },
+  outbound::{openai::words_from_logprobs, structured_output::ApiTurnReport},
+};
+
+/// Websocket base url of the OpenAI Realtime api
+const OPENAI_REALTIME_BASE_URL: &str = "wss://api.openai.com/v1/realtime";
+/// Default speech to speech model
+const DEFAULT_REALTIME_MODEL: &str = "gpt-realtime";
+/// Default model transcribing user messages
+const DEFAULT_REALTIME_TRANSCRIPTION_MODEL: &str = "gpt-4o-mini-transcribe";
+
+/// Sample rate of the 16-bit mono pcm audio of the Realtime api
+const SAMPLE_RATE: u32 = 24_000;
+/// Size of one second of the pcm audio
+const PCM_BYTES_PER_SECOND: usize = SAMPLE_RATE as usize * 2;
+/// Size of each reply audio chunk, i.e. one second of pcm audio
+const REPLY_CHUNK_BYTES: usize = PCM_BYTES_PER_SECOND;
+/// Size of each appended input audio chunk, so that no websocket message gets too large
+const INPUT_CHUNK_BYTES: usize = REPLY_CHUNK_BYTES * 4;
+/// Idle duration after which a session is closed instead of being reused
+const SESSION_IDLE_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+/// Maximum duration of receiving the transcription, or the reply, of a chat turn, after which the
+/// session is closed, so that a missing event cannot hang the chat
+const TURN_TIMEOUT: Duration = Duration::from_secs(60);
+/// Speed of speech for beginner users
+const BEGINNER_SPEECH_SPEED: f32 = 0.85;
+/// Name of the function the model calls for reporting the learned material, the corrections and
+/// the scenario progress of a turn
+const TURN_REPORT_FUNCTION: &str = "report_turn";
+
+/// A websocket connection to the Realtime api
+type RealtimeSocket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// A Realtime api session of a chat
+#[derive(Debug)]
+struct RealtimeSession {
+  /// Websocket of the session, locked during a chat turn
+  socket: Arc<AsyncMutex<RealtimeSocket>>,
+  /// Last time the session was used
+  last_used: Instant,
+}
+
+/// Error of a Realtime api server event
+#[derive(Debug, Deserialize)]
+#[allow(clippy::missing_docs_in_private_items)]
+struct ServerError {
+  message: String,
+}
+
+/// Usage of a Realtime api response
+#[derive(Debug, Deserialize)]
+#[allow(clippy::missing_docs_in_private_items)]
+struct ServerResponseUsage {
+  input_tokens: u32,
+  output_tokens: u32,
+}
+
+/// A Realtime api response, as reported when it's done
+#[derive(Debug, Deserialize)]
+#[allow(clippy::missing_docs_in_private_items)]
+struct ServerResponse {
+  status: String,
+  usage: Option<ServerResponseUsage>,
+}
+
+/// The Realtime api server events needed by a chat turn, ignoring all other events
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type")]
+#[allow(clippy::missing_docs_in_private_items)]
+enum ServerEvent {
+  #[serde(rename = "error")]
+  Error { error: ServerError },
+  #[serde(rename = "conversation.item.input_audio_transcription.completed")]
+  InputAudioTranscriptionCompleted {
+    transcript: String,
+    usage: Option<TranscriptionUsage>,
+    logprobs: Option<Vec<LogProbProperties>>,
+  },
+  #[serde(rename = "conversation.item.input_audio_transcription.failed")]
+  InputAudioTranscriptionFailed { error: ServerError },
+  #[serde(rename = "response.output_audio.delta")]
+  OutputAudioDelta { delta: String },
+  #[serde(rename = "response.output_audio_transcript.done")]
+  OutputAudioTranscriptDone { transcript: String },
+  #[serde(rename = "response.function_call_arguments.done")]
+  FunctionCallArgumentsDone { call_id: String, arguments: String },
+  #[serde(rename = "response.done")]
+  ResponseDone { response: ServerResponse },
+  #[serde(other)]
+  Other,
+}
+
+/// Implementation of [RealtimeAiAgent] with the OpenAI Realtime api, keeping a websocket session
+/// per chat so that the conversation state lives on the provider side. Placement, progression and
+/// chat summaries are done by a text llm, and the learned material, corrections and scenario
+/// progress of a reply are reported by the Realtime api through a function call.
+///
+/// # Notes
+/// User messages are moderated once transcribed, and an unsafe one is replied with a canned reply
+/// instead. Replies are spoken as they are generated, so they cannot be moderated.
+#[derive(Debug, Clone)]
+pub struct OpenAiRealtimeAiAgent<
+  AG: AiGateway,
+  UM: UserManagement,
+  ER: EpisRepository,
+  MP: ModerationPort,
+  RS: ReviewScheduler,
+  HR: HistoryRetriever,
+> {
+  /// Ai gateway, for the text generations of placement, progression and chat summaries
+  ai_gateway: Arc<AG>,
+  /// User management, for credit and CEFR level
+  user_management: Arc<UM>,
+  /// Epis repository, for chatmates, messages and vocab
+  epis_repo: Arc<ER>,
+  /// Moderation of user messages
+  moderation: Arc<MP>,
+  /// Scheduler of learned vocab reviews
+  review_scheduler: Arc<RS>,
+  /// Retriever of past exchanges related to user messages
+  history_retriever: Arc<HR>,
+  /// How proposed CEFR level changes are applied
+  cefr_progression_mode: CefrProgressionMode,
+  /// Text generation model of the ai gateway
+  generation_model: String,
+  /// Speech to speech model
+  model: String,
+  /// Model transcribing user messages
+  transcription_model: String,
+  /// Websocket base url of the Realtime api
+  base_url: String,
+  /// Realtime api key
+  api_key: String,
+  /// Open sessions by chatmate id
+  sessions: Arc<Mutex<HashMap<String, RealtimeSession>>>,
+}
+
+impl<
+  AG: AiGateway,
+  UM: UserManagement,
+  ER: EpisRepository,
+  MP: ModerationPort,
+  RS: ReviewScheduler,
+  HR: HistoryRetriever,
+> OpenAiRealtimeAiAgent<AG, UM, ER, MP, RS, HR>
+{
+  /// Construct an [OpenAiRealtimeAiAgent], defaulting to the OpenAI Realtime api and its default
+  /// models
+  #[allow(clippy::too_many_arguments)]
+  pub fn new(
+    ai_gateway: Arc<AG>,
+    user_management: Arc<UM>,
+    epis_repo: Arc<ER>,
+    moderation: Arc<MP>,
+    review_scheduler: Arc<RS>,
+    history_retriever: Arc<HR>,
+    cefr_progression_mode: CefrProgressionMode,
+    generation_model: String,
+    model: Option<String>,
+    transcription_model: Option<String>,
+    base_url: Option<String>,
+    api_key: String,
+  ) -> Self {
+    Self {
+      ai_gateway,
+      user_management,
+      epis_repo,
+      moderation,
+      review_scheduler,
+      history_retriever,
+      cefr_progression_mode,
+      generation_model,
+      model: model.unwrap_or(DEFAULT_REALTIME_MODEL.to_string()),
+      transcription_model: transcription_model
+        .unwrap_or(DEFAULT_REALTIME_TRANSCRIPTION_MODEL.to_string()),
+      base_url: base_url.unwrap_or(OPENAI_REALTIME_BASE_URL.to_string()),
+      api_key,
+      sessions: Arc::new(Mutex::new(HashMap::new())),
+    }
+  }
+
+  /// Get the open session of a chat, or open a new one seeded with its message history. Idle
+  /// sessions of all chats are closed along the way.
+  async fn session(
+    &self,
+    chatmate_key: &str,
+    message_history: &[ChatMessage],
+  ) -> Result<Arc<AsyncMutex<RealtimeSocket>>, EpisError> {
+    {
+      let mut sessions = self.sessions.lock().map_err(|_| EpisError::Unknown)?;
+      sessions.retain(|_, session| session.last_used.elapsed() < SESSION_IDLE_TIMEOUT);
+      if let Some(session) = sessions.get_mut(chatmate_key) {
+        session.last_used = Instant::now();
+        return Ok(session.socket.clone());
+      }
+    }
+
+    let socket = Arc::new(AsyncMutex::new(self.connect(message_history).await?));
+    self
+      .sessions
+      .lock()
+      .map_err(|_| EpisError::Unknown)?
+      .insert(
+        chatmate_key.to_string(),
+        RealtimeSession {
+          socket: socket.clone(),
+          last_used: Instant::now(),
+        },
+      );
+    debug!("Realtime session opened");
+
+    Ok(socket)
+  }
+
+  /// Check if a chat has an open session which is not idle
+  fn has_session(&self, chatmate_key: &str) -> bool {
+    self.sessions.lock().is_ok_and(|sessions| {
+      sessions
+        .get(chatmate_key)
+        .is_some_and(|session| session.last_used.elapsed() < SESSION_IDLE_TIMEOUT)
+    })
+  }
+
+  /// Close the session of a chat, e.g. after it fails, so that the next turn reconnects
+  fn close_session(&self, chatmate_key: &str) {
+    if let Ok(mut sessions) = self.sessions.lock() {
+      sessions.remove(chatmate_key);
+    }
+  }
+
+  /// Connect to the Realtime api and seed the conversation with the message history
+  async fn connect(&self, message_history: &[ChatMessage]) -> Result<RealtimeSocket, EpisError> {
+    let mut request = format!("{}?model={}", self.base_url, self.model)
+      .into_client_request()
+      .map_err(|error| {
+        warn!(%error, "Invalid realtime api url");
+        EpisError::ProviderError
+      })?;
+    let authorization =
+      HeaderValue::from_str(&format!("Bearer {}", self.api_key)).map_err(|error| {
+        warn!(%error, "Invalid realtime api key");
+        EpisError::ProviderError
+      })?;
+    request.headers_mut().insert(AUTHORIZATION, authorization);
+
+    let (mut socket, _) = connect_async(request).await.map_err(|error| {
+      warn!(%error, "Cannot connect to realtime api");
+      EpisError::ProviderError
+    })?;
+
+    for message in message_history {
+      let (role, content_type) = match message.role() {
+        ChatMessageRole::User => ("user", "input_text"),
+        ChatMessageRole::Ai => ("assistant", "output_text"),
+        ChatMessageRole::System => continue,
+      };
+      send_event(
+        &mut socket,
+        json!({
+          "type": "conversation.item.create",
+          "item": {
+            "type": "message",
+            "role": role,
+            "content": [{ "type": content_type, "text": message.message() }],
+          },
+        }),
+      )
+      .await?;
+    }
+
+    Ok(socket)
+  }
+}
+
+/// Send a client event over a Realtime api websocket
+async fn send_event(socket: &mut RealtimeSocket, event: Value) -> Result<(), EpisError> {
+  socket
+    .send(Message::Text(event.to_string().into()))
+    .await
+    .map_err(|error| {
+      warn!(%error, "Cannot send realtime api event");
+      EpisError::ProviderError
+    })
+}
+
+/// Build the session update of a chat turn, configuring voice, audio formats, transcription and
+/// the turn report function. The instructions are given per response instead, as they depend on
+/// the transcription of the turn.
+fn session_update(
+  model: &str,
+  transcription_model: &str,
+  voice: &str,
+  cefr_level: &CefrLevel,
+) -> Value {
+  let speed = match cefr_level {
+    CefrLevel::A1 | CefrLevel::A2 => BEGINNER_SPEECH_SPEED,
+    _ => 1.0,
+  };
+  let audio_format = json!({ "type": "audio/pcm", "rate": SAMPLE_RATE });
+
+  json!({
+    "type": "session.update",
+    "session": {
+      "type": "realtime",
+      "model": model,
+      "output_modalities": ["audio"],
+      // Token log probabilities of transcriptions are used for scoring pronunciation
+      "include": ["item.input_audio_transcription.logprobs"],
+      "audio": {
+        "input": {
+          "format": audio_format,
+          "transcription": { "model": transcription_model },
+          // Each chat turn is a whole recorded message, so turns are committed manually
+          "turn_detection": null,
+        },
+        "output": {
+          "format": audio_format,
+          "voice": voice,
+          "speed": speed,
+        },
+      },
+      "tools": [{
+        "type": "function",
+        "name": TURN_REPORT_FUNCTION,
+        "description": "Report the learned material used in the reply, the corrections of the user message and the scenario progress",
+        "parameters": schema_for!(ApiTurnReport),
+      }],
+      "tool_choice": "auto",
+    },
+  })
+}
+
+/// Decode a wav message into 16-bit little endian mono pcm at the Realtime api sample rate,
+/// downmixing and linearly resampling it if needed
+fn wav_to_pcm(audio_bytes: &[u8]) -> anyhow::Result<SimpleBytes> {
+  let mut reader = WavReader::new(Cursor::new(audio_bytes))?;
+  let spec = reader.spec();
+  let samples = match spec.sample_format {
+    SampleFormat::Float => reader.samples::<f32>().collect::<Result<Vec<_>, _>>()?,
+    SampleFormat::Int => {
+      let scale = (1i64 << (spec.bits_per_sample - 1)) as f32;
+      reader
+        .samples::<i32>()
+        .map(|sample| sample.map(|sample| sample as f32 / scale))
+        .collect::<Result<Vec<_>, _>>()?
+    }
+  };
+
+  let mono = samples
+    .chunks(usize::from(spec.channels.max(1)))
+    .map(|frame| frame.iter().sum::<f32>() / frame.len() as f32)
+    .collect::<Vec<_>>();
+
+  let ratio = f64::from(spec.sample_rate) / f64::from(SAMPLE_RATE);
+  let resampled_len = (mono.len() as f64 / ratio) as usize;
+  let pcm = (0..resampled_len)
+    .flat_map(|index| {
+      let position = index as f64 * ratio;
+      let source_index = position as usize;
+      let fraction = (position - source_index as f64) as f32;
+      let current = mono[source_index];
+      let next = mono.get(source_index + 1).copied().unwrap_or(current);
+      let sample = current + (next - current) * fraction;
+      ((sample.clamp(-1.0, 1.0) * f32::from(i16::MAX)) as i16).to_le_bytes()
+    })
+    .collect();
+
+  Ok(pcm)
+}
+
+/// Encode 16-bit little endian mono pcm at the Realtime api sample rate into wav
+fn pcm_to_wav(pcm: &[u8]) -> anyhow::Result<SimpleBytes> {
+  let mut cursor = Cursor::new(Vec::new());
+  let mut writer = WavWriter::new(
+    &mut cursor,
+    WavSpec {
+      channels: 1,
+      sample_rate: SAMPLE_RATE,
+      bits_per_sample: 16,
+      sample_format: SampleFormat::Int,
+    },
+  )?;
+  for sample in pcm.chunks_exact(2) {
+    writer.write_sample(i16::from_le_bytes([sample[0], sample[1]]))?;
+  }
+  writer.finalize()?;
+
+  Ok(cursor.into_inner())
+}
+
+/// Send a chunk of reply pcm audio as a wav message
+async fn send_reply_chunk(
+  pcm: &[u8],
+  reply_chunks: &Sender<EpisAudioMessage>,
+) -> Result<(), EpisError> {
+  let wav = pcm_to_wav(pcm).map_err(|error| {
+    warn!(%error, "Cannot encode reply audio");
+    EpisError::Unknown
+  })?;
+  reply_chunks
+    .send(EpisAudioMessage::new(wav, EpisAudioMessageFormat::Wav))
+    .await
+    .inspect_err(|_| warn!("Reply chunks receiver is dropped"))
+    .map_err(|_| EpisError::Unknown)?;
+  trace!("Reply chunk sent");
+
+  Ok(())
+}
+
+/// Build the response speaking a canned reply instead of replying to an unsafe user message. The
+/// response is out of band, so it neither sees the conversation nor is added to it.
+fn canned_reply_response(canned_reply: &str) -> Value {
+  json!({
+    "conversation": "none",
+    "input": [],
+    "instructions": format!("Say exactly the following, and nothing else: {canned_reply}"),
+    "tool_choice": "none",
+  })
+}
+
+/// Receive the next Realtime api server event of a session
+async fn next_event(socket: &mut RealtimeSocket) -> Result<ServerEvent, EpisError> {
+  loop {
+    let message = socket
+      .next()
+      .await
+      .ok_or_else(|| {
+        warn!("Realtime api closed the session");
+        EpisError::ProviderError
+      })?
+      .map_err(|error| {
+        warn!(%error, "Cannot receive realtime api event");
+        EpisError::ProviderError
+      })?;
+    let Message::Text(text) = message else {
+      continue;
+    };
+
+    return serde_json::from_str(&text).map_err(|error| {
+      warn!(%error, "Cannot deserialize realtime api event");
+      EpisError::ProviderError
+    });
+  }
+}
+
+/// Transcribe the user message of a chat turn over a session: configure the session, send the
+/// user audio, and return its transcription once it's done, failing if it's not done within the
+/// turn timeout. The reply is not created yet, so that it can use the transcription.
+async fn transcribe_turn(
+  socket: &mut RealtimeSocket,
+  session_update: Value,
+  pcm: &[u8],
+  turn_timeout: Duration,
+) -> Result<TranscriptionResponse, EpisError> {
+  send_event(socket, session_update).await?;
+  for chunk in pcm.chunks(INPUT_CHUNK_BYTES) {
+    send_event(
+      socket,
+      json!({ "type": "input_audio_buffer.append", "audio": BASE64.encode(chunk) }),
+    )
+    .await?;
+  }
+  send_event(socket, json!({ "type": "input_audio_buffer.commit" })).await?;
+
+  let transcription = timeout(turn_timeout, async {
+    loop {
+      match next_event(socket).await? {
+        ServerEvent::Error { error } => {
+          warn!(error = error.message, "Realtime api returned an error");
+          return Err(EpisError::ProviderError);
+        }
+        ServerEvent::InputAudioTranscriptionCompleted {
+          transcript,
+          usage,
+          logprobs,
+        } => {
+          return Ok(TranscriptionResponse::new(
+            transcript,
+            words_from_logprobs(&logprobs.unwrap_or_default()),
+            usage.map(AiUsage::from).unwrap_or_default(),
+          ));
+        }
+        ServerEvent::InputAudioTranscriptionFailed { error } => {
+          warn!(
+            error = error.message,
+            "Realtime api cannot transcribe user message"
+          );
+          return Err(EpisError::ProviderError);
+        }
+        _ => {}
+      }
+    }
+  })
+  .await;
+
+  transcription.map_err(|_| {
+    warn!("Realtime api transcription timed out");
+    EpisError::ProviderError
+  })?
+}
+
+/// Reply to the user message of a chat turn over a session: create the given response, stream its
+/// audio chunks, and return it along with its report once it's done, failing if it's not done
+/// within the turn timeout
+async fn reply_turn(
+  socket: &mut RealtimeSocket,
+  response: Value,
+  reply_chunks: &Sender<EpisAudioMessage>,
+  turn_timeout: Duration,
+) -> Result<GenerationResponse, EpisError> {
+  send_event(
+    socket,
+    json!({ "type": "response.create", "response": response }),
+  )
+  .await?;
+
+  let mut reply = String::new();
+  let mut turn_report = None;
+  let mut function_call_ids = Vec::new();
+  let mut pending_audio = Vec::new();
+  let mut reply_audio_bytes = 0;
+
+  let reply_usage = timeout(turn_timeout, async {
+    loop {
+      match next_event(socket).await? {
+        ServerEvent::Error { error } => {
+          warn!(error = error.message, "Realtime api returned an error");
+          return Err(EpisError::ProviderError);
+        }
+        ServerEvent::OutputAudioDelta { delta } => {
+          let audio = BASE64.decode(delta).map_err(|error| {
+            warn!(%error, "Cannot decode reply audio delta");
+            EpisError::ProviderError
+          })?;
+          reply_audio_bytes += audio.len();
+          pending_audio.extend(audio);
+          while pending_audio.len() >= REPLY_CHUNK_BYTES {
+            let chunk = pending_audio.drain(..REPLY_CHUNK_BYTES).collect::<Vec<_>>();
+            send_reply_chunk(&chunk, reply_chunks).await?;
+          }
+        }
+        ServerEvent::OutputAudioTranscriptDone { transcript } => reply.push_str(&transcript),
+        ServerEvent::FunctionCallArgumentsDone { call_id, arguments } => {
+          match serde_json::from_str::<ApiTurnReport>(&arguments) {
+            Ok(report) => turn_report = Some(report),
+            Err(error) => warn!(%error, "Cannot deserialize turn report, ignoring it"),
+          }
+          function_call_ids.push(call_id);
+        }
+        ServerEvent::ResponseDone { response } => {
+          if matches!(response.status.as_str(), "failed" | "cancelled") {
+            warn!(
+              status = response.status,
+              "Realtime api response was not completed"
+            );
+            return Err(EpisError::ProviderError);
+          }
+          return Ok(
+            response
+              .usage
+              .map(|usage| AiUsage::new(usage.input_tokens, usage.output_tokens, 0.0, 0))
+              .unwrap_or_default(),
+          );
+        }
+        _ => {}
+      }
+    }
+  })
+  .await;
+  let mut reply_usage = reply_usage.map_err(|_| {
+    warn!("Realtime api reply timed out");
+    EpisError::ProviderError
+  })??;
+
+  if !pending_audio.is_empty() {
+    send_reply_chunk(&pending_audio, reply_chunks).await?;
+  }
+
+  // Function calls must be answered, so that the conversation stays valid for the next turn
+  for call_id in function_call_ids {
+    send_event(
+      socket,
+      json!({
+        "type": "conversation.item.create",
+        "item": {
+          "type": "function_call_output",
+          "call_id": call_id,
+          "output": json!({ "recorded": true }).to_string(),
+        },
+      }),
+    )
+    .await?;
+  }
+
+  reply_usage += AiUsage::new(
+    0,
+    0,
+    reply_audio_bytes as f32 / PCM_BYTES_PER_SECOND as f32,
+    0,
+  );
+
+  Ok(
+    turn_report
+      .unwrap_or_default()
+      .into_generation_response(reply, reply_usage),
+  )
+}
+
+impl<
+  AG: AiGateway,
+  UM: UserManagement,
+  ER: EpisRepository,
+  MP: ModerationPort,
+  RS: ReviewScheduler,
+  HR: HistoryRetriever,
+> RealtimeAiAgent for OpenAiRealtimeAiAgent<AG, UM, ER, MP, RS, HR>
+{
+  async fn chat(
+    &self,
+    audio_message: EpisAudioMessage,
+    context: &RealtimeAiAgentChatContext,
+    reply_chunks: Sender<EpisAudioMessage>,
+  ) -> Result<Vec<ChatEvent>, EpisError> {
+    let credit_auth_status = self
+      .user_management
+      .authorize_by_credit(context.user_id())
+      .await?;
+
+    if let CreditAuthStatus::Unauthorized = credit_auth_status {
+      return Err(EpisError::NoCredit);
+    }
+
+    let Some(chatmate) = self
+      .epis_repo
+      .get_chatmate_by_id(context.chatmate_id())
+      .await
+      .inspect_err(|error| warn!(%error, "Error while getting chatmate by id"))
+      .map_err(|_| EpisError::RepoError)?
+    else {
+      warn!(chatmate_id=%context.chatmate_id(), "Chatmate not found");
+      return Err(EpisError::Unknown);
+    };
+
+    let (audio_bytes, audio_format) = audio_message.into_parts();
+    let EpisAudioMessageFormat::Wav = audio_format else {
+      warn!(%audio_format, "Only wav audio is supported by the realtime api");
+      return Err(EpisError::ProviderError);
+    };
+    let pcm = wav_to_pcm(&audio_bytes).map_err(|error| {
+      warn!(%error, "Cannot decode user audio");
+      EpisError::ProviderError
+    })?;
+
+    let user_cefr_level = self
+      .user_management
+      .get_cefr_level(context.user_id(), chatmate.language())
+      .await
+      .inspect_err(|error| warn!(%error, "Error while getting user CEFR level"))
+      .map_err(|_| EpisError::RepoError)?;
+    // A learner without a CEFR level is placed by assessing their first turns with a chatmate
+    let placement_levels = match user_cefr_level {
+      Some(_) => None,
+      None => Some(
+        self
+          .epis_repo
+          .get_placement_levels(context.chatmate_id())
+          .await
+          .inspect_err(|error| warn!(%error, "Error while getting placement levels"))
+          .map_err(|_| EpisError::RepoError)?,
+      ),
+    };
+
+    let due_vocab = self
+      .epis_repo
+      .fetch_due_vocab(context.chatmate_id(), None)
+      .await
+      .inspect_err(|error| warn!(%error, "Error while fetching due vocab"))
+      .map_err(|_| EpisError::RepoError)?;
+
+    let chat_memory = self
+      .epis_repo
+      .get_chat_memory(context.chatmate_id())
+      .await
+      .inspect_err(|error| warn!(%error, "Error while getting chat memory"))
+      .map_err(|_| EpisError::RepoError)?;
+
+    let (mut instructions, user_cefr_level) = match &placement_levels {
+      Some(assessed_levels) => {
+        let probe_level = probe_level(assessed_levels);
+        let instructions =
+          generate_placement_instructions(chatmate.language(), &probe_level, assessed_levels.len());
+        (instructions, probe_level)
+      }
+      None => {
+        let user_cefr_level = user_cefr_level.unwrap_or_default();
+        let learning_focus =
+          get_learning_focus(self.epis_repo.as_ref(), context.chatmate_id()).await?;
+        let instructions = generate_instructions(
+          chatmate.language(),
+          chatmate.native_language(),
+          &user_cefr_level,
+          &due_vocab,
+          &chat_memory,
+          &learning_focus,
+          context.scenario_progress().as_ref(),
+        );
+        (instructions, user_cefr_level)
+      }
+    };
+    instructions.push_str(&format!(
+      "\n# Turn report\n\nAlong with speaking your reply, report the learned material, the corrections and the scenario progress by calling the `{TURN_REPORT_FUNCTION}` function.\n"
+    ));
+    if let Some(speech_instructions) = generate_speech_instructions(
+      chatmate.voice().speaking_style().as_deref(),
+      &user_cefr_level,
+    ) {
+      instructions.push_str(&format!("\n# Voice\n\n{speech_instructions}\n"));
+    }
+
+    let chatmate_key = chatmate.id().to_string();
+    // Only a new session needs the message history, and only a placement turn needs the last reply
+    // of it, so it's fetched lazily
+    let message_history = if self.has_session(&chatmate_key) && placement_levels.is_none() {
+      Vec::new()
+    } else {
+      self
+        .epis_repo
+        .get_chat_message_history(chatmate.id(), Some(CHAT_HISTORY_SIZE))
+        .await
+        .inspect_err(|error| warn!(%error, "Error while getting chat message history"))
+        .map_err(|_| EpisError::RepoError)?
+    };
+    let last_reply = message_history
+      .iter()
+      .rev()
+      .find(|message| matches!(message.role(), ChatMessageRole::Ai))
+      .map(|message| message.message().clone());
+    let socket = self.session(&chatmate_key, &message_history).await?;
+
+    let turn = async {
+      let mut socket = socket.lock().await;
+      let transcription_response = transcribe_turn(
+        &mut socket,
+        session_update(
+          &self.model,
+          &self.transcription_model,
+          chatmate.voice().voice(),
+          &user_cefr_level,
+        ),
+        &pcm,
+        TURN_TIMEOUT,
+      )
+      .await?;
+
+      // An unsafe user message is not replied by the model at all, and is replied with a canned
+      // reply instead
+      if moderate(
+        self.moderation.as_ref(),
+        self.epis_repo.as_ref(),
+        &chatmate,
+        transcription_response.text(),
+        ModerationStage::Input,
+        ModerationAction::CannedReply,
+      )
+      .await?
+      {
+        let generation_response = reply_turn(
+          &mut socket,
+          canned_reply_response(&generate_canned_reply(chatmate.language())),
+          &reply_chunks,
+          TURN_TIMEOUT,
+        )
+        .await?;
+        return Ok((transcription_response, None, generation_response));
+      }
+
+      // A failed retrieval doesn't fail the chat turn, as the reply only misses older context
+      let retrieved_exchanges = self
+        .history_retriever
+        .retrieve(
+          context.chatmate_id(),
+          transcription_response.text(),
+          CHAT_HISTORY_SIZE,
+          RECALL_LIMIT,
+        )
+        .await
+        .inspect_err(|error| warn!(%error, "Retrieving past exchanges failed"))
+        .unwrap_or_default();
+      if let Some(recall_message) = generate_recall_message(retrieved_exchanges.past_exchanges()) {
+        instructions.push_str(&format!("\n# Recall\n\n{}\n", recall_message.message()));
+      }
+
+      let generation_response = reply_turn(
+        &mut socket,
+        json!({ "instructions": instructions }),
+        &reply_chunks,
+        TURN_TIMEOUT,
+      )
+      .await?;
+
+      Ok((
+        transcription_response,
+        Some(retrieved_exchanges),
+        generation_response,
+      ))
+    };
+    let (transcription_response, retrieved_exchanges, generation_response) = turn
+      .await
+      .inspect_err(|_: &EpisError| self.close_session(&chatmate_key))?;
+
+    let (transcription, transcribed_words, mut transcription_usage) =
+      transcription_response.into_parts();
+    // Past exchanges are only retrieved for a reply of the model, not for a canned reply
+    let Some(retrieved_exchanges) = retrieved_exchanges else {
+      // The session still holds the unsafe user message, so it's closed, and the next turn
+      // reconnects
+      self.close_session(&chatmate_key);
+      record_chat_turn(
+        self.user_management.as_ref(),
+        self.epis_repo.as_ref(),
+        self.review_scheduler.as_ref(),
+        context,
+        chatmate.language(),
+        Vec::new(),
+        ChatTurn::new(
+          transcription,
+          transcribed_words,
+          transcription_usage,
+          generate_canned_reply(chatmate.language()),
+          Vec::new(),
+          Vec::new(),
+          generation_response.usage().clone(),
+        ),
+      )
+      .await?;
+
+      return Ok(Vec::new());
+    };
+    // Retrieval usage is accounted to the user message, as it's the one retrieved for
+    transcription_usage += retrieved_exchanges.into_parts().1;
+    let (reply, learned_vocab, corrections, scenario_assessment, reply_usage) =
+      generation_response.into_parts();
+
+    if let Some(assessed_levels) = &placement_levels {
+      // Assessment usage is accounted to the user message, as it's the assessed one. A failed
+      // assessment doesn't fail the chat turn, as the reply is already delivered, and the next turn
+      // is assessed instead.
+      transcription_usage += assess_placement_turn(
+        self.ai_gateway.as_ref(),
+        self.user_management.as_ref(),
+        self.epis_repo.as_ref(),
+        &self.generation_model,
+        context,
+        chatmate.language(),
+        assessed_levels,
+        last_reply.as_deref(),
+        &transcription,
+      )
+      .await
+      .inspect_err(|error| warn!(%error, "Placement assessment failed"))
+      .unwrap_or_default();
+    }
+
+    let user_message_id = record_chat_turn(
+      self.user_management.as_ref(),
+      self.epis_repo.as_ref(),
+      self.review_scheduler.as_ref(),
+      context,
+      chatmate.language(),
+      due_vocab,
+      ChatTurn::new(
+        transcription,
+        transcribed_words,
+        transcription_usage,
+        reply,
+        learned_vocab,
+        corrections.clone(),
+        reply_usage,
+      ),
+    )
+    .await?;
+    if placement_levels.is_none() {
+      spawn_progression_evaluation(
+        self.ai_gateway.clone(),
+        self.user_management.clone(),
+        self.epis_repo.clone(),
+        self.generation_model.clone(),
+        context.clone(),
+        chatmate.language().clone(),
+        user_cefr_level,
+        self.cefr_progression_mode.clone(),
+        user_message_id,
+      );
+    }
+    spawn_memory_update(
+      self.ai_gateway.clone(),
+      self.epis_repo.clone(),
+      self.history_retriever.clone(),
+      self.generation_model.clone(),
+      context.chatmate_id().clone(),
+      chatmate.language().clone(),
+    );
+
+    let mut events = Vec::new();
+    if !corrections.is_empty() {
+      events.push(ChatEvent::Corrections(corrections));
+    }
+    // A scenario is not played during placement, so its progress is not advanced then
+    if placement_levels.is_none()
+      && let Some(scenario_progress) = context
+        .scenario_progress()
+        .as_ref()
+        .and_then(|scenario_progress| advance_scenario(scenario_progress, &scenario_assessment))
+    {
+      events.push(ChatEvent::ScenarioProgress(scenario_progress));
+    }
+    Ok(events)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use tokio::{net::TcpListener, sync::mpsc};
+  use tokio_tungstenite::accept_async;
+
+  use super::*;
+  use crate::domain::models::ChatMateLanguage;
+
+  /// Serve a single Realtime api session which answers client events of the given types with the
+  /// given server events, keeping the session open otherwise as a stalled provider would, and
+  /// return a client socket connected to it
+  async fn mock_session(answers: Vec<(&'static str, Vec<Value>)>) -> RealtimeSocket {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+      let (stream, _) = listener.accept().await.unwrap();
+      let mut socket = accept_async(stream).await.unwrap();
+      while let Some(Ok(message)) = socket.next().await {
+        let Message::Text(text) = message else {
+          continue;
+        };
+        let client_event = serde_json::from_str::<Value>(&text).unwrap();
+        let server_events = answers
+          .iter()
+          .filter(|(client_event_type, _)| client_event["type"] == *client_event_type)
+          .flat_map(|(_, server_events)| server_events);
+        for server_event in server_events {
+          socket
+            .send(Message::Text(server_event.to_string().into()))
+            .await
+            .unwrap();
+        }
+      }
+    });
+
+    let (socket, _) = connect_async(format!("ws://{address}")).await.unwrap();
+    socket
+  }
+
+  /// Transcribe a short user message over a session
+  async fn transcribe(
+    socket: &mut RealtimeSocket,
+    turn_timeout: Duration,
+  ) -> Result<TranscriptionResponse, EpisError> {
+    transcribe_turn(
+      socket,
+      json!({ "type": "session.update" }),
+      &[0; 4],
+      turn_timeout,
+    )
+    .await
+  }
+
+  /// Reply to the user message over a session with the given response, returning the reply along
+  /// with its audio chunks
+  async fn reply_with(
+    socket: &mut RealtimeSocket,
+    response: Value,
+    turn_timeout: Duration,
+  ) -> (Result<GenerationResponse, EpisError>, Vec<EpisAudioMessage>) {
+    let (reply_chunks, mut reply_chunks_receiver) = mpsc::channel(16);
+    let generation_response = reply_turn(socket, response, &reply_chunks, turn_timeout).await;
+    drop(reply_chunks);
+
+    let mut chunks = Vec::new();
+    while let Some(chunk) = reply_chunks_receiver.recv().await {
+      chunks.push(chunk);
+    }
+    (generation_response, chunks)
+  }
+
+  /// Reply to the user message over a session, returning the reply along with its audio chunks
+  async fn reply(
+    socket: &mut RealtimeSocket,
+    turn_timeout: Duration,
+  ) -> (Result<GenerationResponse, EpisError>, Vec<EpisAudioMessage>) {
+    reply_with(
+      socket,
+      json!({ "instructions": "Reply in Spanish" }),
+      turn_timeout,
+    )
+    .await
+  }
+
+  /// A transcription completes with words scored by the log probabilities of their tokens
+  #[tokio::test]
+  async fn transcribe_turn_completes_with_words() {
+    let mut socket = mock_session(vec![(
+      "input_audio_buffer.commit",
+      vec![
+        json!({ "type": "input_audio_buffer.committed" }),
+        json!({
+          "type": "conversation.item.input_audio_transcription.completed",
+          "transcript": "Hola amigo",
+          "logprobs": [
+            { "token": "Hola", "logprob": 0.0, "bytes": [] },
+            { "token": " ami", "logprob": 0.0, "bytes": [] },
+            { "token": "go", "logprob": -1.0, "bytes": [] },
+          ],
+        }),
+      ],
+    )])
+    .await;
+
+    let transcription_response = transcribe(&mut socket, TURN_TIMEOUT).await.unwrap();
+
+    assert_eq!(transcription_response.text(), "Hola amigo");
+    let words = transcription_response
+      .words()
+      .iter()
+      .map(|word| (word.word().as_str(), *word.confidence() < 1.0))
+      .collect::<Vec<_>>();
+    assert_eq!(words, [("Hola", false), ("amigo", true)]);
+  }
+
+  /// A transcription fails as soon as the Realtime api returns an error
+  #[tokio::test]
+  async fn transcribe_turn_fails_on_server_error() {
+    let mut socket = mock_session(vec![(
+      "input_audio_buffer.commit",
+      vec![json!({ "type": "error", "error": { "message": "Invalid audio" } })],
+    )])
+    .await;
+
+    let transcription_response = transcribe(&mut socket, TURN_TIMEOUT).await;
+
+    assert!(matches!(
+      transcription_response,
+      Err(EpisError::ProviderError)
+    ));
+  }
+
+  /// A transcription which never completes fails once the turn timeout expires, instead of hanging
+  #[tokio::test]
+  async fn transcribe_turn_times_out_on_missing_event() {
+    let mut socket = mock_session(vec![(
+      "input_audio_buffer.commit",
+      vec![json!({ "type": "input_audio_buffer.committed" })],
+    )])
+    .await;
+
+    let transcription_response = transcribe(&mut socket, Duration::from_millis(200)).await;
+
+    assert!(matches!(
+      transcription_response,
+      Err(EpisError::ProviderError)
+    ));
+  }
+
+  /// A reply completes once its response is done, streaming its audio in chunks along with the
+  /// reported turn
+  #[tokio::test]
+  async fn reply_turn_completes_with_report() {
+    let reply_audio = vec![0; REPLY_CHUNK_BYTES + PCM_BYTES_PER_SECOND / 2];
+    let turn_report = json!({
+      "learned_material": { "vocab": [] },
+      "corrections": [{
+        "original": "yo es",
+        "corrected": "yo soy",
+        "category": "grammar",
+        "explanation": "Ser is conjugated as soy for yo.",
+      }],
+      "scenario_progress": { "completed_goals": [1], "succeeded": false },
+    });
+    let mut socket = mock_session(vec![(
+      "response.create",
+      vec![
+        json!({ "type": "response.created" }),
+        json!({ "type": "response.output_audio.delta", "delta": BASE64.encode(&reply_audio) }),
+        json!({ "type": "response.output_audio_transcript.done", "transcript": "¡Hola!" }),
+        json!({
+          "type": "response.function_call_arguments.done",
+          "call_id": "call_1",
+          "arguments": turn_report.to_string(),
+        }),
+        json!({
+          "type": "response.done",
+          "response": { "status": "completed", "usage": { "input_tokens": 12, "output_tokens": 7 } },
+        }),
+      ],
+    )])
+    .await;
+
+    let (generation_response, chunks) = reply(&mut socket, TURN_TIMEOUT).await;
+    let generation_response = generation_response.unwrap();
+
+    assert_eq!(generation_response.text(), "¡Hola!");
+    assert_eq!(generation_response.corrections().len(), 1);
+    assert_eq!(
+      generation_response.scenario_assessment().completed_goals(),
+      &[1]
+    );
+    assert_eq!(*generation_response.usage().input_tokens(), 12);
+    assert_eq!(*generation_response.usage().output_tokens(), 7);
+    assert_eq!(*generation_response.usage().audio_seconds(), 1.5);
+    assert_eq!(chunks.len(), 2);
+  }
+
+  /// A reply whose response is never done fails once the turn timeout expires, instead of hanging
+  #[tokio::test]
+  async fn reply_turn_times_out_on_missing_event() {
+    let mut socket = mock_session(vec![(
+      "response.create",
+      vec![json!({ "type": "response.created" })],
+    )])
+    .await;
+
+    let (generation_response, _) = reply(&mut socket, Duration::from_millis(200)).await;
+
+    assert!(matches!(generation_response, Err(EpisError::ProviderError)));
+  }
+
+  /// A canned reply is spoken by an out of band response which neither sees the conversation nor
+  /// reports the turn
+  #[tokio::test]
+  async fn canned_reply_is_spoken_out_of_band() {
+    let canned_reply = generate_canned_reply(&ChatMateLanguage::Es);
+    let response = canned_reply_response(&canned_reply);
+    assert_eq!(response["conversation"], "none");
+    assert_eq!(response["input"], json!([]));
+    assert_eq!(response["tool_choice"], "none");
+    assert!(
+      response["instructions"]
+        .as_str()
+        .unwrap()
+        .ends_with(&canned_reply)
+    );
+
+    let mut socket = mock_session(vec![(
+      "response.create",
+      vec![
+        json!({ "type": "response.output_audio.delta", "delta": BASE64.encode([0; 4]) }),
+        json!({ "type": "response.output_audio_transcript.done", "transcript": canned_reply }),
+        json!({ "type": "response.done", "response": { "status": "completed" } }),
+      ],
+    )])
+    .await;
+
+    let (generation_response, chunks) = reply_with(&mut socket, response, TURN_TIMEOUT).await;
+    let generation_response = generation_response.unwrap();
+
+    assert_eq!(generation_response.text(), &canned_reply);
+    assert!(generation_response.corrections().is_empty());
+    assert_eq!(chunks.len(), 1);
+  }
+}
@@ -3,13 +3,21 @@
 use std::result::Result;
 use std::str::FromStr;
 
-use sqlx::{PgPool, error::Error as SqlxError, migrate, postgres::PgPoolOptions, query};
+use pgvector::Vector;
+use sqlx::{
+  PgConnection, PgPool, error::Error as SqlxError, migrate, postgres::PgPoolOptions, query,
+};
 use tracing::{info, warn};
+use uuid::Uuid;
 
 use crate::domain::{
   models::{
-    ChatMate, ChatMateLanguage, ChatMessage, ChatMessageRole, EpisError, Id, LearnedVocabData,
-    LearnedVocabStatus, UserId,
+    AiUsage, CefrAssessment, CefrLevel, ChatMate, ChatMateLanguage, ChatMateVoice, ChatMemory,
+    ChatMessage, ChatMessageRole, Correction, CorrectionCategory, CurriculumUnit, EpisError, Id,
+    LearnedVocabData, LearnedVocabStatus, LearningGoal, LearningGoalData, LevelChange,
+    LevelChangeStatus, MessageEmbedding, ModerationAction, ModerationEvent, ModerationStage,
+    NativeLanguage, PartOfSpeech, PastExchange, PronunciationScore, ReviewState, ScheduledVocab,
+    StoredChatMessage, UnsummarizedMessages, UsageReport, UserId, VocabStats, WordPronunciation,
   },
   ports::EpisRepository,
 };
@@ -17,6 +25,59 @@ use crate::domain::{
 /// Default page size for any paginated query
 const DEFAULT_PAGE_SIZE: u8 = 10;
 
+/// Stored representation of a level change status
+fn level_change_status_str(status: &LevelChangeStatus) -> &'static str {
+  match status {
+    LevelChangeStatus::Pending => "pending",
+    LevelChangeStatus::Applied => "applied",
+    LevelChangeStatus::Rejected => "rejected",
+  }
+}
+
+/// Stored representation of a part of speech
+fn part_of_speech_str(part_of_speech: &PartOfSpeech) -> &'static str {
+  match part_of_speech {
+    PartOfSpeech::Noun => "noun",
+    PartOfSpeech::Verb => "verb",
+    PartOfSpeech::Adjective => "adjective",
+    PartOfSpeech::Adverb => "adverb",
+    PartOfSpeech::Pronoun => "pronoun",
+    PartOfSpeech::Preposition => "preposition",
+    PartOfSpeech::Conjunction => "conjunction",
+    PartOfSpeech::Interjection => "interjection",
+    PartOfSpeech::Idiom => "idiom",
+    PartOfSpeech::Other => "other",
+  }
+}
+
+/// Stored representation of a vocab detail, a blank detail being stored as unknown
+fn vocab_detail_str(detail: &str) -> Option<&str> {
+  let detail = detail.trim();
+  (!detail.is_empty()).then_some(detail)
+}
+
+/// Insert the units of the curriculum of a learning goal, in order
+async fn insert_curriculum(
+  connection: &mut PgConnection,
+  learning_goal_id: &Uuid,
+  curriculum: &[CurriculumUnit],
+) -> Result<(), SqlxError> {
+  // Units have vocab lists of different lengths, so they cannot be unnested at once
+  for (position, unit) in curriculum.iter().enumerate() {
+    query!(
+      "INSERT INTO curriculum_unit (goal_id, position, title, target_vocab) VALUES ($1, $2, $3, $4)",
+      learning_goal_id,
+      position as i32,
+      unit.title(),
+      unit.target_vocab(),
+    )
+    .execute(&mut *connection)
+    .await?;
+  }
+
+  Ok(())
+}
+
 /// Database connection manager for PostgreSQL
 #[derive(Debug, Clone)]
 pub struct Postgres {
@@ -47,11 +108,16 @@ impl EpisRepository for Postgres {
     &self,
     user_id: &UserId,
     chatmate_language: &ChatMateLanguage,
+    chatmate_voice: &ChatMateVoice,
+    native_language: &NativeLanguage,
   ) -> Result<ChatMate, EpisError> {
     let chatmate = query!(
-      "INSERT INTO chatmate (user_id, language) VALUES ($1, $2) RETURNING id, language",
+      "INSERT INTO chatmate (user_id, language, voice, speaking_style, native_language) VALUES ($1, $2, $3, $4, $5) RETURNING id, language, voice, speaking_style, native_language",
       user_id,
       chatmate_language.to_string(),
+      chatmate_voice.voice(),
+      chatmate_voice.speaking_style().as_deref(),
+      native_language.as_ref(),
     )
     .fetch_one(self.pool())
     .await
@@ -72,6 +138,8 @@ impl EpisRepository for Postgres {
     Ok(ChatMate::new(
       ChatMateLanguage::from_str(&chatmate.language).map_err(|_| EpisError::RepoError)?,
       chatmate.id.into(),
+      ChatMateVoice::new(chatmate.voice, chatmate.speaking_style),
+      NativeLanguage::new(chatmate.native_language),
     ))
   }
 
@@ -87,7 +155,9 @@ impl EpisRepository for Postgres {
         ChatMateLanguage::from_str(&chatmate.language)
           .inspect_err(|error| warn!(language=%chatmate.language, %error, "Language is unexpected and should not exist in the database"))
           .map_err(|_| EpisError::RepoError)?,
-       chatmate.id.into())));
+       chatmate.id.into(),
+       ChatMateVoice::new(chatmate.voice, chatmate.speaking_style),
+       NativeLanguage::new(chatmate.native_language))));
     }
 
     Ok(None)
@@ -113,7 +183,9 @@ impl EpisRepository for Postgres {
         ChatMateLanguage::from_str(&chatmate.language)
           .inspect_err(|error| warn!(language=%chatmate.language, %error, "Language is unexpected and should not exist in the database"))
           .map_err(|_| EpisError::RepoError)?,
-       chatmate.id.into())));
+       chatmate.id.into(),
+       ChatMateVoice::new(chatmate.voice, chatmate.speaking_style),
+       NativeLanguage::new(chatmate.native_language))));
     }
 
     Ok(None)
@@ -125,7 +197,7 @@ impl EpisRepository for Postgres {
     limit: Option<u8>,
   ) -> Result<Vec<ChatMate>, EpisError> {
     let chatmates = query!(
-      "SELECT id, language FROM chatmate WHERE user_id = $1 ORDER BY created_at ASC LIMIT $2",
+      "SELECT id, language, voice, speaking_style, native_language FROM chatmate WHERE user_id = $1 ORDER BY created_at ASC LIMIT $2",
       user_id,
       limit.unwrap_or(DEFAULT_PAGE_SIZE) as i16,
     )
@@ -140,7 +212,14 @@ impl EpisRepository for Postgres {
         ChatMateLanguage::from_str(&chatmate.language)
           .inspect_err(|error| warn!(language=%chatmate.language, %error, "Language is unexpected and should not exist in the database"))
           .ok()
-          .map(|language| ChatMate::new(language, chatmate.id.into()))
+          .map(|language| {
+            ChatMate::new(
+              language,
+              chatmate.id.into(),
+              ChatMateVoice::new(chatmate.voice, chatmate.speaking_style),
+              NativeLanguage::new(chatmate.native_language),
+            )
+          })
       })
       .collect();
 
@@ -216,6 +295,797 @@ impl EpisRepository for Postgres {
     Ok(message.id.into())
   }
 
+  async fn store_corrections(
+    &self,
+    message_id: &Id,
+    corrections: &[Correction],
+  ) -> Result<(), EpisError> {
+    let mut originals = Vec::new();
+    let mut corrected_fragments = Vec::new();
+    let mut categories = Vec::new();
+    let mut explanations = Vec::new();
+    for correction in corrections {
+      originals.push(correction.original().clone());
+      corrected_fragments.push(correction.corrected().clone());
+      categories.push(
+        match correction.category() {
+          CorrectionCategory::Grammar => "grammar",
+          CorrectionCategory::Vocabulary => "vocabulary",
+          CorrectionCategory::WordOrder => "word_order",
+          CorrectionCategory::Usage => "usage",
+        }
+        .to_string(),
+      );
+      explanations.push(correction.explanation().clone());
+    }
+
+    query!(
+      "INSERT INTO correction (message_id, original, corrected, category, explanation) SELECT $1, * FROM UNNEST($2::TEXT[], $3::TEXT[], $4::TEXT[], $5::TEXT[])",
+      message_id.as_ref(),
+      &originals,
+      &corrected_fragments,
+      &categories,
+      &explanations,
+    )
+    .execute(self.pool())
+    .await
+    .inspect_err(|error| warn!(%error, "Storing corrections failed"))
+    .map_err(|_| EpisError::RepoError)?;
+
+    Ok(())
+  }
+
+  async fn store_pronunciation_scores(
+    &self,
+    message_id: &Id,
+    pronunciation_scores: &[PronunciationScore],
+  ) -> Result<(), EpisError> {
+    let positions = (0..pronunciation_scores.len() as i32).collect::<Vec<_>>();
+    let words = pronunciation_scores
+      .iter()
+      .map(|pronunciation_score| pronunciation_score.word().clone())
+      .collect::<Vec<_>>();
+    let intended_words = pronunciation_scores
+      .iter()
+      .map(|pronunciation_score| pronunciation_score.intended().clone())
+      .collect::<Vec<_>>();
+    let scores = pronunciation_scores
+      .iter()
+      .map(|pronunciation_score| *pronunciation_score.score())
+      .collect::<Vec<_>>();
+    let starts = pronunciation_scores
+      .iter()
+      .map(|pronunciation_score| *pronunciation_score.start())
+      .collect::<Vec<_>>();
+    let ends = pronunciation_scores
+      .iter()
+      .map(|pronunciation_score| *pronunciation_score.end())
+      .collect::<Vec<_>>();
+
+    query!(
+      "INSERT INTO pronunciation_score (message_id, position, word, intended, score, start_seconds, end_seconds) SELECT $1, * FROM UNNEST($2::INT[], $3::TEXT[], $4::TEXT[], $5::REAL[], $6::REAL[], $7::REAL[])",
+      message_id.as_ref(),
+      &positions,
+      &words,
+      &intended_words as &[Option<String>],
+      &scores,
+      &starts as &[Option<f32>],
+      &ends as &[Option<f32>],
+    )
+    .execute(self.pool())
+    .await
+    .inspect_err(|error| warn!(%error, "Storing pronunciation scores failed"))
+    .map_err(|_| EpisError::RepoError)?;
+
+    Ok(())
+  }
+
+  async fn get_pronunciation_report(
+    &self,
+    chatmate_id: &Id,
+    max_score: f32,
+    limit: Option<u8>,
+  ) -> Result<Vec<WordPronunciation>, EpisError> {
+    let result = query!(
+      r#"SELECT
+          COALESCE(pronunciation_score.intended, pronunciation_score.word) AS "word!",
+          AVG(pronunciation_score.score)::REAL AS "average_score!",
+          COUNT(*) AS "attempts!",
+          ARRAY_REMOVE(
+            ARRAY_AGG(DISTINCT pronunciation_score.word)
+              FILTER (WHERE pronunciation_score.word <> pronunciation_score.intended),
+            NULL
+          ) AS "heard_as!"
+        FROM pronunciation_score
+        JOIN message ON message.id = pronunciation_score.message_id
+        WHERE message.chatmate_id = $1
+        GROUP BY 1
+        HAVING AVG(pronunciation_score.score) < $2
+        ORDER BY 2 ASC
+        LIMIT $3"#,
+      chatmate_id.as_ref(),
+      max_score as f64,
+      limit.unwrap_or(DEFAULT_PAGE_SIZE) as i16,
+    )
+    .fetch_all(self.pool())
+    .await
+    .inspect_err(|error| warn!(%error, "Getting pronunciation report failed"))
+    .map_err(|_| EpisError::RepoError)?;
+
+    Ok(
+      result
+        .into_iter()
+        .map(|word_record| {
+          WordPronunciation::new(
+            word_record.word,
+            word_record.average_score,
+            word_record.attempts as u32,
+            word_record.heard_as,
+          )
+        })
+        .collect(),
+    )
+  }
+
+  async fn store_usage(&self, message_id: &Id, usage: &AiUsage) -> Result<(), EpisError> {
+    query!(
+      "INSERT INTO usage (message_id, input_tokens, output_tokens, audio_seconds, characters) VALUES ($1, $2, $3, $4, $5)
+        ON CONFLICT (message_id) DO UPDATE SET
+          input_tokens = usage.input_tokens + EXCLUDED.input_tokens,
+          output_tokens = usage.output_tokens + EXCLUDED.output_tokens,
+          audio_seconds = usage.audio_seconds + EXCLUDED.audio_seconds,
+          characters = usage.characters + EXCLUDED.characters",
+      message_id.as_ref(),
+      *usage.input_tokens() as i32,
+      *usage.output_tokens() as i32,
+      usage.audio_seconds(),
+      *usage.characters() as i32,
+    )
+    .execute(self.pool())
+    .await
+    .inspect_err(|error| warn!(%error, "Storing usage failed"))
+    .map_err(|_| EpisError::RepoError)?;
+
+    Ok(())
+  }
+
+  async fn store_moderation_event(
+    &self,
+    chatmate_id: &Id,
+    moderation_event: &ModerationEvent,
+  ) -> Result<(), EpisError> {
+    let stage = match moderation_event.stage() {
+      ModerationStage::Input => "input",
+      ModerationStage::Output => "output",
+    };
+    let action = match moderation_event.action() {
+      ModerationAction::CannedReply => "canned_reply",
+      ModerationAction::Replaced => "replaced",
+    };
+
+    query!(
+      "INSERT INTO moderation_event (chatmate_id, stage, action, content, categories) VALUES ($1, $2, $3, $4, $5)",
+      chatmate_id.as_ref(),
+      stage,
+      action,
+      moderation_event.text(),
+      moderation_event.verdict().categories(),
+    )
+    .execute(self.pool())
+    .await
+    .inspect_err(|error| warn!(%error, "Storing moderation event failed"))
+    .map_err(|_| EpisError::RepoError)?;
+
+    Ok(())
+  }
+
+  async fn store_placement_assessment(
+    &self,
+    chatmate_id: &Id,
+    cefr_assessment: &CefrAssessment,
+  ) -> Result<(), EpisError> {
+    query!(
+      "INSERT INTO placement_assessment (chatmate_id, cefr_level, rationale) VALUES ($1, $2, $3)",
+      chatmate_id.as_ref(),
+      cefr_assessment.cefr_level().to_string(),
+      cefr_assessment.rationale(),
+    )
+    .execute(self.pool())
+    .await
+    .inspect_err(|error| warn!(%error, "Storing placement assessment failed"))
+    .map_err(|_| EpisError::RepoError)?;
+
+    Ok(())
+  }
+
+  async fn get_placement_levels(&self, chatmate_id: &Id) -> Result<Vec<CefrLevel>, EpisError> {
+    let result = query!(
+      "SELECT cefr_level FROM placement_assessment WHERE chatmate_id = $1 ORDER BY created_at ASC",
+      chatmate_id.as_ref(),
+    )
+    .fetch_all(self.pool())
+    .await
+    .inspect_err(|error| warn!(%error, "Fetching placement levels failed"))
+    .map_err(|_| EpisError::RepoError)?;
+
+    result
+      .into_iter()
+      .map(|assessment_record| {
+        CefrLevel::from_str(&assessment_record.cefr_level)
+          .inspect_err(|error| warn!(%error, "Invalid stored CEFR level"))
+          .map_err(|_| EpisError::RepoError)
+      })
+      .collect()
+  }
+
+  async fn count_user_messages(&self, chatmate_id: &Id) -> Result<u64, EpisError> {
+    let result = query!(
+      r#"SELECT COUNT(*) AS "count!" FROM message WHERE chatmate_id = $1 AND role = 'user'"#,
+      chatmate_id.as_ref(),
+    )
+    .fetch_one(self.pool())
+    .await
+    .inspect_err(|error| warn!(%error, "Counting user messages failed"))
+    .map_err(|_| EpisError::RepoError)?;
+
+    Ok(result.count as u64)
+  }
+
+  async fn get_vocab_stats(&self, chatmate_id: &Id) -> Result<VocabStats, EpisError> {
+    let stats = query!(
+      r#"SELECT
+          COUNT(*) AS "learned!",
+          COUNT(*) FILTER (WHERE produced_count > 0) AS "produced!",
+          COUNT(*) FILTER (WHERE interval_days >= 21) AS "mature!"
+        FROM learned_vocab
+        WHERE chatmate_id = $1"#,
+      chatmate_id.as_ref(),
+    )
+    .fetch_one(self.pool())
+    .await
+    .inspect_err(|error| warn!(%error, "Getting vocab stats failed"))
+    .map_err(|_| EpisError::RepoError)?;
+
+    Ok(VocabStats::new(
+      stats.learned as u32,
+      stats.produced as u32,
+      stats.mature as u32,
+    ))
+  }
+
+  async fn create_level_change(
+    &self,
+    chatmate_id: &Id,
+    from_level: &CefrLevel,
+    to_level: &CefrLevel,
+    rationale: &str,
+    status: &LevelChangeStatus,
+  ) -> Result<LevelChange, EpisError> {
+    let level_change = query!(
+      "INSERT INTO level_change (chatmate_id, from_level, to_level, rationale, status) VALUES ($1, $2, $3, $4, $5) RETURNING id",
+      chatmate_id.as_ref(),
+      from_level.to_string(),
+      to_level.to_string(),
+      rationale,
+      level_change_status_str(status),
+    )
+    .fetch_one(self.pool())
+    .await
+    .inspect_err(|error| warn!(%error, "Storing level change failed"))
+    .map_err(|_| EpisError::RepoError)?;
+
+    Ok(LevelChange::new(
+      level_change.id.into(),
+      from_level.clone(),
+      to_level.clone(),
+      rationale.to_string(),
+      status.clone(),
+    ))
+  }
+
+  async fn get_level_changes(&self, chatmate_id: &Id) -> Result<Vec<LevelChange>, EpisError> {
+    let result = query!(
+      "SELECT id, from_level, to_level, rationale, status FROM level_change WHERE chatmate_id = $1 ORDER BY created_at DESC",
+      chatmate_id.as_ref(),
+    )
+    .fetch_all(self.pool())
+    .await
+    .inspect_err(|error| warn!(%error, "Fetching level changes failed"))
+    .map_err(|_| EpisError::RepoError)?;
+
+    result
+      .into_iter()
+      .map(|level_change_record| {
+        let status = match level_change_record.status.as_str() {
+          "pending" => LevelChangeStatus::Pending,
+          "applied" => LevelChangeStatus::Applied,
+          "rejected" => LevelChangeStatus::Rejected,
+          status => {
+            warn!(status, "Invalid stored level change status");
+            return Err(EpisError::RepoError);
+          }
+        };
+
+        Ok(LevelChange::new(
+          level_change_record.id.into(),
+          CefrLevel::from_str(&level_change_record.from_level)
+            .inspect_err(|error| warn!(%error, "Invalid stored CEFR level"))
+            .map_err(|_| EpisError::RepoError)?,
+          CefrLevel::from_str(&level_change_record.to_level)
+            .inspect_err(|error| warn!(%error, "Invalid stored CEFR level"))
+            .map_err(|_| EpisError::RepoError)?,
+          level_change_record.rationale,
+          status,
+        ))
+      })
+      .collect()
+  }
+
+  async fn update_level_change_status(
+    &self,
+    level_change_id: &Id,
+    status: &LevelChangeStatus,
+  ) -> Result<(), EpisError> {
+    query!(
+      "UPDATE level_change SET status = $2, updated_at = now() WHERE id = $1",
+      level_change_id.as_ref(),
+      level_change_status_str(status),
+    )
+    .execute(self.pool())
+    .await
+    .inspect_err(|error| warn!(%error, "Updating level change status failed"))
+    .map_err(|_| EpisError::RepoError)?;
+
+    Ok(())
+  }
+
+  async fn get_learning_goals(&self, chatmate_id: &Id) -> Result<Vec<LearningGoal>, EpisError> {
+    let result = query!(
+      r#"SELECT learning_goal.id, learning_goal.description,
+            curriculum_unit.title AS "title?", curriculum_unit.target_vocab AS "target_vocab?"
+        FROM learning_goal
+        LEFT JOIN curriculum_unit ON curriculum_unit.goal_id = learning_goal.id
+        WHERE learning_goal.chatmate_id = $1
+        ORDER BY learning_goal.created_at, learning_goal.id, curriculum_unit.position"#,
+      chatmate_id.as_ref(),
+    )
+    .fetch_all(self.pool())
+    .await
+    .inspect_err(|error| warn!(%error, "Fetching learning goals failed"))
+    .map_err(|_| EpisError::RepoError)?;
+
+    // Each row is a unit of a goal, or a goal without any unit, and the units of a goal are
+    // adjacent
+    let mut learning_goals: Vec<(Uuid, String, Vec<CurriculumUnit>)> = Vec::new();
+    for learning_goal_record in result {
+      let unit = learning_goal_record.title.map(|title| {
+        CurriculumUnit::new(title, learning_goal_record.target_vocab.unwrap_or_default())
+      });
+      match learning_goals.last_mut() {
+        Some((id, _, curriculum)) if *id == learning_goal_record.id => curriculum.extend(unit),
+        _ => learning_goals.push((
+          learning_goal_record.id,
+          learning_goal_record.description,
+          unit.into_iter().collect(),
+        )),
+      }
+    }
+
+    Ok(
+      learning_goals
+        .into_iter()
+        .map(|(id, description, curriculum)| LearningGoal::new(id.into(), description, curriculum))
+        .collect(),
+    )
+  }
+
+  async fn create_learning_goal(
+    &self,
+    chatmate_id: &Id,
+    learning_goal_data: &LearningGoalData,
+  ) -> Result<LearningGoal, EpisError> {
+    let mut transaction = self
+      .pool()
+      .begin()
+      .await
+      .inspect_err(|error| warn!(%error, "Beginning learning goal creation failed"))
+      .map_err(|_| EpisError::RepoError)?;
+
+    let learning_goal_id = query!(
+      "INSERT INTO learning_goal (chatmate_id, description) VALUES ($1, $2) RETURNING id",
+      chatmate_id.as_ref(),
+      learning_goal_data.description(),
+    )
+    .fetch_one(&mut *transaction)
+    .await
+    .inspect_err(|error| warn!(%error, "Creating learning goal failed"))
+    .map_err(|_| EpisError::RepoError)?
+    .id;
+    insert_curriculum(
+      &mut transaction,
+      &learning_goal_id,
+      learning_goal_data.curriculum(),
+    )
+    .await
+    .inspect_err(|error| warn!(%error, "Creating curriculum failed"))
+    .map_err(|_| EpisError::RepoError)?;
+
+    transaction
+      .commit()
+      .await
+      .inspect_err(|error| warn!(%error, "Committing learning goal creation failed"))
+      .map_err(|_| EpisError::RepoError)?;
+
+    Ok(LearningGoal::new(
+      learning_goal_id.into(),
+      learning_goal_data.description().clone(),
+      learning_goal_data.curriculum().clone(),
+    ))
+  }
+
+  async fn update_learning_goal(
+    &self,
+    chatmate_id: &Id,
+    learning_goal_id: &Id,
+    learning_goal_data: &LearningGoalData,
+  ) -> Result<Option<LearningGoal>, EpisError> {
+    let mut transaction = self
+      .pool()
+      .begin()
+      .await
+      .inspect_err(|error| warn!(%error, "Beginning learning goal update failed"))
+      .map_err(|_| EpisError::RepoError)?;
+
+    let updated = query!(
+      "UPDATE learning_goal SET description = $3, updated_at = now() WHERE id = $1 AND chatmate_id = $2",
+      learning_goal_id.as_ref(),
+      chatmate_id.as_ref(),
+      learning_goal_data.description(),
+    )
+    .execute(&mut *transaction)
+    .await
+    .inspect_err(|error| warn!(%error, "Updating learning goal failed"))
+    .map_err(|_| EpisError::RepoError)?
+    .rows_affected()
+      > 0;
+    if !updated {
+      return Ok(None);
+    }
+
+    query!(
+      "DELETE FROM curriculum_unit WHERE goal_id = $1",
+      learning_goal_id.as_ref(),
+    )
+    .execute(&mut *transaction)
+    .await
+    .inspect_err(|error| warn!(%error, "Deleting curriculum failed"))
+    .map_err(|_| EpisError::RepoError)?;
+    insert_curriculum(
+      &mut transaction,
+      learning_goal_id.as_ref(),
+      learning_goal_data.curriculum(),
+    )
+    .await
+    .inspect_err(|error| warn!(%error, "Creating curriculum failed"))
+    .map_err(|_| EpisError::RepoError)?;
+
+    transaction
+      .commit()
+      .await
+      .inspect_err(|error| warn!(%error, "Committing learning goal update failed"))
+      .map_err(|_| EpisError::RepoError)?;
+
+    Ok(Some(LearningGoal::new(
+      learning_goal_id.clone(),
+      learning_goal_data.description().clone(),
+      learning_goal_data.curriculum().clone(),
+    )))
+  }
+
+  async fn delete_learning_goal(
+    &self,
+    chatmate_id: &Id,
+    learning_goal_id: &Id,
+  ) -> Result<bool, EpisError> {
+    let deleted = query!(
+      "DELETE FROM learning_goal WHERE id = $1 AND chatmate_id = $2",
+      learning_goal_id.as_ref(),
+      chatmate_id.as_ref(),
+    )
+    .execute(self.pool())
+    .await
+    .inspect_err(|error| warn!(%error, "Deleting learning goal failed"))
+    .map_err(|_| EpisError::RepoError)?
+    .rows_affected()
+      > 0;
+
+    Ok(deleted)
+  }
+
+  async fn get_chat_memory(&self, chatmate_id: &Id) -> Result<ChatMemory, EpisError> {
+    let chat_memory = query!(
+      "SELECT summary, learner_facts FROM chat_memory WHERE chatmate_id = $1",
+      chatmate_id.as_ref(),
+    )
+    .fetch_optional(self.pool())
+    .await
+    .inspect_err(|error| warn!(%error, "Getting chat memory failed"))
+    .map_err(|_| EpisError::RepoError)?
+    .map(|chat_memory_record| {
+      ChatMemory::new(chat_memory_record.summary, chat_memory_record.learner_facts)
+    })
+    .unwrap_or_default();
+
+    Ok(chat_memory)
+  }
+
+  async fn get_unsummarized_messages(
+    &self,
+    chatmate_id: &Id,
+    recent_messages: u8,
+  ) -> Result<Option<UnsummarizedMessages>, EpisError> {
+    let messages = query!(
+      r#"SELECT id, content, role FROM message
+        WHERE chatmate_id = $1
+          AND created_at > COALESCE(
+            (SELECT summarized_until FROM chat_memory WHERE chatmate_id = $1),
+            '-infinity'
+          )
+          AND id NOT IN (
+            SELECT id FROM message WHERE chatmate_id = $1 ORDER BY created_at DESC LIMIT $2
+          )
+        ORDER BY created_at ASC"#,
+      chatmate_id.as_ref(),
+      recent_messages as i16,
+    )
+    .fetch_all(self.pool())
+    .await
+    .inspect_err(|error| warn!(%error, "Getting unsummarized messages failed"))
+    .map_err(|_| EpisError::RepoError)?;
+
+    let Some(last_message_id) = messages.last().map(|message| message.id) else {
+      return Ok(None);
+    };
+    let messages = messages
+      .into_iter()
+      .filter_map(|message| {
+        let role = match message.role.as_str() {
+          "user" => ChatMessageRole::User,
+          "ai" => ChatMessageRole::Ai,
+          "system" => ChatMessageRole::System,
+          _ => return None,
+        };
+
+        Some(ChatMessage::new(role, message.content))
+      })
+      .collect();
+
+    Ok(Some(UnsummarizedMessages::new(
+      messages,
+      last_message_id.into(),
+    )))
+  }
+
+  async fn store_chat_memory(
+    &self,
+    chatmate_id: &Id,
+    chat_memory: &ChatMemory,
+    summarized_until_message_id: &Id,
+  ) -> Result<(), EpisError> {
+    query!(
+      r#"INSERT INTO chat_memory (chatmate_id, summary, learner_facts, summarized_until)
+        SELECT $1, $2, $3, created_at FROM message WHERE id = $4
+        ON CONFLICT (chatmate_id) DO UPDATE SET
+          summary = EXCLUDED.summary,
+          learner_facts = EXCLUDED.learner_facts,
+          summarized_until = EXCLUDED.summarized_until,
+          updated_at = now()"#,
+      chatmate_id.as_ref(),
+      chat_memory.summary(),
+      chat_memory.learner_facts(),
+      summarized_until_message_id.as_ref(),
+    )
+    .execute(self.pool())
+    .await
+    .inspect_err(|error| warn!(%error, "Storing chat memory failed"))
+    .map_err(|_| EpisError::RepoError)?;
+
+    Ok(())
+  }
+
+  async fn get_unembedded_messages(
+    &self,
+    chatmate_id: &Id,
+    model: &str,
+    limit: u8,
+  ) -> Result<Vec<StoredChatMessage>, EpisError> {
+    let messages = query!(
+      r#"SELECT id, content, role FROM message
+        WHERE chatmate_id = $1
+          AND NOT EXISTS (
+            SELECT 1 FROM message_embedding WHERE message_id = message.id AND model = $2
+          )
+        ORDER BY created_at ASC
+        LIMIT $3"#,
+      chatmate_id.as_ref(),
+      model,
+      limit as i16,
+    )
+    .fetch_all(self.pool())
+    .await
+    .inspect_err(|error| warn!(%error, "Getting unembedded messages failed"))
+    .map_err(|_| EpisError::RepoError)?;
+
+    let messages = messages
+      .into_iter()
+      .filter_map(|message| {
+        let role = match message.role.as_str() {
+          "user" => ChatMessageRole::User,
+          "ai" => ChatMessageRole::Ai,
+          "system" => ChatMessageRole::System,
+          _ => return None,
+        };
+
+        Some(StoredChatMessage::new(
+          message.id.into(),
+          ChatMessage::new(role, message.content),
+        ))
+      })
+      .collect();
+
+    Ok(messages)
+  }
+
+  async fn store_message_embeddings(
+    &self,
+    model: &str,
+    message_embeddings: &[MessageEmbedding],
+  ) -> Result<(), EpisError> {
+    for message_embedding in message_embeddings {
+      query!(
+        "INSERT INTO message_embedding (message_id, model, embedding) VALUES ($1, $2, $3) ON CONFLICT (message_id, model) DO NOTHING",
+        message_embedding.message_id().as_ref(),
+        model,
+        Vector::from(message_embedding.embedding().clone()) as _,
+      )
+      .execute(self.pool())
+      .await
+      .inspect_err(|error| warn!(%error, "Storing message embedding failed"))
+      .map_err(|_| EpisError::RepoError)?;
+    }
+
+    Ok(())
+  }
+
+  async fn find_related_exchanges(
+    &self,
+    chatmate_id: &Id,
+    model: &str,
+    embedding: &[f32],
+    recent_messages: u8,
+    min_similarity: f32,
+    limit: u8,
+  ) -> Result<Vec<PastExchange>, EpisError> {
+    let embedding = Vector::from(embedding.to_vec());
+    let exchanges = query!(
+      r#"SELECT user_message.id, user_message.content AS user_message, ai_reply.content AS "ai_reply?"
+        FROM message_embedding
+        JOIN message AS user_message ON user_message.id = message_embedding.message_id
+        LEFT JOIN LATERAL (
+          SELECT content FROM message
+          WHERE chatmate_id = user_message.chatmate_id
+            AND role = 'ai'
+            AND created_at > user_message.created_at
+          ORDER BY created_at ASC
+          LIMIT 1
+        ) AS ai_reply ON true
+        WHERE user_message.chatmate_id = $1
+          AND user_message.role = 'user'
+          AND message_embedding.model = $2
+          AND vector_dims(message_embedding.embedding) = vector_dims($3)
+          AND user_message.id NOT IN (
+            SELECT id FROM message WHERE chatmate_id = $1 ORDER BY created_at DESC LIMIT $4
+          )
+          AND 1 - (message_embedding.embedding <=> $3) >= $5
+        ORDER BY message_embedding.embedding <=> $3
+        LIMIT $6"#,
+      chatmate_id.as_ref(),
+      model,
+      embedding as _,
+      recent_messages as i16,
+      min_similarity as f64,
+      limit as i16,
+    )
+    .fetch_all(self.pool())
+    .await
+    .inspect_err(|error| warn!(%error, "Finding related exchanges failed"))
+    .map_err(|_| EpisError::RepoError)?;
+
+    Ok(
+      exchanges
+        .into_iter()
+        .map(|exchange| {
+          PastExchange::new(exchange.id.into(), exchange.user_message, exchange.ai_reply)
+        })
+        .collect(),
+    )
+  }
+
+  async fn get_past_exchanges(
+    &self,
+    chatmate_id: &Id,
+    after_message_id: Option<&Id>,
+    limit: u16,
+  ) -> Result<Vec<PastExchange>, EpisError> {
+    let exchanges = query!(
+      r#"SELECT id AS "id!", user_message AS "user_message!", ai_reply AS "ai_reply?" FROM (
+          SELECT user_message.id, user_message.content AS user_message, ai_reply.content AS ai_reply, user_message.created_at
+          FROM message AS user_message
+          LEFT JOIN LATERAL (
+            SELECT content FROM message
+            WHERE chatmate_id = user_message.chatmate_id
+              AND role = 'ai'
+              AND created_at > user_message.created_at
+            ORDER BY created_at ASC
+            LIMIT 1
+          ) AS ai_reply ON true
+          WHERE user_message.chatmate_id = $1
+            AND user_message.role = 'user'
+            AND user_message.created_at > COALESCE(
+              (SELECT created_at FROM message WHERE id = $2),
+              '-infinity'
+            )
+          ORDER BY user_message.created_at DESC
+          LIMIT $3
+        ) AS past_exchange
+        ORDER BY created_at ASC"#,
+      chatmate_id.as_ref(),
+      after_message_id.map(|message_id| *message_id.as_ref()),
+      limit as i32,
+    )
+    .fetch_all(self.pool())
+    .await
+    .inspect_err(|error| warn!(%error, "Getting past exchanges failed"))
+    .map_err(|_| EpisError::RepoError)?;
+
+    Ok(
+      exchanges
+        .into_iter()
+        .map(|exchange| {
+          PastExchange::new(exchange.id.into(), exchange.user_message, exchange.ai_reply)
+        })
+        .collect(),
+    )
+  }
+
+  async fn get_usage_by_user(&self, user_id: &UserId) -> Result<UsageReport, EpisError> {
+    // Sums of int columns are bigints, and the real column is summed as a double, so that large
+    // totals don't overflow
+    let usage = query!(
+      r#"SELECT
+          COALESCE(SUM(usage.input_tokens), 0) AS "input_tokens!",
+          COALESCE(SUM(usage.output_tokens), 0) AS "output_tokens!",
+          COALESCE(SUM(usage.audio_seconds::DOUBLE PRECISION), 0) AS "audio_seconds!",
+          COALESCE(SUM(usage.characters), 0) AS "characters!"
+        FROM usage
+        JOIN message ON message.id = usage.message_id
+        JOIN chatmate ON chatmate.id = message.chatmate_id
+        WHERE chatmate.user_id = $1"#,
+      user_id,
+    )
+    .fetch_one(self.pool())
+    .await
+    .inspect_err(|error| warn!(%error, "Getting usage by user failed"))
+    .map_err(|_| EpisError::RepoError)?;
+
+    Ok(UsageReport::new(
+      u64::try_from(usage.input_tokens).unwrap_or_default(),
+      u64::try_from(usage.output_tokens).unwrap_or_default(),
+      usage.audio_seconds,
+      u64::try_from(usage.characters).unwrap_or_default(),
+    ))
+  }
+
   async fn store_learned_vocab(
     &self,
     chatmate_id: &Id,
@@ -225,40 +1095,66 @@ impl EpisRepository for Postgres {
     // https://github.com/launchbadge/sqlx/issues/294
     // https://github.com/mkermani144/epis/issues/11
     for learned_vocab_data in learned_vocab_data_list {
+      let review_state = learned_vocab_data.review_state();
+      let streak = *review_state.repetitions() as i16;
+      let interval_days = *review_state.interval_days() as f64;
       match learned_vocab_data.status() {
         LearnedVocabStatus::New => {
+          let details = learned_vocab_data.details().as_ref();
           query!(
-            // NOTE: For now, on vocab conflict we do nothing. In future, we may want to change
-            // usage_count, etc.
-            "INSERT INTO learned_vocab (chatmate_id, vocab) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+            // NOTE: For now, on vocab conflict we only fill in its missing details. In future, we
+            // may want to change usage_count, etc.
+            "INSERT INTO learned_vocab (chatmate_id, vocab, streak, ease, stability, difficulty, interval_days, due_at, part_of_speech, translation, definition, example) VALUES ($1, $2, $3, $4, $5, $6, $7, now() + $8 * INTERVAL '1 day', $9, $10, $11, $12) ON CONFLICT (chatmate_id, vocab) DO UPDATE SET part_of_speech = COALESCE(learned_vocab.part_of_speech, EXCLUDED.part_of_speech), translation = COALESCE(learned_vocab.translation, EXCLUDED.translation), definition = COALESCE(learned_vocab.definition, EXCLUDED.definition), example = COALESCE(learned_vocab.example, EXCLUDED.example)",
             chatmate_id.as_ref(),
             learned_vocab_data.vocab().as_ref() as &str,
+            streak,
+            review_state.ease(),
+            review_state.stability(),
+            review_state.difficulty(),
+            review_state.interval_days(),
+            interval_days,
+            details.map(|details| part_of_speech_str(details.part_of_speech())),
+            details.and_then(|details| vocab_detail_str(details.translation())),
+            details.and_then(|details| vocab_detail_str(details.definition())),
+            details.and_then(|details| vocab_detail_str(details.example())),
           )
           .execute(self.pool())
           .await
           .inspect_err(|error| warn!(%error, "Storing new learned vocab failed"))
           .map_err(|_| EpisError::RepoError)?;
         }
-        LearnedVocabStatus::Reviewed => {
+        LearnedVocabStatus::Produced => {
           query!(
-            "UPDATE learned_vocab SET last_used = now(), usage_count = usage_count + 1, streak = streak + 1 WHERE chatmate_id = $1 AND vocab = $2",
+            "UPDATE learned_vocab SET last_used = now(), usage_count = usage_count + 1, produced_count = produced_count + 1, streak = $3, ease = $4, stability = $5, difficulty = $6, interval_days = $7, due_at = now() + $8 * INTERVAL '1 day' WHERE chatmate_id = $1 AND vocab = $2",
             chatmate_id.as_ref(),
             learned_vocab_data.vocab().as_ref() as &str,
+            streak,
+            review_state.ease(),
+            review_state.stability(),
+            review_state.difficulty(),
+            review_state.interval_days(),
+            interval_days,
           )
           .execute(self.pool())
           .await
-          .inspect_err(|error| warn!(%error, "Storing reviewed vocab failed"))
+          .inspect_err(|error| warn!(%error, "Storing produced vocab failed"))
           .map_err(|_| EpisError::RepoError)?;
         }
-        LearnedVocabStatus::Reset => {
+        LearnedVocabStatus::Reviewed | LearnedVocabStatus::Reset => {
           query!(
-            "UPDATE learned_vocab SET last_used = now(), usage_count = usage_count + 1, streak = 0 WHERE chatmate_id = $1 AND vocab = $2",
+            "UPDATE learned_vocab SET last_used = now(), usage_count = usage_count + 1, streak = $3, ease = $4, stability = $5, difficulty = $6, interval_days = $7, due_at = now() + $8 * INTERVAL '1 day' WHERE chatmate_id = $1 AND vocab = $2",
             chatmate_id.as_ref(),
             learned_vocab_data.vocab().as_ref() as &str,
+            streak,
+            review_state.ease(),
+            review_state.stability(),
+            review_state.difficulty(),
+            review_state.interval_days(),
+            interval_days,
           )
           .execute(self.pool())
           .await
-          .inspect_err(|error| warn!(%error, "Storing reset vocab failed"))
+          .inspect_err(|error| warn!(%error, "Storing reviewed vocab failed"))
           .map_err(|_| EpisError::RepoError)?;
         }
       }
@@ -267,21 +1163,72 @@ impl EpisRepository for Postgres {
     Ok(())
   }
 
+  async fn fetch_learned_vocab(&self, chatmate_id: &Id) -> Result<Vec<String>, EpisError> {
+    let result = query!(
+      "SELECT vocab FROM learned_vocab WHERE chatmate_id = $1",
+      chatmate_id.as_ref(),
+    )
+    .fetch_all(self.pool())
+    .await
+    .inspect_err(|error| warn!(%error, "Fetching learned vocab failed"))
+    .map_err(|_| EpisError::RepoError)?;
+
+    let learned_vocab = result
+      .into_iter()
+      .map(|word_record| word_record.vocab)
+      .collect::<Vec<_>>();
+
+    Ok(learned_vocab)
+  }
+
+  async fn get_scheduled_vocab(
+    &self,
+    chatmate_id: &Id,
+    vocab: &[String],
+  ) -> Result<Vec<ScheduledVocab>, EpisError> {
+    let result = query!(
+      r#"SELECT vocab, ease, stability, difficulty, interval_days, streak,
+            (EXTRACT(EPOCH FROM now() - last_used) / 86400)::float8 AS "elapsed_days!"
+        FROM learned_vocab
+        WHERE chatmate_id = $1 AND vocab = ANY($2::text[]::citext[])"#,
+      chatmate_id.as_ref(),
+      vocab,
+    )
+    .fetch_all(self.pool())
+    .await
+    .inspect_err(|error| warn!(%error, "Fetching scheduled vocab failed"))
+    .map_err(|_| EpisError::RepoError)?;
+
+    let scheduled_vocab = result
+      .into_iter()
+      .map(|word_record| {
+        ScheduledVocab::new(
+          word_record.vocab,
+          ReviewState::new(
+            word_record.ease,
+            word_record.stability,
+            word_record.difficulty,
+            word_record.interval_days,
+            word_record.streak.max(0) as u32,
+          ),
+          word_record.elapsed_days as f32,
+        )
+      })
+      .collect::<Vec<_>>();
+
+    Ok(scheduled_vocab)
+  }
+
   async fn fetch_due_vocab(
     &self,
     chatmate_id: &Id,
     limit: Option<u8>,
   ) -> Result<Vec<String>, EpisError> {
     let result = query!(
-      r#"WITH due_words AS (
-            SELECT vocab, EXTRACT(EPOCH FROM(now() - (last_used + ((2 ^ (streak - 1)) * INTERVAL '1 day')))) AS due
-            FROM learned_vocab
-            WHERE chatmate_id = $1
-        )
-        SELECT vocab
-        FROM due_words
-        WHERE due > 0
-        ORDER BY due DESC
+      r#"SELECT vocab
+        FROM learned_vocab
+        WHERE chatmate_id = $1 AND due_at <= now()
+        ORDER BY due_at ASC
         LIMIT $2"#,
       chatmate_id.as_ref(),
       limit.unwrap_or(DEFAULT_PAGE_SIZE) as i16
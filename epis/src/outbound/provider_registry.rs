@@ -0,0 +1,435 @@
+//! Registry of AI providers, routing each [AiGateway] operation to its configured provider
+
+use std::str::FromStr;
+
+use anyhow::{Context, anyhow};
+use tokio::sync::mpsc::Sender;
+use tracing::{info, warn};
+
+use crate::{
+  config::{AiModel, AiModels},
+  domain::{
+    models::{
+      AiUsage, CefrAssessment, ChatMessage, ChatSummary, EmbeddingResponse, EpisAudioMessageFormat,
+      EpisError, GenerationResponse, SimpleBytes, TextToSpeechResponse, TranscriptionResponse,
+    },
+    ports::AiGateway,
+  },
+  outbound::{
+    fake::FakeAiGateway,
+    ollama::Ollama,
+    openai::{ApiFlavour, OpenAi},
+    resilient::ResilientAiGateway,
+    structured_output::DEFAULT_MAX_REPAIR_ATTEMPTS,
+    tts_cache::{TtsCache, tts_cache_key},
+  },
+};
+
+/// All AI providers supported by Epis
+#[derive(Debug, Clone)]
+pub enum AiProvider {
+  /// The official OpenAI api
+  OpenAi,
+  /// Any server exposing an OpenAI-compatible api under a custom base url
+  OpenAiCompatible,
+  /// A (usually local) Ollama instance
+  Ollama,
+  /// A fake provider returning scripted responses from a fixture file
+  Fake,
+}
+
+impl FromStr for AiProvider {
+  type Err = anyhow::Error;
+
+  fn from_str(provider: &str) -> Result<Self, Self::Err> {
+    match provider {
+      "openai" => Ok(Self::OpenAi),
+      "openai-compatible" => Ok(Self::OpenAiCompatible),
+      "ollama" => Ok(Self::Ollama),
+      "fake" => Ok(Self::Fake),
+      unknown => Err(anyhow!(
+        "Unknown ai provider `{unknown}`, expected one of: openai, openai-compatible, ollama, fake"
+      )),
+    }
+  }
+}
+
+/// An [AiGateway] backed by any of the supported providers
+#[derive(Debug, Clone)]
+#[allow(clippy::missing_docs_in_private_items)]
+pub enum ProviderGateway {
+  OpenAi(OpenAi),
+  Ollama(Ollama),
+  Fake(FakeAiGateway),
+}
+
+impl ProviderGateway {
+  /// Build the gateway of an ai model based on its provider
+  ///
+  /// # Errors
+  /// - If provider is unknown, an error is returned
+  /// - If provider config is incomplete or invalid, e.g. a missing base url, an error is returned
+  pub fn try_from_ai_model(
+    ai_model: &AiModel,
+    openai_api_key: Option<&str>,
+  ) -> anyhow::Result<Self> {
+    let api_key = ai_model.api_key().as_deref().or(openai_api_key);
+    let api_flavour = ai_model
+      .api_flavour()
+      .as_deref()
+      .map(ApiFlavour::from_str)
+      .transpose()?;
+    let max_repair_attempts = ai_model
+      .max_repair_attempts()
+      .unwrap_or(DEFAULT_MAX_REPAIR_ATTEMPTS);
+
+    match AiProvider::from_str(ai_model.provider())? {
+      AiProvider::OpenAi => Ok(Self::OpenAi(OpenAi::new(
+        api_key.context("An api key is required for openai provider")?,
+        ai_model.base_url().clone(),
+        api_flavour.unwrap_or_default(),
+        max_repair_attempts,
+      ))),
+      // Most OpenAI-compatible servers only implement the Chat Completions api
+      AiProvider::OpenAiCompatible => Ok(Self::OpenAi(OpenAi::new(
+        api_key.unwrap_or_default(),
+        Some(
+          ai_model
+            .base_url()
+            .clone()
+            .context("A base url is required for openai-compatible provider")?,
+        ),
+        api_flavour.unwrap_or(ApiFlavour::ChatCompletions),
+        max_repair_attempts,
+      ))),
+      AiProvider::Ollama => Ok(Self::Ollama(Ollama::try_new(
+        ai_model.base_url().clone(),
+        max_repair_attempts,
+      )?)),
+      AiProvider::Fake => Ok(Self::Fake(FakeAiGateway::try_from_fixture(
+        ai_model
+          .fixture_path()
+          .as_deref()
+          .context("A fixture path is required for fake provider")?,
+      )?)),
+    }
+  }
+}
+
+impl AiGateway for ProviderGateway {
+  async fn generate(
+    &self,
+    model: &str,
+    messages: &[ChatMessage],
+  ) -> Result<GenerationResponse, EpisError> {
+    match self {
+      Self::OpenAi(gateway) => gateway.generate(model, messages).await,
+      Self::Ollama(gateway) => gateway.generate(model, messages).await,
+      Self::Fake(gateway) => gateway.generate(model, messages).await,
+    }
+  }
+
+  async fn generate_stream(
+    &self,
+    model: &str,
+    messages: &[ChatMessage],
+    text_deltas: Sender<String>,
+  ) -> Result<GenerationResponse, EpisError> {
+    match self {
+      Self::OpenAi(gateway) => gateway.generate_stream(model, messages, text_deltas).await,
+      Self::Ollama(gateway) => gateway.generate_stream(model, messages, text_deltas).await,
+      Self::Fake(gateway) => gateway.generate_stream(model, messages, text_deltas).await,
+    }
+  }
+
+  async fn assess_cefr_level(
+    &self,
+    model: &str,
+    messages: &[ChatMessage],
+  ) -> Result<CefrAssessment, EpisError> {
+    match self {
+      Self::OpenAi(gateway) => gateway.assess_cefr_level(model, messages).await,
+      Self::Ollama(gateway) => gateway.assess_cefr_level(model, messages).await,
+      Self::Fake(gateway) => gateway.assess_cefr_level(model, messages).await,
+    }
+  }
+
+  async fn summarize_chat(
+    &self,
+    model: &str,
+    messages: &[ChatMessage],
+  ) -> Result<ChatSummary, EpisError> {
+    match self {
+      Self::OpenAi(gateway) => gateway.summarize_chat(model, messages).await,
+      Self::Ollama(gateway) => gateway.summarize_chat(model, messages).await,
+      Self::Fake(gateway) => gateway.summarize_chat(model, messages).await,
+    }
+  }
+
+  async fn transcribe(
+    &self,
+    model: &str,
+    audio_bytes: SimpleBytes,
+    audio_format: EpisAudioMessageFormat,
+    instructions: Option<&str>,
+  ) -> Result<TranscriptionResponse, EpisError> {
+    match self {
+      Self::OpenAi(gateway) => {
+        gateway
+          .transcribe(model, audio_bytes, audio_format, instructions)
+          .await
+      }
+      Self::Ollama(gateway) => {
+        gateway
+          .transcribe(model, audio_bytes, audio_format, instructions)
+          .await
+      }
+      Self::Fake(gateway) => {
+        gateway
+          .transcribe(model, audio_bytes, audio_format, instructions)
+          .await
+      }
+    }
+  }
+
+  async fn text_to_speech(
+    &self,
+    model: &str,
+    text: String,
+    voice: &str,
+    instructions: Option<&str>,
+  ) -> Result<TextToSpeechResponse, EpisError> {
+    match self {
+      Self::OpenAi(gateway) => {
+        gateway
+          .text_to_speech(model, text, voice, instructions)
+          .await
+      }
+      Self::Ollama(gateway) => {
+        gateway
+          .text_to_speech(model, text, voice, instructions)
+          .await
+      }
+      Self::Fake(gateway) => {
+        gateway
+          .text_to_speech(model, text, voice, instructions)
+          .await
+      }
+    }
+  }
+
+  async fn embed(&self, model: &str, texts: &[String]) -> Result<EmbeddingResponse, EpisError> {
+    match self {
+      Self::OpenAi(gateway) => gateway.embed(model, texts).await,
+      Self::Ollama(gateway) => gateway.embed(model, texts).await,
+      Self::Fake(gateway) => gateway.embed(model, texts).await,
+    }
+  }
+}
+
+/// A composite [AiGateway], routing each operation to the gateway configured for it
+#[derive(Debug, Clone)]
+pub struct RoutedAiGateway {
+  /// Gateway used for speech to text
+  stt: ResilientAiGateway<ProviderGateway>,
+  /// Gateway used for text generation
+  llm: ResilientAiGateway<ProviderGateway>,
+  /// Gateway used for text to speech
+  tts: ResilientAiGateway<ProviderGateway>,
+  /// Gateway used for embedding, if configured
+  embedding: Option<ResilientAiGateway<ProviderGateway>>,
+  /// Cache of text to speech audio, if enabled
+  tts_cache: Option<TtsCache>,
+}
+
+/// Ensure an ai model and its fallbacks are served by providers supporting speech, as Ollama only
+/// supports text generation and embedding
+///
+/// # Errors
+/// If the ai model or any of its fallbacks is served by Ollama, an error is returned
+fn ensure_speech_support(ai_model: &AiModel) -> anyhow::Result<()> {
+  for ai_model in std::iter::once(ai_model).chain(ai_model.fallbacks()) {
+    if let AiProvider::Ollama = AiProvider::from_str(ai_model.provider())? {
+      return Err(anyhow!(
+        "Ollama doesn't support speech, so it cannot serve `{}`",
+        ai_model.model()
+      ));
+    }
+  }
+
+  Ok(())
+}
+
+/// Build the resilient gateway of an ai model, along with its fallbacks
+///
+/// # Errors
+/// If the gateway of the ai model or any of its fallbacks cannot be built, an error is returned
+fn try_build_resilient_gateway(
+  ai_model: &AiModel,
+  openai_api_key: Option<&str>,
+) -> anyhow::Result<ResilientAiGateway<ProviderGateway>> {
+  let gateway = ProviderGateway::try_from_ai_model(ai_model, openai_api_key)?;
+
+  ai_model.fallbacks().iter().enumerate().try_fold(
+    ResilientAiGateway::new(gateway, ai_model.resilience().clone())
+      .with_voice(ai_model.voice().clone()),
+    |resilient_gateway, (index, fallback)| {
+      let fallback_gateway = ProviderGateway::try_from_ai_model(fallback, openai_api_key)
+        .with_context(|| format!("Cannot build fallback gateway #{index}"))?;
+
+      Ok(resilient_gateway.with_fallback(
+        fallback_gateway,
+        fallback.model().clone(),
+        fallback.voice().clone(),
+        fallback.resilience().clone(),
+      ))
+    },
+  )
+}
+
+impl RoutedAiGateway {
+  /// Build a [RoutedAiGateway] from the ai models config
+  ///
+  /// # Errors
+  /// - If the gateway of any of the ai models cannot be built, e.g. because of an unknown
+  ///   provider, an error is returned
+  /// - If the stt or tts model is served by a provider not supporting speech, an error is returned
+  pub fn try_from_config(
+    ai_models: &AiModels,
+    openai_api_key: Option<&str>,
+  ) -> anyhow::Result<Self> {
+    ensure_speech_support(ai_models.stt()).context("Invalid stt model")?;
+    ensure_speech_support(ai_models.tts()).context("Invalid tts model")?;
+    let stt = try_build_resilient_gateway(ai_models.stt(), openai_api_key)
+      .context("Cannot build stt gateway")?;
+    let llm = try_build_resilient_gateway(ai_models.llm(), openai_api_key)
+      .context("Cannot build llm gateway")?;
+    let tts = try_build_resilient_gateway(ai_models.tts(), openai_api_key)
+      .context("Cannot build tts gateway")?;
+    let embedding = ai_models
+      .embedding()
+      .as_ref()
+      .map(|embedding| try_build_resilient_gateway(embedding, openai_api_key))
+      .transpose()
+      .context("Cannot build embedding gateway")?;
+
+    info!(
+      stt = ai_models.stt().provider(),
+      llm = ai_models.llm().provider(),
+      tts = ai_models.tts().provider(),
+      embedding = ai_models
+        .embedding()
+        .as_ref()
+        .map(|embedding| embedding.provider()),
+      llm_fallbacks = ai_models.llm().fallbacks().len(),
+      "Ai gateways built successfully"
+    );
+
+    Ok(Self {
+      stt,
+      llm,
+      tts,
+      embedding,
+      tts_cache: None,
+    })
+  }
+
+  /// Cache text to speech audio in a [TtsCache]
+  pub fn with_tts_cache(mut self, tts_cache: TtsCache) -> Self {
+    self.tts_cache = Some(tts_cache);
+    self
+  }
+}
+
+impl AiGateway for RoutedAiGateway {
+  async fn generate(
+    &self,
+    model: &str,
+    messages: &[ChatMessage],
+  ) -> Result<GenerationResponse, EpisError> {
+    self.llm.generate(model, messages).await
+  }
+
+  async fn generate_stream(
+    &self,
+    model: &str,
+    messages: &[ChatMessage],
+    text_deltas: Sender<String>,
+  ) -> Result<GenerationResponse, EpisError> {
+    self.llm.generate_stream(model, messages, text_deltas).await
+  }
+
+  async fn assess_cefr_level(
+    &self,
+    model: &str,
+    messages: &[ChatMessage],
+  ) -> Result<CefrAssessment, EpisError> {
+    self.llm.assess_cefr_level(model, messages).await
+  }
+
+  async fn summarize_chat(
+    &self,
+    model: &str,
+    messages: &[ChatMessage],
+  ) -> Result<ChatSummary, EpisError> {
+    self.llm.summarize_chat(model, messages).await
+  }
+
+  async fn transcribe(
+    &self,
+    model: &str,
+    audio_bytes: SimpleBytes,
+    audio_format: EpisAudioMessageFormat,
+    instructions: Option<&str>,
+  ) -> Result<TranscriptionResponse, EpisError> {
+    self
+      .stt
+      .transcribe(model, audio_bytes, audio_format, instructions)
+      .await
+  }
+
+  async fn text_to_speech(
+    &self,
+    model: &str,
+    text: String,
+    voice: &str,
+    instructions: Option<&str>,
+  ) -> Result<TextToSpeechResponse, EpisError> {
+    let Some(tts_cache) = &self.tts_cache else {
+      return self
+        .tts
+        .text_to_speech(model, text, voice, instructions)
+        .await;
+    };
+
+    // Audio is cached by the model and voice actually serving it, so a lookup only finds the audio
+    // of the primary model, not the one of a fallback standing in for it
+    let (primary_model, primary_voice) = self.tts.primary_model_and_voice(model, voice);
+    let key = tts_cache_key(primary_model, primary_voice, instructions, &text);
+    // A cache hit costs nothing, so it has no usage
+    if let Some(audio) = tts_cache.get(&key).await {
+      return Ok(TextToSpeechResponse::new(audio, AiUsage::default()));
+    }
+
+    let cached_text = text.clone();
+    let (speech, served_model, served_voice) = self
+      .tts
+      .served_text_to_speech(model, text, voice, instructions)
+      .await?;
+    tts_cache.put(
+      tts_cache_key(&served_model, &served_voice, instructions, &cached_text),
+      speech.audio().clone(),
+    );
+
+    Ok(speech)
+  }
+
+  async fn embed(&self, model: &str, texts: &[String]) -> Result<EmbeddingResponse, EpisError> {
+    let Some(embedding) = &self.embedding else {
+      warn!("No embedding model is configured");
+      return Err(EpisError::ProviderError);
+    };
+
+    embedding.embed(model, texts).await
+  }
+}
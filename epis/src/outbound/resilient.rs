@@ -0,0 +1,599 @@
+//! Resilient [AiGateway] wrapper, adding timeouts, retries, circuit breaking and failover to any
+//! gateway
+
+use std::{
+  sync::{
+    Arc, Mutex,
+    atomic::{AtomicBool, Ordering},
+  },
+  time::{Duration, Instant},
+};
+
+use tokio::{sync::mpsc, time::sleep};
+use tracing::{debug, trace, warn};
+
+use crate::{
+  config::ResiliencePolicy,
+  domain::{
+    models::{
+      CefrAssessment, ChatMessage, ChatSummary, EmbeddingResponse, EpisAudioMessageFormat,
+      EpisError, GenerationResponse, SimpleBytes, TextToSpeechResponse, TranscriptionResponse,
+    },
+    ports::AiGateway,
+  },
+};
+
+/// Capacity of the channel of text deltas of each generation stream attempt
+const ATTEMPT_TEXT_DELTAS_CAPACITY: usize = 64;
+
+/// State of a circuit breaker
+#[derive(Debug, Default)]
+enum CircuitState {
+  /// Calls are allowed
+  #[default]
+  Closed,
+  /// Calls are allowed, tracking the number of consecutive transient failures
+  Failing(u32),
+  /// Calls are rejected until the cooldown is over, after which a single trial call is allowed
+  Open(Instant),
+  /// A single trial call started at the instant is in progress, and other calls are rejected. If
+  /// the trial call doesn't finish within the cooldown, e.g. as it's cancelled, another one is
+  /// allowed.
+  HalfOpen(Instant),
+}
+
+/// A circuit breaker, rejecting calls to a gateway for a while after consecutive failures
+#[derive(Debug)]
+struct CircuitBreaker {
+  /// Current state of the circuit
+  state: Mutex<CircuitState>,
+  /// Number of consecutive transient failures after which the circuit opens
+  threshold: u32,
+  /// Duration the circuit stays open
+  cooldown: Duration,
+}
+
+impl CircuitBreaker {
+  /// Check if a call is allowed, letting a single trial call through once the cooldown of an open
+  /// circuit is over
+  fn allows_call(&self) -> bool {
+    let mut state = self
+      .state
+      .lock()
+      .expect("Circuit state lock is not poisoned");
+    let now = Instant::now();
+    match *state {
+      CircuitState::Closed | CircuitState::Failing(_) => true,
+      CircuitState::Open(until) if now < until => false,
+      CircuitState::HalfOpen(since) if now < since + self.cooldown => false,
+      CircuitState::Open(_) | CircuitState::HalfOpen(_) => {
+        debug!("Circuit breaker half-opened, allowing a trial call");
+        *state = CircuitState::HalfOpen(now);
+        true
+      }
+    }
+  }
+
+  /// Record a successful call, closing the circuit
+  fn record_success(&self) {
+    *self
+      .state
+      .lock()
+      .expect("Circuit state lock is not poisoned") = CircuitState::Closed;
+  }
+
+  /// Record a transient failure, opening the circuit if threshold is reached or if it was a trial
+  /// call of an open circuit. Return whether the circuit is open.
+  fn record_failure(&self) -> bool {
+    let mut state = self
+      .state
+      .lock()
+      .expect("Circuit state lock is not poisoned");
+    let failures = match *state {
+      CircuitState::Closed => 1,
+      CircuitState::Failing(failures) => failures + 1,
+      CircuitState::Open(_) | CircuitState::HalfOpen(_) => self.threshold,
+    };
+
+    if failures >= self.threshold {
+      warn!(failures, "Circuit breaker opened");
+      *state = CircuitState::Open(Instant::now() + self.cooldown);
+      return true;
+    }
+
+    *state = CircuitState::Failing(failures);
+    false
+  }
+}
+
+/// A gateway wrapped by a [ResilientAiGateway], along with its resilience state
+#[derive(Debug, Clone)]
+struct Target<AG: AiGateway> {
+  /// The wrapped gateway
+  gateway: AG,
+  /// Model used instead of the requested one, which is the case for fallbacks
+  model: Option<String>,
+  /// Voice used instead of the requested one, e.g. for fallbacks of providers not supporting it
+  voice: Option<String>,
+  /// Resilience policy of calls to the gateway
+  policy: ResiliencePolicy,
+  /// Circuit breaker of the gateway
+  circuit_breaker: Arc<CircuitBreaker>,
+}
+
+/// An [AiGateway] calling an ordered list of gateways, the first one being the primary and the
+/// rest fallbacks. Each call is timed out and retried with exponential backoff on transient
+/// errors, before failing over to the next gateway. Gateways failing repeatedly are skipped for a
+/// while by a circuit breaker.
+#[derive(Debug, Clone)]
+pub struct ResilientAiGateway<AG: AiGateway> {
+  /// Ordered list of gateways
+  targets: Vec<Target<AG>>,
+}
+
+impl<AG: AiGateway> ResilientAiGateway<AG> {
+  /// Construct a [ResilientAiGateway] from a primary gateway and its resilience policy
+  pub fn new(gateway: AG, policy: ResiliencePolicy) -> Self {
+    let mut resilient_gateway = Self {
+      targets: Vec::new(),
+    };
+    resilient_gateway.push(gateway, None, None, policy);
+
+    resilient_gateway
+  }
+
+  /// Add a fallback gateway using a specific model, and a specific voice if set, tried after all
+  /// of the previous ones fail
+  pub fn with_fallback(
+    mut self,
+    gateway: AG,
+    model: String,
+    voice: Option<String>,
+    policy: ResiliencePolicy,
+  ) -> Self {
+    self.push(gateway, Some(model), voice, policy);
+    self
+  }
+
+  /// Use a specific voice for the primary gateway instead of the requested one
+  pub fn with_voice(mut self, voice: Option<String>) -> Self {
+    if let Some(primary) = self.targets.first_mut() {
+      primary.voice = voice;
+    }
+    self
+  }
+
+  /// Add a gateway to the targets
+  fn push(
+    &mut self,
+    gateway: AG,
+    model: Option<String>,
+    voice: Option<String>,
+    policy: ResiliencePolicy,
+  ) {
+    let circuit_breaker = Arc::new(CircuitBreaker {
+      state: Mutex::new(CircuitState::Closed),
+      threshold: *policy.circuit_breaker_threshold(),
+      cooldown: Duration::from_millis(*policy.circuit_breaker_cooldown_ms()),
+    });
+    self.targets.push(Target {
+      gateway,
+      model,
+      voice,
+      policy,
+      circuit_breaker,
+    });
+  }
+
+  /// Model and voice a text to speech call is served by if the primary gateway serves it
+  pub fn primary_model_and_voice<'a>(
+    &'a self,
+    model: &'a str,
+    voice: &'a str,
+  ) -> (&'a str, &'a str) {
+    let primary = self.targets.first();
+    (
+      primary
+        .and_then(|primary| primary.model.as_deref())
+        .unwrap_or(model),
+      primary
+        .and_then(|primary| primary.voice.as_deref())
+        .unwrap_or(voice),
+    )
+  }
+
+  /// Convert a text to speech, returning the speech along with the model and voice it's actually
+  /// served by, which differ from the requested ones if a fallback serves it
+  ///
+  /// # Errors
+  /// If all of the targets fail, the last error is returned
+  pub async fn served_text_to_speech(
+    &self,
+    model: &str,
+    text: String,
+    voice: &str,
+    instructions: Option<&str>,
+  ) -> Result<(TextToSpeechResponse, String, String), EpisError> {
+    self
+      .call(
+        "text_to_speech",
+        || true,
+        |gateway, target_model, target_voice| {
+          let text = text.clone();
+          async move {
+            let served_model = target_model.unwrap_or_else(|| model.to_string());
+            let served_voice = target_voice.unwrap_or_else(|| voice.to_string());
+            let speech = gateway
+              .text_to_speech(&served_model, text, &served_voice, instructions)
+              .await?;
+
+            Ok((speech, served_model, served_voice))
+          }
+        },
+      )
+      .await
+  }
+
+  /// Call an operation resiliently on the targets, in order, passing the model and voice of each
+  /// target used instead of the requested ones, if any. Calls are only retried or failed over
+  /// while `can_retry` returns true, and a target is not retried once its circuit opens.
+  ///
+  /// # Errors
+  /// If all of the targets fail, the last error is returned
+  async fn call<T, F, Fut>(
+    &self,
+    operation: &str,
+    can_retry: impl Fn() -> bool,
+    mut call: F,
+  ) -> Result<T, EpisError>
+  where
+    F: FnMut(AG, Option<String>, Option<String>) -> Fut,
+    Fut: Future<Output = Result<T, EpisError>>,
+  {
+    let mut last_error = EpisError::ProviderError;
+
+    for (index, target) in self.targets.iter().enumerate() {
+      if !can_retry() {
+        break;
+      }
+      if !target.circuit_breaker.allows_call() {
+        debug!(
+          operation,
+          target = index,
+          "Circuit is open, skipping target"
+        );
+        continue;
+      }
+
+      let mut backoff = Duration::from_millis(*target.policy.initial_backoff_ms());
+      for attempt in 0..=*target.policy.max_retries() {
+        if attempt > 0 {
+          if !can_retry() {
+            break;
+          }
+          sleep(backoff).await;
+          backoff *= 2;
+        }
+
+        let result = tokio::time::timeout(
+          Duration::from_millis(*target.policy.timeout_ms()),
+          call(
+            target.gateway.clone(),
+            target.model.clone(),
+            target.voice.clone(),
+          ),
+        )
+        .await
+        .unwrap_or_else(|_| {
+          warn!(operation, target = index, attempt, "Call timed out");
+          Err(EpisError::TransientProviderError)
+        });
+
+        match result {
+          Ok(value) => {
+            target.circuit_breaker.record_success();
+            return Ok(value);
+          }
+          Err(EpisError::TransientProviderError) => {
+            warn!(
+              operation,
+              target = index,
+              attempt,
+              "Call failed with a transient error"
+            );
+            last_error = EpisError::TransientProviderError;
+            if target.circuit_breaker.record_failure() {
+              debug!(operation, target = index, "Circuit opened, failing over");
+              break;
+            }
+          }
+          Err(error) => {
+            warn!(%error, operation, target = index, "Call failed, failing over");
+            last_error = error;
+            break;
+          }
+        }
+      }
+    }
+
+    Err(last_error)
+  }
+}
+
+impl<AG: AiGateway> AiGateway for ResilientAiGateway<AG> {
+  async fn generate(
+    &self,
+    model: &str,
+    messages: &[ChatMessage],
+  ) -> Result<GenerationResponse, EpisError> {
+    self
+      .call(
+        "generate",
+        || true,
+        |gateway, target_model, _| async move {
+          gateway
+            .generate(target_model.as_deref().unwrap_or(model), messages)
+            .await
+        },
+      )
+      .await
+  }
+
+  async fn assess_cefr_level(
+    &self,
+    model: &str,
+    messages: &[ChatMessage],
+  ) -> Result<CefrAssessment, EpisError> {
+    self
+      .call(
+        "assess_cefr_level",
+        || true,
+        |gateway, target_model, _| async move {
+          gateway
+            .assess_cefr_level(target_model.as_deref().unwrap_or(model), messages)
+            .await
+        },
+      )
+      .await
+  }
+
+  async fn summarize_chat(
+    &self,
+    model: &str,
+    messages: &[ChatMessage],
+  ) -> Result<ChatSummary, EpisError> {
+    self
+      .call(
+        "summarize_chat",
+        || true,
+        |gateway, target_model, _| async move {
+          gateway
+            .summarize_chat(target_model.as_deref().unwrap_or(model), messages)
+            .await
+        },
+      )
+      .await
+  }
+
+  async fn generate_stream(
+    &self,
+    model: &str,
+    messages: &[ChatMessage],
+    text_deltas: mpsc::Sender<String>,
+  ) -> Result<GenerationResponse, EpisError> {
+    // Once a text delta is forwarded, the call cannot be retried without duplicating the text
+    let forwarded = AtomicBool::new(false);
+    let text_deltas = &text_deltas;
+    let forwarded_ref = &forwarded;
+
+    self
+      .call(
+        "generate_stream",
+        || !forwarded.load(Ordering::Relaxed),
+        |gateway, target_model, _| async move {
+          let (attempt_sender, mut attempt_receiver) = mpsc::channel(ATTEMPT_TEXT_DELTAS_CAPACITY);
+
+          let generation = gateway.generate_stream(
+            target_model.as_deref().unwrap_or(model),
+            messages,
+            attempt_sender,
+          );
+          let forwarding = async {
+            while let Some(text_delta) = attempt_receiver.recv().await {
+              forwarded_ref.store(true, Ordering::Relaxed);
+              if text_deltas.send(text_delta).await.is_err() {
+                trace!("Text deltas receiver is dropped");
+              }
+            }
+          };
+
+          let (generation_response, _) = tokio::join!(generation, forwarding);
+          generation_response
+        },
+      )
+      .await
+  }
+
+  async fn transcribe(
+    &self,
+    model: &str,
+    audio_bytes: SimpleBytes,
+    audio_format: EpisAudioMessageFormat,
+    instructions: Option<&str>,
+  ) -> Result<TranscriptionResponse, EpisError> {
+    self
+      .call(
+        "transcribe",
+        || true,
+        |gateway, target_model, _| {
+          let audio_bytes = audio_bytes.clone();
+          let audio_format = audio_format.clone();
+          async move {
+            gateway
+              .transcribe(
+                target_model.as_deref().unwrap_or(model),
+                audio_bytes,
+                audio_format,
+                instructions,
+              )
+              .await
+          }
+        },
+      )
+      .await
+  }
+
+  async fn text_to_speech(
+    &self,
+    model: &str,
+    text: String,
+    voice: &str,
+    instructions: Option<&str>,
+  ) -> Result<TextToSpeechResponse, EpisError> {
+    let (speech, _, _) = self
+      .served_text_to_speech(model, text, voice, instructions)
+      .await?;
+
+    Ok(speech)
+  }
+
+  async fn embed(&self, model: &str, texts: &[String]) -> Result<EmbeddingResponse, EpisError> {
+    self
+      .call(
+        "embed",
+        || true,
+        |gateway, target_model, _| async move {
+          gateway
+            .embed(target_model.as_deref().unwrap_or(model), texts)
+            .await
+        },
+      )
+      .await
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::sync::atomic::AtomicU32;
+
+  use serde_json::json;
+
+  use super::*;
+  use crate::outbound::fake::FakeAiGateway;
+
+  /// A resilient gateway over a fake gateway, retrying up to three times, and opening its circuit
+  /// after a number of failures for a few milliseconds
+  fn resilient_gateway(circuit_breaker_threshold: u32) -> ResilientAiGateway<FakeAiGateway> {
+    let gateway = FakeAiGateway::try_from_fixture(concat!(
+      env!("CARGO_MANIFEST_DIR"),
+      "/fixtures/fake_ai_gateway.yaml"
+    ))
+    .expect("Fake ai gateway fixture is valid");
+    let policy = serde_json::from_value(json!({
+      "max_retries": 3,
+      "initial_backoff_ms": 1,
+      "circuit_breaker_threshold": circuit_breaker_threshold,
+      "circuit_breaker_cooldown_ms": 20,
+    }))
+    .expect("Resilience policy is valid");
+
+    ResilientAiGateway::new(gateway, policy)
+  }
+
+  /// Call a resilient gateway with an operation always failing with a transient error, returning
+  /// the number of attempts
+  async fn count_failing_attempts(resilient_gateway: &ResilientAiGateway<FakeAiGateway>) -> u32 {
+    let attempts = AtomicU32::new(0);
+    let result: Result<(), EpisError> = resilient_gateway
+      .call(
+        "test",
+        || true,
+        |_, _, _| async {
+          attempts.fetch_add(1, Ordering::Relaxed);
+          Err(EpisError::TransientProviderError)
+        },
+      )
+      .await;
+    assert!(result.is_err());
+
+    attempts.load(Ordering::Relaxed)
+  }
+
+  /// A target is not retried once its circuit opens on reaching the threshold
+  #[tokio::test]
+  async fn stops_retrying_once_circuit_opens() {
+    let resilient_gateway = resilient_gateway(2);
+
+    assert_eq!(count_failing_attempts(&resilient_gateway).await, 2);
+    assert_eq!(count_failing_attempts(&resilient_gateway).await, 0);
+  }
+
+  /// A failed trial call of a half-open circuit is not retried
+  #[tokio::test]
+  async fn does_not_retry_failed_trial_call() {
+    let resilient_gateway = resilient_gateway(2);
+    count_failing_attempts(&resilient_gateway).await;
+
+    sleep(Duration::from_millis(25)).await;
+
+    assert_eq!(count_failing_attempts(&resilient_gateway).await, 1);
+  }
+
+  /// A circuit breaker opening after two failures, with a cooldown of a few milliseconds
+  fn circuit_breaker() -> CircuitBreaker {
+    CircuitBreaker {
+      state: Mutex::new(CircuitState::Closed),
+      threshold: 2,
+      cooldown: Duration::from_millis(20),
+    }
+  }
+
+  /// The circuit opens once the threshold is reached, and a single trial call is allowed after
+  /// the cooldown
+  #[test]
+  fn allows_a_single_trial_call_after_cooldown() {
+    let circuit_breaker = circuit_breaker();
+    circuit_breaker.record_failure();
+    assert!(circuit_breaker.allows_call());
+    circuit_breaker.record_failure();
+    assert!(!circuit_breaker.allows_call());
+
+    std::thread::sleep(Duration::from_millis(25));
+
+    assert!(circuit_breaker.allows_call());
+    assert!(!circuit_breaker.allows_call());
+    circuit_breaker.record_success();
+    assert!(circuit_breaker.allows_call());
+    assert!(circuit_breaker.allows_call());
+  }
+
+  /// A failed trial call opens the circuit again
+  #[test]
+  fn reopens_on_failed_trial_call() {
+    let circuit_breaker = circuit_breaker();
+    circuit_breaker.record_failure();
+    circuit_breaker.record_failure();
+    std::thread::sleep(Duration::from_millis(25));
+    assert!(circuit_breaker.allows_call());
+
+    circuit_breaker.record_failure();
+
+    assert!(!circuit_breaker.allows_call());
+  }
+
+  /// A trial call not finished within the cooldown, e.g. a cancelled one, doesn't keep the circuit
+  /// half-open forever
+  #[test]
+  fn allows_another_trial_call_if_one_is_lost() {
+    let circuit_breaker = circuit_breaker();
+    circuit_breaker.record_failure();
+    circuit_breaker.record_failure();
+    std::thread::sleep(Duration::from_millis(25));
+    assert!(circuit_breaker.allows_call());
+    assert!(!circuit_breaker.allows_call());
+
+    std::thread::sleep(Duration::from_millis(25));
+
+    assert!(circuit_breaker.allows_call());
+  }
+}
@@ -0,0 +1,662 @@
+//! Structured output schema shared by all LLM adapters
+
+use schemars::JsonSchema;
+use serde::{Deserialize, de::DeserializeOwned};
+use tokio::sync::mpsc::Sender;
+use tracing::{debug, trace, warn};
+
+use crate::domain::models::{
+  AiUsage, CefrAssessment, CefrLevel, ChatMessage, ChatMessageRole, ChatSummary, Correction,
+  CorrectionCategory, EpisError, GenerationResponse, NewVocab, PartOfSpeech, ScenarioAssessment,
+  VocabDetails,
+};
+
+/// Deserialized part of speech of a vocab returned by API
+#[derive(Debug, Clone, JsonSchema, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[allow(clippy::missing_docs_in_private_items)]
+pub enum ApiPartOfSpeech {
+  Noun,
+  Verb,
+  Adjective,
+  Adverb,
+  Pronoun,
+  Preposition,
+  Conjunction,
+  Interjection,
+  Idiom,
+  Other,
+}
+
+impl From<ApiPartOfSpeech> for PartOfSpeech {
+  fn from(api_part_of_speech: ApiPartOfSpeech) -> Self {
+    match api_part_of_speech {
+      ApiPartOfSpeech::Noun => PartOfSpeech::Noun,
+      ApiPartOfSpeech::Verb => PartOfSpeech::Verb,
+      ApiPartOfSpeech::Adjective => PartOfSpeech::Adjective,
+      ApiPartOfSpeech::Adverb => PartOfSpeech::Adverb,
+      ApiPartOfSpeech::Pronoun => PartOfSpeech::Pronoun,
+      ApiPartOfSpeech::Preposition => PartOfSpeech::Preposition,
+      ApiPartOfSpeech::Conjunction => PartOfSpeech::Conjunction,
+      ApiPartOfSpeech::Interjection => PartOfSpeech::Interjection,
+      ApiPartOfSpeech::Idiom => PartOfSpeech::Idiom,
+      ApiPartOfSpeech::Other => PartOfSpeech::Other,
+    }
+  }
+}
+
+/// Deserialized learned vocab returned by API
+#[derive(Debug, Clone, JsonSchema, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ApiVocab {
+  /// The vocab, in base or lemma form
+  vocab: String,
+  /// Part of speech of the vocab
+  part_of_speech: ApiPartOfSpeech,
+  /// Translation of the vocab into the native language of the learner
+  translation: String,
+  /// Short and simple definition of the vocab, in the language of the chat
+  definition: String,
+  /// Sentence of the response the vocab is used in
+  example: String,
+}
+
+impl From<ApiVocab> for NewVocab {
+  fn from(api_vocab: ApiVocab) -> Self {
+    NewVocab::new(
+      api_vocab.vocab,
+      VocabDetails::new(
+        api_vocab.part_of_speech.into(),
+        api_vocab.translation,
+        api_vocab.definition,
+        api_vocab.example,
+      ),
+    )
+  }
+}
+
+/// Deserialized learned material returned by API
+#[derive(Debug, Clone, Default, JsonSchema, Deserialize)]
+#[serde(deny_unknown_fields)]
+#[allow(clippy::missing_docs_in_private_items)]
+pub struct ApiLearnedMaterial {
+  vocab: Vec<ApiVocab>,
+}
+
+impl ApiLearnedMaterial {
+  /// Convert into the learned vocab
+  pub fn into_vocab(self) -> Vec<NewVocab> {
+    self.vocab.into_iter().map(Into::into).collect()
+  }
+}
+
+/// Deserialized category of a correction returned by API
+#[derive(Debug, Clone, JsonSchema, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[allow(clippy::missing_docs_in_private_items)]
+pub enum ApiCorrectionCategory {
+  Grammar,
+  Vocabulary,
+  WordOrder,
+  Usage,
+}
+
+impl From<ApiCorrectionCategory> for CorrectionCategory {
+  fn from(api_category: ApiCorrectionCategory) -> Self {
+    match api_category {
+      ApiCorrectionCategory::Grammar => Self::Grammar,
+      ApiCorrectionCategory::Vocabulary => Self::Vocabulary,
+      ApiCorrectionCategory::WordOrder => Self::WordOrder,
+      ApiCorrectionCategory::Usage => Self::Usage,
+    }
+  }
+}
+
+/// Deserialized correction of the user message returned by API
+#[derive(Debug, Clone, JsonSchema, Deserialize)]
+#[serde(deny_unknown_fields)]
+#[allow(clippy::missing_docs_in_private_items)]
+pub struct ApiCorrection {
+  original: String,
+  corrected: String,
+  category: ApiCorrectionCategory,
+  explanation: String,
+}
+
+impl From<ApiCorrection> for Correction {
+  fn from(api_correction: ApiCorrection) -> Self {
+    Self::new(
+      api_correction.original,
+      api_correction.corrected,
+      api_correction.category.into(),
+      api_correction.explanation,
+    )
+  }
+}
+
+/// Deserialized assessment of the user message of a role-play scenario returned by API
+#[derive(Debug, Clone, Default, JsonSchema, Deserialize)]
+#[serde(deny_unknown_fields)]
+#[allow(clippy::missing_docs_in_private_items)]
+pub struct ApiScenarioProgress {
+  completed_goals: Vec<u8>,
+  succeeded: bool,
+}
+
+impl From<ApiScenarioProgress> for ScenarioAssessment {
+  fn from(api_scenario_progress: ApiScenarioProgress) -> Self {
+    Self::new(
+      api_scenario_progress.completed_goals,
+      api_scenario_progress.succeeded,
+    )
+  }
+}
+
+/// Deserialized generation API response
+#[derive(Debug, Clone, JsonSchema, Deserialize)]
+#[serde(deny_unknown_fields)]
+#[allow(clippy::missing_docs_in_private_items)]
+pub struct ApiResponse {
+  response: String,
+  learned_material: ApiLearnedMaterial,
+  corrections: Vec<ApiCorrection>,
+  scenario_progress: ApiScenarioProgress,
+}
+
+impl ApiResponse {
+  /// Convert into a [GenerationResponse] with the usage of its generation
+  pub fn into_generation_response(self, usage: AiUsage) -> GenerationResponse {
+    GenerationResponse::new(
+      self.response,
+      self.learned_material.into_vocab(),
+      self.corrections.into_iter().map(Correction::from).collect(),
+      self.scenario_progress.into(),
+      usage,
+    )
+  }
+}
+
+/// Deserialized report of a speech to speech turn returned by API, i.e. a generation API response
+/// without its text, as the reply is spoken instead
+#[derive(Debug, Clone, Default, JsonSchema, Deserialize)]
+#[serde(deny_unknown_fields)]
+#[allow(clippy::missing_docs_in_private_items)]
+pub struct ApiTurnReport {
+  learned_material: ApiLearnedMaterial,
+  corrections: Vec<ApiCorrection>,
+  scenario_progress: ApiScenarioProgress,
+}
+
+impl ApiTurnReport {
+  /// Convert into a [GenerationResponse] with the transcript of the spoken reply and the usage of
+  /// its generation
+  pub fn into_generation_response(self, text: String, usage: AiUsage) -> GenerationResponse {
+    GenerationResponse::new(
+      text,
+      self.learned_material.into_vocab(),
+      self.corrections.into_iter().map(Correction::from).collect(),
+      self.scenario_progress.into(),
+      usage,
+    )
+  }
+}
+
+/// Deserialized CEFR level returned by API
+#[derive(Debug, Clone, JsonSchema, Deserialize)]
+#[allow(clippy::missing_docs_in_private_items)]
+pub enum ApiCefrLevel {
+  A1,
+  A2,
+  B1,
+  B2,
+  C1,
+  C2,
+}
+
+impl From<ApiCefrLevel> for CefrLevel {
+  fn from(api_cefr_level: ApiCefrLevel) -> Self {
+    match api_cefr_level {
+      ApiCefrLevel::A1 => Self::A1,
+      ApiCefrLevel::A2 => Self::A2,
+      ApiCefrLevel::B1 => Self::B1,
+      ApiCefrLevel::B2 => Self::B2,
+      ApiCefrLevel::C1 => Self::C1,
+      ApiCefrLevel::C2 => Self::C2,
+    }
+  }
+}
+
+/// Deserialized CEFR level assessment API response
+#[derive(Debug, Clone, JsonSchema, Deserialize)]
+#[serde(deny_unknown_fields)]
+#[allow(clippy::missing_docs_in_private_items)]
+pub struct ApiCefrAssessment {
+  cefr_level: ApiCefrLevel,
+  rationale: String,
+}
+
+impl ApiCefrAssessment {
+  /// Convert into a [CefrAssessment] with the usage of its generation
+  pub fn into_cefr_assessment(self, usage: AiUsage) -> CefrAssessment {
+    CefrAssessment::new(self.cefr_level.into(), self.rationale, usage)
+  }
+}
+
+/// Deserialized chat summary API response
+#[derive(Debug, Clone, JsonSchema, Deserialize)]
+#[serde(deny_unknown_fields)]
+#[allow(clippy::missing_docs_in_private_items)]
+pub struct ApiChatSummary {
+  summary: String,
+  learner_facts: Vec<String>,
+}
+
+impl ApiChatSummary {
+  /// Convert into a [ChatSummary] with the usage of its generation
+  pub fn into_chat_summary(self, usage: AiUsage) -> ChatSummary {
+    ChatSummary::new(self.summary, self.learner_facts, usage)
+  }
+}
+
+/// Default max number of corrective re-prompts after an LLM output not matching the schema
+pub const DEFAULT_MAX_REPAIR_ATTEMPTS: u32 = 1;
+
+/// Leniently deserialized learned vocab, tolerating missing details and unknown fields
+#[derive(Debug, Deserialize)]
+#[allow(clippy::missing_docs_in_private_items)]
+struct LenientApiVocabDetails {
+  vocab: String,
+  #[serde(default)]
+  part_of_speech: Option<ApiPartOfSpeech>,
+  #[serde(default)]
+  translation: String,
+  #[serde(default)]
+  definition: String,
+  #[serde(default)]
+  example: String,
+}
+
+/// Leniently deserialized learned vocab, either with its details or as a bare word
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+#[allow(clippy::missing_docs_in_private_items)]
+enum LenientApiVocab {
+  Detailed(LenientApiVocabDetails),
+  Bare(String),
+}
+
+impl From<LenientApiVocab> for ApiVocab {
+  fn from(lenient_vocab: LenientApiVocab) -> Self {
+    let details = match lenient_vocab {
+      LenientApiVocab::Detailed(details) => details,
+      LenientApiVocab::Bare(vocab) => LenientApiVocabDetails {
+        vocab,
+        part_of_speech: None,
+        translation: String::new(),
+        definition: String::new(),
+        example: String::new(),
+      },
+    };
+
+    Self {
+      vocab: details.vocab,
+      part_of_speech: details.part_of_speech.unwrap_or(ApiPartOfSpeech::Other),
+      translation: details.translation,
+      definition: details.definition,
+      example: details.example,
+    }
+  }
+}
+
+/// Leniently deserialized learned material, tolerating missing and unknown fields
+#[derive(Debug, Default, Deserialize)]
+#[allow(clippy::missing_docs_in_private_items)]
+struct LenientApiLearnedMaterial {
+  #[serde(default)]
+  vocab: Vec<LenientApiVocab>,
+}
+
+/// Leniently deserialized generation API response, only requiring the response text
+#[derive(Debug, Deserialize)]
+#[allow(clippy::missing_docs_in_private_items)]
+struct LenientApiResponse {
+  response: String,
+  #[serde(default)]
+  learned_material: Option<LenientApiLearnedMaterial>,
+  #[serde(default)]
+  corrections: Vec<ApiCorrection>,
+  #[serde(default)]
+  scenario_progress: ApiScenarioProgress,
+}
+
+impl From<LenientApiResponse> for ApiResponse {
+  fn from(lenient_response: LenientApiResponse) -> Self {
+    Self {
+      response: lenient_response.response,
+      learned_material: ApiLearnedMaterial {
+        vocab: lenient_response
+          .learned_material
+          .unwrap_or_default()
+          .vocab
+          .into_iter()
+          .map(Into::into)
+          .collect(),
+      },
+      corrections: lenient_response.corrections,
+      scenario_progress: lenient_response.scenario_progress,
+    }
+  }
+}
+
+/// Parse the raw json output text of an LLM into an [ApiResponse], strictly at first, and then
+/// leniently, i.e. ignoring any text around the json object (e.g. markdown code fences), unknown
+/// fields and missing learned material, corrections or scenario progress
+///
+/// # Errors
+/// If output text cannot be parsed even leniently, the description of the schema error is
+/// returned
+fn parse_api_response(output_text: &str) -> Result<ApiResponse, String> {
+  if output_text.trim().is_empty() {
+    return Err("the output is empty".to_string());
+  }
+
+  let strict_error = match serde_json::from_str::<ApiResponse>(output_text) {
+    Ok(api_response) => return Ok(api_response),
+    Err(error) => error,
+  };
+
+  let json_object = output_text
+    .find('{')
+    .zip(output_text.rfind('}'))
+    .filter(|(start, end)| start < end)
+    .map(|(start, end)| &output_text[start..=end]);
+  let Some(json_object) = json_object else {
+    return Err(strict_error.to_string());
+  };
+
+  let api_response =
+    serde_json::from_str::<LenientApiResponse>(json_object).map_err(|error| error.to_string())?;
+  debug!("Llm output was parsed leniently");
+
+  Ok(api_response.into())
+}
+
+/// Parse the raw json output text of an LLM into any schema, strictly at first, and then ignoring
+/// any text around the json object (e.g. markdown code fences)
+///
+/// # Errors
+/// If output text cannot be parsed, the description of the schema error is returned
+pub fn parse_json_output<T: DeserializeOwned>(output_text: &str) -> Result<T, String> {
+  if output_text.trim().is_empty() {
+    return Err("the output is empty".to_string());
+  }
+
+  let strict_error = match serde_json::from_str::<T>(output_text) {
+    Ok(output) => return Ok(output),
+    Err(error) => error,
+  };
+
+  output_text
+    .find('{')
+    .zip(output_text.rfind('}'))
+    .filter(|(start, end)| start < end)
+    .ok_or_else(|| strict_error.to_string())
+    .and_then(|(start, end)| {
+      serde_json::from_str::<T>(&output_text[start..=end]).map_err(|error| error.to_string())
+    })
+}
+
+/// Parse the raw json output text of an LLM into a [GenerationResponse], repairing it by
+/// corrective re-prompts if it doesn't match the schema. Each re-prompt includes the invalid
+/// output and its schema error, and the usage of all of them is added to the generation usage.
+///
+/// # Errors
+/// - If output text still doesn't match the schema after the max repair attempts,
+///   [EpisError::InvalidStructuredOutput] is returned
+/// - If a re-prompt fails, its error is returned
+pub async fn parse_or_repair_generation<F, Fut>(
+  output_text: String,
+  usage: AiUsage,
+  messages: &[ChatMessage],
+  max_repair_attempts: u32,
+  regenerate: F,
+) -> Result<GenerationResponse, EpisError>
+where
+  F: FnMut(Vec<ChatMessage>) -> Fut,
+  Fut: Future<Output = Result<(String, AiUsage), EpisError>>,
+{
+  let (api_response, usage) = parse_or_repair(
+    output_text,
+    usage,
+    messages,
+    max_repair_attempts,
+    parse_api_response,
+    regenerate,
+  )
+  .await?;
+
+  Ok(api_response.into_generation_response(usage))
+}
+
+/// Parse the raw json output text of an LLM by a parser, repairing it by corrective re-prompts if
+/// it doesn't match the schema, and returning it along with the total usage of all generations
+///
+/// # Errors
+/// - If output text still doesn't match the schema after the max repair attempts,
+///   [EpisError::InvalidStructuredOutput] is returned
+/// - If a re-prompt fails, its error is returned
+pub async fn parse_or_repair<T, P, F, Fut>(
+  output_text: String,
+  usage: AiUsage,
+  messages: &[ChatMessage],
+  max_repair_attempts: u32,
+  parse: P,
+  mut regenerate: F,
+) -> Result<(T, AiUsage), EpisError>
+where
+  P: Fn(&str) -> Result<T, String>,
+  F: FnMut(Vec<ChatMessage>) -> Fut,
+  Fut: Future<Output = Result<(String, AiUsage), EpisError>>,
+{
+  let mut output_text = output_text;
+  let mut total_usage = usage;
+  let mut repair_messages = messages.to_vec();
+  let mut repair_attempt = 0;
+
+  loop {
+    let error = match parse(&output_text) {
+      Ok(output) => return Ok((output, total_usage)),
+      Err(error) => error,
+    };
+
+    if repair_attempt >= max_repair_attempts {
+      warn!(%error, repair_attempt, "Llm output does not match the schema, giving up");
+      return Err(EpisError::InvalidStructuredOutput);
+    }
+    warn!(
+      %error,
+      repair_attempt,
+      "Llm output does not match the schema, re-prompting to repair it"
+    );
+
+    repair_messages.push(ChatMessage::new(ChatMessageRole::Ai, output_text));
+    repair_messages.push(ChatMessage::new(
+      ChatMessageRole::System,
+      format!(
+        "Your previous reply is invalid: {error}. Reply again with the same response, but only with a json object matching the json schema, without any other text."
+      ),
+    ));
+
+    let (repaired_output_text, repair_usage) = regenerate(repair_messages.clone()).await?;
+    output_text = repaired_output_text;
+    total_usage += repair_usage;
+    repair_attempt += 1;
+  }
+}
+
+/// Key of the response text in the raw json of an [ApiResponse]
+const RESPONSE_KEY: &str = "\"response\"";
+
+/// Incrementally extract the response text of an [ApiResponse] from the deltas of its raw json,
+/// so that the text can be streamed before the whole json is generated
+#[derive(Debug, Default)]
+pub struct ResponseTextExtractor {
+  /// Raw json received so far
+  raw: String,
+  /// Length of the response text extracted so far
+  extracted_len: usize,
+}
+
+impl ResponseTextExtractor {
+  /// Push a raw json delta, returning the newly available part of the response text, which may be
+  /// empty
+  pub fn push(&mut self, raw_delta: &str) -> String {
+    self.raw.push_str(raw_delta);
+
+    let Some(text) = self.decoded_text() else {
+      return String::new();
+    };
+    let text_delta = text
+      .get(self.extracted_len..)
+      .unwrap_or_default()
+      .to_string();
+    self.extracted_len = text.len();
+
+    text_delta
+  }
+
+  /// Push a raw json delta and send the newly available part of the response text, if any,
+  /// through a channel
+  pub async fn forward(&mut self, raw_delta: &str, text_deltas: &Sender<String>) {
+    let text_delta = self.push(raw_delta);
+    if !text_delta.is_empty() && text_deltas.send(text_delta).await.is_err() {
+      trace!("Text deltas receiver is dropped");
+    }
+  }
+
+  /// Get the raw json received so far
+  pub fn raw(&self) -> &str {
+    &self.raw
+  }
+
+  /// Reconcile a generation response, e.g. a repaired one, with the response text forwarded so
+  /// far. If its text continues the forwarded text, the rest of it is forwarded. Otherwise the
+  /// forwarded text is kept as the response text, as it's already been delivered.
+  pub async fn reconcile(
+    &self,
+    generation_response: GenerationResponse,
+    text_deltas: &Sender<String>,
+  ) -> GenerationResponse {
+    let forwarded_text = self.decoded_text().unwrap_or_default();
+    let Some(rest) = generation_response.text().strip_prefix(&forwarded_text) else {
+      warn!("Repaired response text differs from the forwarded one, keeping the forwarded one");
+      let (_, learned_vocab, corrections, scenario_assessment, usage) =
+        generation_response.into_parts();
+      return GenerationResponse::new(
+        forwarded_text,
+        learned_vocab,
+        corrections,
+        scenario_assessment,
+        usage,
+      );
+    };
+
+    if !rest.is_empty() && text_deltas.send(rest.to_string()).await.is_err() {
+      trace!("Text deltas receiver is dropped");
+    }
+
+    generation_response
+  }
+
+  /// Decode the complete part of the response text received so far, if its value has started
+  fn decoded_text(&self) -> Option<String> {
+    let after_key = &self.raw[self.raw.find(RESPONSE_KEY)? + RESPONSE_KEY.len()..];
+    let after_colon = after_key.trim_start().strip_prefix(':')?;
+    let value = after_colon.trim_start().strip_prefix('"')?;
+
+    // Find the end of the complete part of the string, not cutting any escape sequence
+    let bytes = value.as_bytes();
+    let mut end = 0;
+    while end < bytes.len() {
+      match bytes[end] {
+        b'"' => break,
+        b'\\' if bytes.get(end + 1) == Some(&b'u') => {
+          if end + 6 > bytes.len() {
+            break;
+          }
+          end += 6;
+        }
+        b'\\' => {
+          if end + 2 > bytes.len() {
+            break;
+          }
+          end += 2;
+        }
+        _ => end += 1,
+      }
+    }
+
+    // A cut surrogate pair is not decodable yet, so it's retried on the next delta
+    serde_json::from_str(&format!("\"{}\"", &value[..end])).ok()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// Push raw json deltas into an extractor, returning the text delta of each of them
+  fn extract(raw_deltas: &[&str]) -> Vec<String> {
+    let mut extractor = ResponseTextExtractor::default();
+    raw_deltas
+      .iter()
+      .map(|raw_delta| extractor.push(raw_delta))
+      .collect()
+  }
+
+  /// Response text is extracted as soon as its value starts, even if its key is split
+  #[test]
+  fn extracts_text_across_deltas() {
+    assert_eq!(
+      extract(&[
+        "{\"resp",
+        "onse\": \"Ho",
+        "la",
+        "\", \"learned_material\": {}}"
+      ]),
+      vec!["", "Ho", "la", ""]
+    );
+  }
+
+  /// An escape sequence split across deltas is only extracted once it's complete
+  #[test]
+  fn waits_for_split_escape_sequences() {
+    assert_eq!(
+      extract(&[
+        "{\"response\": \"Di \\",
+        "\"hola\\",
+        "\" y adi\\u00",
+        "f3s\\n\"}"
+      ]),
+      vec!["Di ", "\"hola", "\" y adi", "ós\n"]
+    );
+  }
+
+  /// A surrogate pair split across deltas is only extracted once it's complete
+  #[test]
+  fn waits_for_split_surrogate_pairs() {
+    assert_eq!(
+      extract(&["{\"response\": \"Hola \\ud83d", "\\ude00\"}"]),
+      vec!["", "Hola 😀"]
+    );
+  }
+
+  /// Nothing is extracted if the json has no response text
+  #[test]
+  fn extracts_nothing_without_response() {
+    assert_eq!(
+      extract(&["{\"corrections\": [], ", "\"learned_material\": {}}"]),
+      vec!["", ""]
+    );
+  }
+}
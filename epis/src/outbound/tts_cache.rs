@@ -0,0 +1,516 @@
+//! Content-addressed cache of text to speech audio, so that identical texts are not synthesized
+//! over and over again
+
+use std::{
+  path::PathBuf,
+  str::FromStr,
+  sync::{
+    Arc,
+    atomic::{AtomicBool, AtomicU64, Ordering},
+  },
+  time::{Duration, SystemTime},
+};
+
+use anyhow::{Context, anyhow};
+use derive_getters::Getters;
+use sha2::{Digest, Sha256};
+use sqlx::query;
+use tokio::{fs, task::spawn_blocking};
+use tracing::{debug, info, trace, warn};
+
+use crate::{config::TtsCacheConfig, domain::models::SimpleBytes, outbound::postgres::Postgres};
+
+/// Storage backends supported by the tts cache
+#[derive(Debug, Clone)]
+pub enum TtsCacheBackendKind {
+  /// Audio files in a local directory
+  Filesystem,
+  /// A `bytea` table in the Postgres database
+  Postgres,
+}
+
+impl FromStr for TtsCacheBackendKind {
+  type Err = anyhow::Error;
+
+  fn from_str(backend: &str) -> Result<Self, Self::Err> {
+    match backend {
+      "filesystem" => Ok(Self::Filesystem),
+      "postgres" => Ok(Self::Postgres),
+      unknown => Err(anyhow!(
+        "Unknown tts cache backend `{unknown}`, expected one of: filesystem, postgres"
+      )),
+    }
+  }
+}
+
+/// Extension of the temp files of the filesystem backend, written before being renamed to their key
+const TEMP_FILE_EXTENSION: &str = "tmp";
+
+/// Interval of logging the hit and miss counts of the tts cache
+const STATS_LOG_INTERVAL: Duration = Duration::from_secs(300);
+
+/// Counter making the temp file names of concurrent writers of a key unique
+static TEMP_FILE_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Compute the cache key of a text to speech call, i.e. the hex sha256 of all of its inputs
+pub fn tts_cache_key(model: &str, voice: &str, instructions: Option<&str>, text: &str) -> String {
+  let mut hasher = Sha256::new();
+  // Fields are length prefixed, so that different inputs never produce the same hashed bytes
+  for field in [model, voice, instructions.unwrap_or_default(), text] {
+    hasher.update((field.len() as u64).to_be_bytes());
+    hasher.update(field);
+  }
+  hasher.update([instructions.is_some() as u8]);
+
+  format!("{:x}", hasher.finalize())
+}
+
+/// Filesystem backend, storing each entry as a file named after its key. Access time is tracked
+/// by the file modification time.
+#[derive(Debug, Clone)]
+pub struct FilesystemTtsCache {
+  /// Directory of the cached audio files
+  dir: PathBuf,
+}
+
+impl FilesystemTtsCache {
+  /// Get the audio of a key, marking it as recently used
+  async fn get(&self, key: &str) -> anyhow::Result<Option<SimpleBytes>> {
+    let path = self.dir.join(key);
+    let audio = match fs::read(&path).await {
+      Ok(audio) => audio,
+      Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+      Err(error) => return Err(error.into()),
+    };
+
+    let file = fs::OpenOptions::new()
+      .write(true)
+      .open(&path)
+      .await?
+      .into_std()
+      .await;
+    spawn_blocking(move || file.set_modified(SystemTime::now())).await??;
+
+    Ok(Some(audio))
+  }
+
+  /// Store the audio of a key
+  async fn put(&self, key: &str, audio: &[u8]) -> anyhow::Result<()> {
+    // Write to a temp file first, so that a concurrent get never reads a partial file, named
+    // uniquely so that concurrent writers of a key don't write to the same file
+    let temp_path = self.dir.join(format!(
+      "{key}.{}.{TEMP_FILE_EXTENSION}",
+      TEMP_FILE_COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    fs::write(&temp_path, audio).await?;
+    fs::rename(&temp_path, self.dir.join(key)).await?;
+
+    Ok(())
+  }
+
+  /// Evict least recently used entries beyond the max size. Temp files are in-flight writes, so
+  /// they are neither counted nor evicted.
+  async fn evict(&self, max_size_bytes: u64) -> anyhow::Result<()> {
+    let mut entries = Vec::new();
+    let mut read_dir = fs::read_dir(&self.dir).await?;
+    while let Some(entry) = read_dir.next_entry().await? {
+      let path = entry.path();
+      if path
+        .extension()
+        .is_some_and(|extension| extension == TEMP_FILE_EXTENSION)
+      {
+        continue;
+      }
+      let metadata = entry.metadata().await?;
+      if metadata.is_file() {
+        entries.push((metadata.modified()?, metadata.len(), path));
+      }
+    }
+
+    let mut total_size: u64 = entries.iter().map(|(_, size, _)| size).sum();
+    entries.sort_by_key(|(modified, _, _)| *modified);
+    for (_, size, path) in entries {
+      if total_size <= max_size_bytes {
+        break;
+      }
+      match fs::remove_file(&path).await {
+        Ok(()) => debug!(path = %path.display(), "Tts cache entry evicted"),
+        // The entry may have been evicted concurrently, e.g. by another instance
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => {}
+        Err(error) => return Err(error.into()),
+      }
+      total_size -= size;
+    }
+
+    Ok(())
+  }
+}
+
+/// Postgres backend, storing entries in the `tts_cache` table
+#[derive(Debug, Clone)]
+pub struct PostgresTtsCache {
+  /// Postgres connection
+  postgres: Arc<Postgres>,
+}
+
+impl PostgresTtsCache {
+  /// Get the audio of a key, marking it as recently used
+  async fn get(&self, key: &str) -> anyhow::Result<Option<SimpleBytes>> {
+    let entry = query!(
+      "UPDATE tts_cache SET last_accessed_at = now() WHERE key = $1 RETURNING audio",
+      key,
+    )
+    .fetch_optional(self.postgres.pool())
+    .await?;
+
+    Ok(entry.map(|entry| entry.audio))
+  }
+
+  /// Store the audio of a key
+  async fn put(&self, key: &str, audio: &[u8]) -> anyhow::Result<()> {
+    query!(
+      "INSERT INTO tts_cache (key, audio, size_bytes) VALUES ($1, $2, $3) ON CONFLICT DO NOTHING",
+      key,
+      audio,
+      audio.len() as i32,
+    )
+    .execute(self.postgres.pool())
+    .await?;
+
+    Ok(())
+  }
+
+  /// Evict least recently used entries beyond the max size
+  async fn evict(&self, max_size_bytes: u64) -> anyhow::Result<()> {
+    let evicted = query!(
+      r#"DELETE FROM tts_cache
+        WHERE key IN (
+          SELECT key FROM (
+            SELECT key, SUM(size_bytes) OVER (ORDER BY last_accessed_at DESC, key) AS cumulative_size
+            FROM tts_cache
+          ) AS ranked_entries
+          WHERE cumulative_size > $1
+        )"#,
+      i64::try_from(max_size_bytes).unwrap_or(i64::MAX),
+    )
+    .execute(self.postgres.pool())
+    .await?
+    .rows_affected();
+    if evicted > 0 {
+      debug!(evicted, "Tts cache entries evicted");
+    }
+
+    Ok(())
+  }
+}
+
+/// Storage backend of a [TtsCache]
+#[derive(Debug, Clone)]
+#[allow(clippy::missing_docs_in_private_items)]
+pub enum TtsCacheBackend {
+  Filesystem(FilesystemTtsCache),
+  Postgres(PostgresTtsCache),
+}
+
+impl TtsCacheBackend {
+  /// Store the audio of a key
+  async fn put(&self, key: &str, audio: &[u8]) -> anyhow::Result<()> {
+    match self {
+      Self::Filesystem(cache) => cache.put(key, audio).await,
+      Self::Postgres(cache) => cache.put(key, audio).await,
+    }
+  }
+
+  /// Evict least recently used entries beyond the max size
+  async fn evict(&self, max_size_bytes: u64) -> anyhow::Result<()> {
+    match self {
+      Self::Filesystem(cache) => cache.evict(max_size_bytes).await,
+      Self::Postgres(cache) => cache.evict(max_size_bytes).await,
+    }
+  }
+}
+
+/// Hit and miss counters of a [TtsCache]
+#[derive(Debug, Default)]
+#[allow(clippy::missing_docs_in_private_items)]
+struct TtsCacheCounters {
+  hits: AtomicU64,
+  misses: AtomicU64,
+}
+
+impl TtsCacheCounters {
+  /// Get a snapshot of the counters
+  fn stats(&self) -> TtsCacheStats {
+    TtsCacheStats {
+      hits: self.hits.load(Ordering::Relaxed),
+      misses: self.misses.load(Ordering::Relaxed),
+    }
+  }
+}
+
+/// Number of hits and misses of a [TtsCache] since it's built
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Getters)]
+pub struct TtsCacheStats {
+  /// Number of lookups finding the audio
+  hits: u64,
+  /// Number of lookups not finding the audio, including failed ones
+  misses: u64,
+}
+
+/// A size capped, least recently used cache of text to speech audio. Cache failures are logged
+/// and treated as misses, so that they never fail a text to speech call. Entries are stored and
+/// evicted in the background, off the reply path. Hit and miss counts are logged at a fixed
+/// interval.
+#[derive(Debug, Clone)]
+pub struct TtsCache {
+  /// Storage backend
+  backend: TtsCacheBackend,
+  /// Max total size of the cached audio
+  max_size_bytes: u64,
+  /// Hit and miss counters
+  counters: Arc<TtsCacheCounters>,
+  /// Whether an eviction is in progress, so that concurrent stores don't scan the cache at once
+  evicting: Arc<AtomicBool>,
+}
+
+impl TtsCache {
+  /// Build a [TtsCache] from its config
+  ///
+  /// # Errors
+  /// - If backend is unknown, an error is returned
+  /// - If the filesystem backend has no path, or its directory cannot be created, an error is
+  ///   returned
+  pub async fn try_from_config(
+    tts_cache_config: &TtsCacheConfig,
+    postgres: Arc<Postgres>,
+  ) -> anyhow::Result<Self> {
+    let backend = match TtsCacheBackendKind::from_str(tts_cache_config.backend())? {
+      TtsCacheBackendKind::Filesystem => {
+        let dir = PathBuf::from(
+          tts_cache_config
+            .path()
+            .as_deref()
+            .context("A path is required for filesystem tts cache backend")?,
+        );
+        fs::create_dir_all(&dir)
+          .await
+          .with_context(|| format!("Cannot create tts cache directory {}", dir.display()))?;
+
+        TtsCacheBackend::Filesystem(FilesystemTtsCache { dir })
+      }
+      TtsCacheBackendKind::Postgres => TtsCacheBackend::Postgres(PostgresTtsCache { postgres }),
+    };
+
+    info!(
+      backend = tts_cache_config.backend(),
+      max_size_bytes = tts_cache_config.max_size_bytes(),
+      "Tts cache built successfully"
+    );
+
+    let tts_cache = Self::new(backend, *tts_cache_config.max_size_bytes());
+    tts_cache.spawn_stats_logging();
+
+    Ok(tts_cache)
+  }
+
+  /// Construct a [TtsCache] from its backend and max total size
+  fn new(backend: TtsCacheBackend, max_size_bytes: u64) -> Self {
+    Self {
+      backend,
+      max_size_bytes,
+      counters: Arc::new(TtsCacheCounters::default()),
+      evicting: Arc::new(AtomicBool::new(false)),
+    }
+  }
+
+  /// Get the number of hits and misses since the cache is built
+  pub fn stats(&self) -> TtsCacheStats {
+    self.counters.stats()
+  }
+
+  /// Log the hit and miss counts at a fixed interval in the background, whenever they change
+  fn spawn_stats_logging(&self) {
+    let tts_cache = self.clone();
+    tokio::spawn(async move {
+      let mut interval = tokio::time::interval(STATS_LOG_INTERVAL);
+      let mut last_stats = tts_cache.stats();
+      loop {
+        interval.tick().await;
+        let stats = tts_cache.stats();
+        if stats == last_stats {
+          continue;
+        }
+
+        let lookups = stats.hits + stats.misses;
+        info!(
+          hits = stats.hits,
+          misses = stats.misses,
+          hit_rate = stats.hits as f64 / lookups as f64,
+          "Tts cache stats"
+        );
+        last_stats = stats;
+      }
+    });
+  }
+
+  /// Get the cached audio of a key, recording a hit or a miss
+  pub async fn get(&self, key: &str) -> Option<SimpleBytes> {
+    let audio = match &self.backend {
+      TtsCacheBackend::Filesystem(cache) => cache.get(key).await,
+      TtsCacheBackend::Postgres(cache) => cache.get(key).await,
+    }
+    .inspect_err(|error| warn!(%error, "Getting tts cache entry failed"))
+    .ok()
+    .flatten();
+
+    let (hits, misses) = if audio.is_some() {
+      (
+        self.counters.hits.fetch_add(1, Ordering::Relaxed) + 1,
+        self.counters.misses.load(Ordering::Relaxed),
+      )
+    } else {
+      (
+        self.counters.hits.load(Ordering::Relaxed),
+        self.counters.misses.fetch_add(1, Ordering::Relaxed) + 1,
+      )
+    };
+    debug!(hit = audio.is_some(), hits, misses, "Tts cache looked up");
+
+    audio
+  }
+
+  /// Store the audio of a key in the background, and then evict least recently used entries
+  /// beyond the max size, unless an eviction is already in progress
+  pub fn put(&self, key: String, audio: SimpleBytes) {
+    let tts_cache = self.clone();
+    tokio::spawn(async move {
+      if let Err(error) = tts_cache.backend.put(&key, &audio).await {
+        warn!(%error, "Storing tts cache entry failed");
+        return;
+      }
+
+      if tts_cache.evicting.swap(true, Ordering::AcqRel) {
+        trace!("Tts cache eviction is already in progress");
+        return;
+      }
+      if let Err(error) = tts_cache.backend.evict(tts_cache.max_size_bytes).await {
+        warn!(%error, "Evicting tts cache entries failed");
+      }
+      tts_cache.evicting.store(false, Ordering::Release);
+    });
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use tempfile::TempDir;
+
+  use super::*;
+
+  /// A tts cache in a temp dir, capped at a max size
+  fn filesystem_tts_cache(max_size_bytes: u64) -> (TtsCache, TempDir) {
+    let dir = TempDir::new().expect("Temp dir is created");
+    let backend = TtsCacheBackend::Filesystem(FilesystemTtsCache {
+      dir: dir.path().to_path_buf(),
+    });
+
+    (TtsCache::new(backend, max_size_bytes), dir)
+  }
+
+  /// Store an entry, marking it as used some seconds ago
+  async fn put_entry(tts_cache: &TtsCache, dir: &TempDir, key: &str, size: usize, age_secs: u64) {
+    tts_cache
+      .backend
+      .put(key, &vec![0; size])
+      .await
+      .expect("Entry is stored");
+    set_age(&dir.path().join(key), age_secs);
+  }
+
+  /// Set the modification time of a file to some seconds ago
+  fn set_age(path: &std::path::Path, age_secs: u64) {
+    std::fs::File::options()
+      .write(true)
+      .open(path)
+      .expect("File exists")
+      .set_modified(SystemTime::now() - Duration::from_secs(age_secs))
+      .expect("Modification time is set");
+  }
+
+  /// Whether an entry is cached
+  fn is_cached(dir: &TempDir, key: &str) -> bool {
+    dir.path().join(key).exists()
+  }
+
+  /// Least recently used entries are evicted until the cache fits its max size, and a lookup
+  /// marks an entry as recently used
+  #[tokio::test]
+  async fn evicts_least_recently_used_entries() {
+    let (tts_cache, dir) = filesystem_tts_cache(25);
+    put_entry(&tts_cache, &dir, "a", 10, 300).await;
+    put_entry(&tts_cache, &dir, "b", 10, 200).await;
+    put_entry(&tts_cache, &dir, "c", 10, 100).await;
+
+    assert!(tts_cache.get("a").await.is_some());
+    tts_cache
+      .backend
+      .evict(25)
+      .await
+      .expect("Entries are evicted");
+
+    assert!(is_cached(&dir, "a"));
+    assert!(!is_cached(&dir, "b"));
+    assert!(is_cached(&dir, "c"));
+  }
+
+  /// Entries are kept as long as the cache fits its max size
+  #[tokio::test]
+  async fn keeps_entries_within_max_size() {
+    let (tts_cache, dir) = filesystem_tts_cache(20);
+    put_entry(&tts_cache, &dir, "a", 10, 200).await;
+    put_entry(&tts_cache, &dir, "b", 10, 100).await;
+
+    tts_cache
+      .backend
+      .evict(20)
+      .await
+      .expect("Entries are evicted");
+
+    assert!(is_cached(&dir, "a"));
+    assert!(is_cached(&dir, "b"));
+  }
+
+  /// In-flight temp files are neither counted towards the max size nor evicted
+  #[tokio::test]
+  async fn skips_temp_files_on_eviction() {
+    let (tts_cache, dir) = filesystem_tts_cache(20);
+    put_entry(&tts_cache, &dir, "a", 10, 200).await;
+    put_entry(&tts_cache, &dir, "b", 10, 100).await;
+    let temp_path = dir.path().join(format!("c.0.{TEMP_FILE_EXTENSION}"));
+    std::fs::write(&temp_path, vec![0; 100]).expect("Temp file is written");
+    set_age(&temp_path, 300);
+
+    tts_cache
+      .backend
+      .evict(20)
+      .await
+      .expect("Entries are evicted");
+
+    assert!(is_cached(&dir, "a"));
+    assert!(is_cached(&dir, "b"));
+    assert!(temp_path.exists());
+  }
+
+  /// Lookups are counted as hits or misses
+  #[tokio::test]
+  async fn counts_hits_and_misses() {
+    let (tts_cache, dir) = filesystem_tts_cache(100);
+    put_entry(&tts_cache, &dir, "a", 10, 0).await;
+
+    assert_eq!(tts_cache.get("a").await, Some(vec![0; 10]));
+    assert_eq!(tts_cache.get("b").await, None);
+    assert_eq!(tts_cache.get("a").await, Some(vec![0; 10]));
+
+    assert_eq!(tts_cache.stats(), TtsCacheStats { hits: 2, misses: 1 });
+  }
+}
@@ -0,0 +1,71 @@
+//! User management selected by config, i.e. Clerk or the fake dev user
+
+use crate::{
+  domain::{
+    models::{AuthStatus, CefrLevel, ChatMateLanguage, CreditAuthStatus, EpisError, UserId},
+    ports::UserManagement,
+  },
+  outbound::{clerk::Clerk, fake::FakeUserManagement},
+};
+
+/// A [UserManagement] backed by any of the supported implementations
+#[derive(Clone)]
+#[allow(clippy::missing_docs_in_private_items)]
+pub enum AnyUserManagement {
+  Clerk(Clerk),
+  Fake(FakeUserManagement),
+}
+
+impl UserManagement for AnyUserManagement {
+  async fn authenticate_jwt(&self, jwt: &str) -> Result<AuthStatus, EpisError> {
+    match self {
+      Self::Clerk(user_management) => user_management.authenticate_jwt(jwt).await,
+      Self::Fake(user_management) => user_management.authenticate_jwt(jwt).await,
+    }
+  }
+
+  async fn authorize_by_credit(&self, user_id: &UserId) -> Result<CreditAuthStatus, EpisError> {
+    match self {
+      Self::Clerk(user_management) => user_management.authorize_by_credit(user_id).await,
+      Self::Fake(user_management) => user_management.authorize_by_credit(user_id).await,
+    }
+  }
+
+  async fn spend_credit(&self, user_id: &UserId) -> Result<(), EpisError> {
+    match self {
+      Self::Clerk(user_management) => user_management.spend_credit(user_id).await,
+      Self::Fake(user_management) => user_management.spend_credit(user_id).await,
+    }
+  }
+
+  async fn get_cefr_level(
+    &self,
+    user_id: &UserId,
+    language: &ChatMateLanguage,
+  ) -> Result<Option<CefrLevel>, EpisError> {
+    match self {
+      Self::Clerk(user_management) => user_management.get_cefr_level(user_id, language).await,
+      Self::Fake(user_management) => user_management.get_cefr_level(user_id, language).await,
+    }
+  }
+
+  async fn set_cefr_level(
+    &self,
+    user_id: &UserId,
+    language: &ChatMateLanguage,
+    cefr_level: &CefrLevel,
+  ) -> Result<(), EpisError> {
+    match self {
+      Self::Clerk(user_management) => {
+        user_management
+          .set_cefr_level(user_id, language, cefr_level)
+          .await
+      }
+      Self::Fake(user_management) => {
+        user_management
+          .set_cefr_level(user_id, language, cefr_level)
+          .await
+      }
+    }
+  }
+}